@@ -0,0 +1,42 @@
+use log::warn;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, sync::mpsc};
+
+// 追加写入的记录文件（每行一条），由后台任务写入，调用方持有状态锁时不做文件 IO
+pub struct AppendLog {
+    path: String,
+    writer: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl AppendLog {
+    pub fn new(path: &str) -> AppendLog {
+        AppendLog { path: path.to_string(), writer: None }
+    }
+
+    // 首次写入时启动后台任务，之后只把记录交给它
+    pub fn push(&mut self, line: String) {
+        let writer = self.writer.get_or_insert_with(|| {
+            let (writer, rx) = mpsc::unbounded_channel();
+            tokio::spawn(write_lines(self.path.clone(), rx));
+            writer
+        });
+        let _ = writer.send(line);
+    }
+}
+
+// 按顺序追加，写入期间积压的多条记录合并为一次写入
+async fn write_lines(path: String, mut rx: mpsc::UnboundedReceiver<String>) {
+    while let Some(line) = rx.recv().await {
+        let mut content = line + "\n";
+        while let Ok(line) = rx.try_recv() {
+            content.push_str(&line);
+            content.push('\n');
+        }
+        let result = match OpenOptions::new().create(true).append(true).open(&path).await {
+            Ok(mut f) => f.write_all(content.as_bytes()).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!("写入记录文件 {} 失败: {}", path, e);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use serde::Deserialize;
+
+// 按失败率缩小批次：设备最近的失败率超过 failure_rate 时，批次按 failure_rate / 失败率 的比例缩小，
+// 使每批预计失败的号码数不超过正常水平，设备恢复后批次自动回到请求的数量
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackpressureConfig {
+    #[serde(default = "default_failure_rate")]
+    pub failure_rate: f64,
+    // 设备累计确认的号码数达到该值后才按失败率缩小，避免样本太少时误判
+    #[serde(default = "default_min_acked")]
+    pub min_acked: u64,
+    // 缩小后的最小批次
+    #[serde(default = "default_min_batch")]
+    pub min_batch: usize,
+}
+
+fn default_failure_rate() -> f64 {
+    0.1
+}
+
+fn default_min_acked() -> u64 {
+    50
+}
+
+fn default_min_batch() -> usize {
+    1
+}
+
+impl BackpressureConfig {
+    // 按失败率缩小后的批次大小，不超过 n
+    pub fn shrink(&self, n: usize, failure_rate: f64) -> usize {
+        if failure_rate <= self.failure_rate {
+            return n;
+        }
+        let size = (n as f64 * self.failure_rate / failure_rate).floor() as usize;
+        size.max(self.min_batch).min(n)
+    }
+}
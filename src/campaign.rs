@@ -0,0 +1,180 @@
+use crate::{datetime::Locale, message::Renderer, pacing::PacingConfig};
+use jiff::Timestamp;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+// 活动配置：使用独立的消息文件，可覆盖部分模板变量
+#[derive(Debug, Clone, Deserialize)]
+pub struct CampaignConfig {
+    pub name: String,
+    pub message_file: String,
+    // 覆盖全局 [vars] 中的同名变量
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    // 该活动的号码文件，启动时追加到号码池；也可在 /import 的对象中用 campaign 字段指定
+    pub numbers_file: Option<String>,
+    // 按设备语言选择的消息文件，如 { en = "msg_promo.en.txt" }
+    #[serde(default)]
+    pub variants: HashMap<String, String>,
+    // 预加载的备选消息，如 { soft = "msg_promo_soft.txt" }，可信调用方在 /fetch 中用 message_id 指定
+    #[serde(default)]
+    pub messages: HashMap<String, String>,
+    // 活动截止时间，如 "2026-12-31T18:00:00+08:00"，之后不再下发该活动的号码，未下发的号码自动导出
+    pub end_at: Option<Timestamp>,
+    // 该活动单条消息允许的最大短信条数，覆盖顶层 max_segments
+    pub max_segments: Option<usize>,
+    // 该活动的下发速率，覆盖顶层 [pacing]
+    pub pacing: Option<PacingConfig>,
+    // 该活动同时未确认的批次数上限，覆盖 [leases] 的 max_outstanding
+    pub max_outstanding: Option<usize>,
+}
+
+// 某种语言的消息，或按 message_id 指定的备选消息
+pub struct Variant {
+    pub message_file: String,
+    pub renderer: Arc<Renderer>,
+}
+
+// 活动及其消息渲染上下文，第 0 个为使用 msg.txt 的默认活动
+pub struct Campaign {
+    pub name: String,
+    pub message_file: String,
+    pub renderer: Arc<Renderer>,
+    // 按语言（小写）索引的消息
+    pub variants: BTreeMap<String, Variant>,
+    // 按 message_id 索引的备选消息
+    pub messages: BTreeMap<String, Variant>,
+    pub end_at: Option<Timestamp>,
+    // 已过截止时间
+    pub ended: bool,
+    // 渲染后超出该条数的号码暂扣不发，未配置时沿用顶层 max_segments
+    pub max_segments: Option<usize>,
+    // 该活动的令牌桶限速，未配置时沿用顶层 [pacing]（各活动的令牌桶相互独立）
+    pub pacing: Option<PacingConfig>,
+    // 未确认批次上限，未配置时沿用 [leases] 的 max_outstanding，0 表示不限制
+    pub max_outstanding: usize,
+}
+
+impl Campaign {
+    pub fn new(config: &CampaignConfig, default: &Campaign, template: String) -> Campaign {
+        let mut renderer = default.renderer.with_template(template);
+        renderer.vars.extend(config.vars.clone());
+        Campaign {
+            name: config.name.clone(),
+            message_file: config.message_file.clone(),
+            renderer: Arc::new(renderer),
+            variants: BTreeMap::new(),
+            messages: BTreeMap::new(),
+            end_at: config.end_at,
+            ended: false,
+            max_segments: config.max_segments.or(default.max_segments),
+            pacing: config.pacing.clone().or_else(|| default.pacing.clone()),
+            max_outstanding: config.max_outstanding.unwrap_or(default.max_outstanding),
+        }
+    }
+
+    // 添加语言消息，日期占位符随语言切换（仅支持 zh / en，其余语言沿用活动设置）
+    pub fn add_variant(&mut self, locale: &str, message_file: &str, template: String) {
+        let mut renderer = self.renderer.with_template(template);
+        renderer.locale = Locale::from_tag(locale).unwrap_or(renderer.locale);
+        let variant = Variant { message_file: message_file.to_string(), renderer: Arc::new(renderer) };
+        self.variants.insert(locale.to_ascii_lowercase().replace('_', "-"), variant);
+    }
+
+    // 添加备选消息，沿用活动的变量和日期格式
+    pub fn add_message(&mut self, id: &str, message_file: &str, template: String) {
+        let renderer = self.renderer.with_template(template);
+        let message = Variant { message_file: message_file.to_string(), renderer: Arc::new(renderer) };
+        self.messages.insert(id.to_string(), message);
+    }
+
+    // 已结束或已过截止时间（截止任务尚未执行时也算结束）
+    pub fn is_over(&self, now: Timestamp) -> bool {
+        self.ended || self.end_at.is_some_and(|end_at| end_at <= now)
+    }
+
+    // 按设备语言选择消息：先精确匹配（如 en-us），再按语言前缀（如 en），都没有时使用活动的默认消息
+    pub fn renderer_for(&self, locale: Option<&str>) -> &Arc<Renderer> {
+        let Some(locale) = locale.map(|l| l.to_ascii_lowercase().replace('_', "-")) else {
+            return &self.renderer;
+        };
+        let language = locale.split('-').next().unwrap_or_default();
+        self.variants
+            .get(&locale)
+            .or_else(|| self.variants.get(language))
+            .map_or(&self.renderer, |v| &v.renderer)
+    }
+
+    // 消息文件及对应的渲染上下文
+    pub fn slot_mut(&mut self, slot: &Slot) -> Option<(&str, &mut Arc<Renderer>)> {
+        let variant = match slot {
+            Slot::Default => return Some((&self.message_file, &mut self.renderer)),
+            Slot::Variant(locale) => self.variants.get_mut(locale),
+            Slot::Message(id) => self.messages.get_mut(id),
+        };
+        variant.map(|v| (v.message_file.as_str(), &mut v.renderer))
+    }
+}
+
+// 活动中的一条消息：默认消息、某种语言的消息或备选消息
+#[derive(Debug, Clone)]
+pub enum Slot {
+    Default,
+    Variant(String),
+    Message(String),
+}
+
+pub fn find(campaigns: &[Campaign], name: &str) -> Option<usize> {
+    campaigns.iter().position(|c| c.name == name)
+}
+
+// 多活动共用设备时按权重轮流分配批次，每个批次只含一个活动的号码
+#[derive(Debug, Clone, Deserialize)]
+pub struct SchedulingConfig {
+    // 活动名 => 权重，未列出的活动权重为 1，0 表示暂停该活动
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+}
+
+// 平滑加权轮询，每台设备各自轮转
+pub struct Scheduler {
+    weights: Vec<i64>,
+    current: HashMap<String, Vec<i64>>,
+}
+
+impl Scheduler {
+    pub fn new(config: &SchedulingConfig, campaigns: &[Campaign]) -> Scheduler {
+        for name in config.weights.keys() {
+            if find(campaigns, name).is_none() {
+                panic!("Unknown campaign '{}' in [scheduling.weights]", name);
+            }
+        }
+        let weights = campaigns.iter().map(|c| config.weights.get(&c.name).copied().unwrap_or(1) as i64).collect();
+        Scheduler { weights, current: HashMap::new() }
+    }
+
+    // 本批次依次尝试的活动：按轮询选出的活动在前，其余按权重从高到低，权重为 0 的活动不参与
+    pub fn order(&mut self, device: &str) -> Vec<usize> {
+        let total: i64 = self.weights.iter().sum();
+        if total == 0 {
+            return Vec::new();
+        }
+        let current = self.current.entry(device.to_string()).or_insert_with(|| vec![0; self.weights.len()]);
+        for (c, w) in current.iter_mut().zip(&self.weights) {
+            *c += w;
+        }
+        let picked = (0..current.len()).max_by_key(|&i| (current[i], std::cmp::Reverse(i))).unwrap_or(0);
+        current[picked] -= total;
+
+        let mut rest: Vec<usize> = (0..self.weights.len()).filter(|&i| i != picked && self.weights[i] > 0).collect();
+        rest.sort_by_key(|&i| std::cmp::Reverse(self.weights[i]));
+        std::iter::once(picked).chain(rest).collect()
+    }
+
+    pub fn weights(&self) -> &[i64] {
+        &self.weights
+    }
+}
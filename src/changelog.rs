@@ -0,0 +1,96 @@
+use jiff::Timestamp;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::append_log::AppendLog;
+use std::{collections::VecDeque, fs};
+
+// 内存中保留的变更记录上限
+const MAX_ENTRIES: usize = 10_000;
+// 每条记录最多保存的号码数，其余只计数
+const MAX_NUMBERS: usize = 100;
+
+// 号码池的一次运行时变更
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub seq: u64,
+    pub at: Timestamp,
+    // import / delete / restore / quarantine / release / opt_out
+    pub action: String,
+    pub count: usize,
+    // 涉及的号码，超过上限时只保存前面一部分
+    pub numbers: Vec<String>,
+    // 操作者：管理接口取 X-Operator 请求头，自动隔离为 device:<设备号>，回复退订为 reply:<规则名>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+pub struct Changelog {
+    entries: VecDeque<Change>,
+    seq: u64,
+    // 追加写入的记录文件（每行一条 JSON），不配置则只保存在内存中
+    file: Option<AppendLog>,
+}
+
+impl Changelog {
+    // 从记录文件加载最近的变更
+    pub fn load(file: Option<&str>) -> Changelog {
+        let mut entries = VecDeque::new();
+        if let Some(content) = file.and_then(|path| fs::read_to_string(path).ok()) {
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str::<Change>(line) {
+                    Ok(change) => entries.push_back(change),
+                    Err(e) => warn!("忽略无法解析的变更记录: {}", e),
+                }
+                if entries.len() > MAX_ENTRIES {
+                    entries.pop_front();
+                }
+            }
+        }
+        let seq = entries.back().map_or(0, |c| c.seq);
+        Changelog { entries, seq, file: file.map(AppendLog::new) }
+    }
+
+    // 记录一次变更，没有涉及号码时不记录
+    pub fn record(
+        &mut self,
+        action: &str,
+        numbers: &[String],
+        operator: Option<String>,
+        reason: Option<String>,
+        trace_id: Option<String>,
+    ) {
+        if numbers.is_empty() {
+            return;
+        }
+        self.seq += 1;
+        let change = Change {
+            seq: self.seq,
+            at: crate::clock::now(),
+            action: action.to_string(),
+            count: numbers.len(),
+            numbers: numbers.iter().take(MAX_NUMBERS).cloned().collect(),
+            operator,
+            reason,
+            trace_id,
+        };
+        self.append(&change);
+        self.entries.push_back(change);
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    // 最新的变更在前
+    pub fn iter(&self) -> impl Iterator<Item = &Change> {
+        self.entries.iter().rev()
+    }
+
+    fn append(&mut self, change: &Change) {
+        let Some(file) = &mut self.file else { return };
+        file.push(serde_json::to_string(change).expect("序列化变更记录失败"));
+    }
+}
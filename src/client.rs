@@ -0,0 +1,309 @@
+// 设备协议客户端：fetch / ack / report / heartbeat，请求失败时按退避重试
+// 同一次调用的重试使用相同的 X-Request-Id，便于服务端去重
+use crate::http_client::{self, encode_component};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// 一个批次，首个号码为测试号（服务端开启时）
+#[derive(Debug, Clone, Deserialize)]
+pub struct Batch {
+    pub numbers: String,
+    pub message: String,
+    pub count: usize,
+    // 消息因号码而不同时返回，顺序与 numbers 一致
+    #[serde(default)]
+    pub items: Vec<Item>,
+    pub retry_after: Option<u64>,
+    // 下发数量少于请求数量时的原因：daily_quota / warm_up / failure_rate / pacing / available
+    pub requested: Option<usize>,
+    pub reason: Option<String>,
+    pub batch_id: Option<String>,
+    // 租约到期时间，之后未确认的号码会被服务端回收
+    pub expires_at: Option<jiff::Timestamp>,
+    // 抽查的号码，发送后需调用 verify 上传凭证才能取下一批
+    #[serde(default)]
+    pub verify: Vec<String>,
+    // 没有下发号码时的原因，如 no_more_numbers、rate_limited、draining，取值见服务端配置中 [strings] 的说明
+    pub code: Option<String>,
+}
+
+impl Batch {
+    pub fn numbers(&self) -> Vec<&str> {
+        self.numbers.split(',').filter(|n| !n.is_empty()).collect()
+    }
+
+    // 号码对应的消息
+    pub fn message_for(&self, number: &str) -> &str {
+        self.items.iter().find(|item| item.number == number).map_or(&self.message, |item| &item.message)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Item {
+    pub number: String,
+    pub message: String,
+}
+
+// 发送失败的号码及原因
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub number: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AckResult {
+    pub batch_id: String,
+    pub count: usize,
+    pub failed: usize,
+    pub quarantined: usize,
+}
+
+// 注册结果，之后使用 device_id 创建客户端
+#[derive(Debug, Clone, Deserialize)]
+pub struct Registration {
+    pub device_id: String,
+    pub shard: usize,
+    pub daily_quota: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // 连接失败、超时等，已用尽重试次数
+    Transport(String),
+    // 服务端返回的非成功状态
+    Status(u16, String),
+    // 响应无法解析
+    Decode(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "transport error: {}", e),
+            Error::Status(status, body) => write!(f, "http {}: {}", status, body),
+            Error::Decode(e) => write!(f, "invalid response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub struct Client {
+    base_url: String,
+    device: String,
+    locale: Option<String>,
+    // 只取优先池的号码
+    priority: bool,
+    // 指定 message_id 时携带的 X-Override-Token
+    override_token: Option<String>,
+    // 多租户部署中的 X-Api-Key（服务端配置了 [tenants] 时必须携带），用量计入对应租户
+    api_key: Option<String>,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+    seq: AtomicU64,
+}
+
+impl Client {
+    // base_url 如 http://10.0.0.2:3000 或 https://sms.example.com
+    pub fn new(base_url: &str, device: &str) -> Result<Client, Error> {
+        http_client::check_url(base_url).map_err(Error::Transport)?;
+        Ok(Client {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            device: device.to_string(),
+            locale: None,
+            priority: false,
+            override_token: None,
+            api_key: None,
+            timeout: Duration::from_secs(10),
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            seq: AtomicU64::new(0),
+        })
+    }
+
+    pub fn locale(mut self, locale: &str) -> Client {
+        self.locale = Some(locale.to_string());
+        self
+    }
+
+    // 专发紧急号码：取号时带 priority=high
+    pub fn priority(mut self) -> Client {
+        self.priority = true;
+        self
+    }
+
+    // 服务端 message_override_token，配置后可用 fetch_message 指定消息
+    pub fn override_token(mut self, token: &str) -> Client {
+        self.override_token = Some(token.to_string());
+        self
+    }
+
+    // 服务端 [tenants] 中配置的 API 密钥，缺少或未配置的密钥返回 Status(401, ..)
+    pub fn api_key(mut self, key: &str) -> Client {
+        self.api_key = Some(key.to_string());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Client {
+        self.timeout = timeout;
+        self
+    }
+
+    // 失败后的重试次数，每次等待时间翻倍
+    pub fn retries(mut self, retries: u32, backoff: Duration) -> Client {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    // 取一批号码，n 为 None 时使用服务端默认数量
+    pub async fn fetch(&self, n: Option<usize>) -> Result<Batch, Error> {
+        self.fetch_with(n, None).await
+    }
+
+    // 取一批号码并使用服务端预加载的备选消息，如对重发号码使用更温和的措辞
+    // 未配置或配置错误的 override_token 返回 Status(403, ..)，不存在的 message_id 返回 Status(400, ..)
+    pub async fn fetch_message(&self, n: Option<usize>, message_id: &str) -> Result<Batch, Error> {
+        self.fetch_with(n, Some(message_id)).await
+    }
+
+    async fn fetch_with(&self, n: Option<usize>, message_id: Option<&str>) -> Result<Batch, Error> {
+        let mut url = format!("{}/fetch?device={}", self.base_url, encode_component(&self.device));
+        if let Some(n) = n {
+            url.push_str(&format!("&n={}", n));
+        }
+        if let Some(locale) = &self.locale {
+            url.push_str(&format!("&locale={}", encode_component(locale)));
+        }
+        if self.priority {
+            url.push_str("&priority=high");
+        }
+        if let Some(id) = message_id {
+            url.push_str(&format!("&message_id={}", encode_component(id)));
+        }
+        let body = self.send("GET", &url, None).await?;
+        serde_json::from_str(&body).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    // 确认批次全部发送成功
+    pub async fn ack(&self, batch_id: &str) -> Result<AckResult, Error> {
+        self.report(batch_id, &[]).await
+    }
+
+    // 确认批次并报告发送失败的号码
+    pub async fn report(&self, batch_id: &str, failed: &[Failure]) -> Result<AckResult, Error> {
+        let url = format!("{}/ack", self.base_url);
+        let body = serde_json::json!({ "batch_id": batch_id, "device": self.device, "failed": failed }).to_string();
+        let body = self.send("POST", &url, Some(&body)).await?;
+        serde_json::from_str(&body).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    // 为抽查的号码上传截图哈希，返回仍待上传的号码
+    pub async fn verify(&self, proofs: &[(&str, &str)]) -> Result<Vec<String>, Error> {
+        let url = format!("{}/verify", self.base_url);
+        let proofs: Vec<Value> = proofs
+            .iter()
+            .map(|(number, hash)| serde_json::json!({ "number": number, "screenshot_hash": hash }))
+            .collect();
+        let body = serde_json::json!({ "device": self.device, "proofs": proofs }).to_string();
+        let body = self.send("POST", &url, Some(&body)).await?;
+        let data: Value = serde_json::from_str(&body).map_err(|e| Error::Decode(e.to_string()))?;
+        serde_json::from_value(data["pending"].clone()).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    // 退回整个未开始发送的批次，号码由服务端立即重新下发，返回退回的号码数
+    pub async fn nack(&self, batch_id: &str, reason: Option<&str>) -> Result<usize, Error> {
+        let url = format!("{}/nack", self.base_url);
+        let body = serde_json::json!({ "batch_id": batch_id, "device": self.device, "reason": reason }).to_string();
+        let body = self.send("POST", &url, Some(&body)).await?;
+        let data: Value = serde_json::from_str(&body).map_err(|e| Error::Decode(e.to_string()))?;
+        data["returned"].as_u64().map(|n| n as usize).ok_or_else(|| Error::Decode("missing returned".to_string()))
+    }
+
+    // 注册设备，服务端严格模式下 UDID 不在允许列表中时返回 Status(403, ..)
+    pub async fn register(
+        &self,
+        udid: &str,
+        ios_version: Option<&str>,
+        carrier: Option<&str>,
+        max_daily: Option<usize>,
+    ) -> Result<Registration, Error> {
+        let url = format!("{}/devices/register", self.base_url);
+        let body = serde_json::json!({
+            "udid": udid,
+            "ios_version": ios_version,
+            "carrier": carrier,
+            "max_daily": max_daily,
+        })
+        .to_string();
+        let body = self.send("POST", &url, Some(&body)).await?;
+        serde_json::from_str(&body).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    // 上报设备状态，meta 如 {"battery": 80}
+    pub async fn heartbeat(&self, meta: HashMap<String, Value>) -> Result<(), Error> {
+        let url = format!("{}/heartbeat", self.base_url);
+        let body = serde_json::json!({ "device": self.device, "meta": meta }).to_string();
+        self.send("POST", &url, Some(&body)).await.map(|_| ())
+    }
+
+    // 上传收到的回复 (号码, 内容)，返回命中退订规则的号码
+    pub async fn replies(&self, replies: &[(&str, &str)]) -> Result<Vec<String>, Error> {
+        let url = format!("{}/replies", self.base_url);
+        let replies: Vec<Value> =
+            replies.iter().map(|(number, text)| serde_json::json!({ "number": number, "text": text })).collect();
+        let body = serde_json::json!({ "device": self.device, "replies": replies }).to_string();
+        let body = self.send("POST", &url, Some(&body)).await?;
+        let data: Value = serde_json::from_str(&body).map_err(|e| Error::Decode(e.to_string()))?;
+        serde_json::from_value(data["opted_out"].clone()).map_err(|e| Error::Decode(e.to_string()))
+    }
+
+    // 连接失败、5xx 和 503 维护中时重试，其余状态直接返回
+    async fn send(&self, method: &str, url: &str, body: Option<&str>) -> Result<String, Error> {
+        let request_id = self.request_id();
+        let mut headers = vec![("X-Request-Id", request_id.as_str())];
+        if body.is_some() {
+            headers.push(("Content-Type", "application/json"));
+        }
+        if let Some(token) = self.override_token.as_deref().filter(|_| url.contains("message_id=")) {
+            headers.push(("X-Override-Token", token));
+        }
+        if let Some(key) = &self.api_key {
+            headers.push(("X-Api-Key", key));
+        }
+
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            let result = http_client::request(method, url, &headers, body, self.timeout).await;
+            let retryable = match &result {
+                Ok(resp) => resp.status >= 500,
+                Err(_) => true,
+            };
+            if !retryable || attempt >= self.retries {
+                return match result {
+                    Ok(resp) if resp.is_success() => Ok(resp.body),
+                    Ok(resp) => Err(Error::Status(resp.status, resp.body)),
+                    Err(e) => Err(Error::Transport(e)),
+                };
+            }
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    fn request_id(&self) -> String {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{:x}-{}", self.device, nanos, seq)
+    }
+}
@@ -0,0 +1,107 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use jiff::{SignedDuration, Timestamp};
+use std::sync::{Arc, LazyLock};
+use tokio::{sync::watch, task::JoinHandle};
+
+// 服务使用的时钟：系统时间加上偏移秒数，偏移只能在测试模式下通过 POST /test/faults {"advance_secs": ...} 调整，
+// 便于集成测试验证发送时段、配额重置、租约过期、活动截止和号码源计划等与时间有关的行为
+#[derive(Clone)]
+pub struct Clock(Arc<watch::Sender<i64>>);
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock(Arc::new(watch::Sender::new(0)))
+    }
+}
+
+impl Clock {
+    pub fn now(&self) -> Timestamp {
+        let now = Timestamp::now();
+        now.checked_add(SignedDuration::from_secs(self.offset())).unwrap_or(now)
+    }
+
+    pub fn offset(&self) -> i64 {
+        *self.0.borrow()
+    }
+
+    // 时钟前进 secs 秒，等待中的 sleep_until 重新计算剩余时间
+    pub fn advance(&self, secs: i64) {
+        self.0.send_modify(|offset| *offset += secs);
+    }
+
+    // 等到时钟到达 until，时钟被调快时提前返回
+    pub async fn sleep_until(&self, until: Timestamp) {
+        let mut changed = self.0.subscribe();
+        loop {
+            let wait = until.duration_since(self.now());
+            if !wait.is_positive() {
+                return;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(wait.unsigned_abs()) => {}
+                _ = changed.changed() => {}
+            }
+        }
+    }
+}
+
+// 服务进程使用全局时钟；同一进程中运行多个服务时（test_util 的进程内模式），各服务的请求和后台任务使用自己的时钟
+static GLOBAL: LazyLock<Clock> = LazyLock::new(Clock::default);
+
+tokio::task_local! {
+    static CURRENT: Clock;
+}
+
+// 当前任务的时钟
+pub fn current() -> Clock {
+    CURRENT.try_with(Clock::clone).unwrap_or_else(|_| GLOBAL.clone())
+}
+
+pub fn now() -> Timestamp {
+    current().now()
+}
+
+pub fn offset() -> i64 {
+    current().offset()
+}
+
+pub fn advance(secs: i64) {
+    current().advance(secs)
+}
+
+pub async fn sleep_until(until: Timestamp) {
+    current().sleep_until(until).await
+}
+
+// 在指定时钟下运行
+pub async fn scope<F: Future>(clock: Clock, f: F) -> F::Output {
+    CURRENT.scope(clock, f).await
+}
+
+// 启动后台任务，沿用当前任务的时钟
+pub fn spawn<F>(f: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(scope(current(), f))
+}
+
+// 在阻塞线程池中运行，同样沿用当前任务的时钟
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let clock = current();
+    tokio::task::spawn_blocking(move || CURRENT.sync_scope(clock, f))
+}
+
+// 请求在服务的时钟下处理
+pub async fn layer(State(clock): State<Clock>, request: Request, next: Next) -> Response {
+    CURRENT.scope(clock, next.run(request)).await
+}
@@ -0,0 +1,152 @@
+use jiff::{tz::TimeZone, Timestamp, ToSpan, Zoned};
+
+// 标准 5 段 cron 表达式：分 时 日 月 周，支持 * , - /，周日为 0 或 7
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    // 日、周字段为 * 时不参与判断；两者都有限制时任一匹配即可
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Cron, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("expected 5 fields (minute hour day month weekday), got {}", fields.len()));
+        }
+        let mut weekdays = field(fields[4], 0, 7)?;
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        weekdays.truncate(7);
+        Ok(Cron {
+            minutes: field(fields[0], 0, 59)?,
+            hours: field(fields[1], 0, 23)?,
+            days: field(fields[2], 1, 31)?,
+            months: field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+
+    fn matches(&self, t: &Zoned) -> bool {
+        let day = self.days[t.day() as usize];
+        let weekday = self.weekdays[t.weekday().to_sunday_zero_offset() as usize];
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        self.minutes[t.minute() as usize] && self.hours[t.hour() as usize] && self.months[t.month() as usize] && day_matches
+    }
+
+    // now 之后下一个匹配的整分钟，一年内都不匹配时（如 2 月 30 日）为 None
+    pub fn next_after(&self, now: Timestamp, tz: &TimeZone) -> Option<Timestamp> {
+        let mut t = now.to_zoned(tz.clone()).with().second(0).subsec_nanosecond(0).build().ok()?;
+        for _ in 0..366 * 24 * 60 {
+            t = t.checked_add(1.minute()).ok()?;
+            if self.matches(&t) {
+                return Some(t.timestamp());
+            }
+        }
+        None
+    }
+}
+
+// 解析一个字段，返回 min..=max 中各值是否匹配
+fn field(spec: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let number = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid value '{}' in '{}'", s, spec));
+    let mut set = vec![false; max as usize + 1];
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, number(step)?.max(1)),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // 5/10 表示从 5 开始每 10 个
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("'{}' out of range {}-{}", part, min, max));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set[value as usize] = true;
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cron;
+    use jiff::{tz::TimeZone, Timestamp};
+
+    fn next(expr: &str, now: &str) -> Option<String> {
+        let tz = TimeZone::get("Asia/Shanghai").unwrap();
+        let now: Timestamp = now.parse().unwrap();
+        Cron::parse(expr).unwrap().next_after(now, &tz).map(|t| t.to_zoned(tz).strftime("%Y-%m-%d %H:%M %a").to_string())
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("* 24 * * *").is_err());
+        assert!(Cron::parse("* * 0 * *").is_err());
+        assert!(Cron::parse("* * * 13 *").is_err());
+        assert!(Cron::parse("* * * * 8").is_err());
+        assert!(Cron::parse("5-1 * * * *").is_err());
+        assert!(Cron::parse("x * * * *").is_err());
+        assert!(Cron::parse("0 */6 * * 1-5").is_ok());
+    }
+
+    #[test]
+    fn next_run_is_strictly_after_now() {
+        assert_eq!(next("* * * * *", "2026-01-01T00:00:30+08:00").as_deref(), Some("2026-01-01 00:01 Thu"));
+        assert_eq!(next("0 * * * *", "2026-01-01T00:00:00+08:00").as_deref(), Some("2026-01-01 01:00 Thu"));
+    }
+
+    #[test]
+    fn supports_steps_ranges_and_lists() {
+        assert_eq!(next("0 */6 * * *", "2026-01-01T07:00:00+08:00").as_deref(), Some("2026-01-01 12:00 Thu"));
+        assert_eq!(next("5/20 * * * *", "2026-01-01T00:30:00+08:00").as_deref(), Some("2026-01-01 00:45 Thu"));
+        assert_eq!(next("0 9-11 * * *", "2026-01-01T11:30:00+08:00").as_deref(), Some("2026-01-02 09:00 Fri"));
+        assert_eq!(next("15,45 8 * * *", "2026-01-01T08:20:00+08:00").as_deref(), Some("2026-01-01 08:45 Thu"));
+    }
+
+    #[test]
+    fn evaluates_in_the_given_timezone() {
+        // 上海 09:00 为 UTC 01:00
+        assert_eq!(next("0 9 * * *", "2026-01-01T00:30:00Z").as_deref(), Some("2026-01-01 09:00 Thu"));
+        assert_eq!(next("0 9 * * *", "2026-01-01T01:30:00Z").as_deref(), Some("2026-01-02 09:00 Fri"));
+    }
+
+    #[test]
+    fn sunday_is_zero_or_seven() {
+        // 2026-01-01 为周四
+        assert_eq!(next("0 0 * * 0", "2026-01-01T00:00:00+08:00").as_deref(), Some("2026-01-04 00:00 Sun"));
+        assert_eq!(next("0 0 * * 7", "2026-01-01T00:00:00+08:00").as_deref(), Some("2026-01-04 00:00 Sun"));
+    }
+
+    #[test]
+    fn restricted_day_and_weekday_match_either() {
+        // 每月 10 日或每周一
+        assert_eq!(next("0 0 10 * 1", "2026-01-01T00:00:00+08:00").as_deref(), Some("2026-01-05 00:00 Mon"));
+        assert_eq!(next("0 0 10 * 1", "2026-01-06T00:00:00+08:00").as_deref(), Some("2026-01-10 00:00 Sat"));
+        // 只限制日时周不参与判断
+        assert_eq!(next("0 0 10 * *", "2026-01-01T00:00:00+08:00").as_deref(), Some("2026-01-10 00:00 Sat"));
+    }
+
+    #[test]
+    fn impossible_dates_never_run() {
+        assert_eq!(next("0 0 30 2 *", "2026-01-01T00:00:00+08:00"), None);
+    }
+}
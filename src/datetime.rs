@@ -0,0 +1,75 @@
+use jiff::{civil::Weekday, tz::TimeZone, ToSpan, Zoned};
+
+// 日期占位符的显示语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    Zh,
+    En,
+}
+
+impl Locale {
+    pub fn parse(s: &str) -> Locale {
+        match s.to_ascii_lowercase().as_str() {
+            "en" | "en-us" | "en_us" => Locale::En,
+            _ => Locale::Zh,
+        }
+    }
+
+    // 按语言标签（如 en-GB、zh_TW）识别，不支持的语言返回 None
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let tag = tag.to_ascii_lowercase();
+        match tag.split(['-', '_']).next() {
+            Some("en") => Some(Locale::En),
+            Some("zh") => Some(Locale::Zh),
+            _ => None,
+        }
+    }
+}
+
+// 解析配置中的时区，未配置时使用系统时区
+pub fn load_timezone(name: Option<&str>) -> TimeZone {
+    match name {
+        Some(name) => TimeZone::get(name)
+            .unwrap_or_else(|e| panic!("Invalid timezone '{}': {}", name, e)),
+        None => TimeZone::system(),
+    }
+}
+
+// 日期时间占位符：{date} {time} {weekday} {tomorrow} {tomorrow_weekday}
+pub fn lookup(key: &str, now: &Zoned, locale: Locale) -> Option<String> {
+    let tomorrow = || now.date().checked_add(1.day()).expect("date overflow");
+    match key {
+        "date" => Some(format_date(now.date(), locale)),
+        "time" => Some(format!("{:02}:{:02}", now.hour(), now.minute())),
+        "weekday" => Some(weekday_name(now.weekday(), locale).to_string()),
+        "tomorrow" => Some(format_date(tomorrow(), locale)),
+        "tomorrow_weekday" => Some(weekday_name(tomorrow().weekday(), locale).to_string()),
+        _ => None,
+    }
+}
+
+fn format_date(date: jiff::civil::Date, locale: Locale) -> String {
+    match locale {
+        Locale::Zh => format!("{}月{}日", date.month(), date.day()),
+        Locale::En => date.strftime("%b %-d").to_string(),
+    }
+}
+
+fn weekday_name(weekday: Weekday, locale: Locale) -> &'static str {
+    match (locale, weekday) {
+        (Locale::Zh, Weekday::Monday) => "星期一",
+        (Locale::Zh, Weekday::Tuesday) => "星期二",
+        (Locale::Zh, Weekday::Wednesday) => "星期三",
+        (Locale::Zh, Weekday::Thursday) => "星期四",
+        (Locale::Zh, Weekday::Friday) => "星期五",
+        (Locale::Zh, Weekday::Saturday) => "星期六",
+        (Locale::Zh, Weekday::Sunday) => "星期日",
+        (Locale::En, Weekday::Monday) => "Monday",
+        (Locale::En, Weekday::Tuesday) => "Tuesday",
+        (Locale::En, Weekday::Wednesday) => "Wednesday",
+        (Locale::En, Weekday::Thursday) => "Thursday",
+        (Locale::En, Weekday::Friday) => "Friday",
+        (Locale::En, Weekday::Saturday) => "Saturday",
+        (Locale::En, Weekday::Sunday) => "Sunday",
+    }
+}
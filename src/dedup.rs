@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+// 按 (设备或批次号, X-Request-Id) 缓存最近的响应，iOS 网络层自动重试同一请求时直接返回缓存，
+// 避免一次取号消耗两个批次
+pub struct RequestCache<T> {
+    ttl: Duration,
+    entries: HashMap<(String, String), Entry<T>>,
+}
+
+enum Entry<T> {
+    // 首个请求仍在处理中，重试的请求等待其结果
+    Pending(watch::Receiver<Option<T>>),
+    Done(Instant, T),
+}
+
+// 查找结果：已有缓存、等待处理中的同一请求，或由调用方处理并在完成后调用 finish
+pub enum Lookup<T> {
+    Cached(T),
+    Wait(watch::Receiver<Option<T>>),
+    Begin(watch::Sender<Option<T>>),
+}
+
+impl<T: Clone> RequestCache<T> {
+    // ttl 为 0 时不缓存
+    pub fn new(ttl_secs: u64) -> RequestCache<T> {
+        RequestCache { ttl: Duration::from_secs(ttl_secs), entries: HashMap::new() }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    pub fn get(&self, scope: &str, request_id: &str) -> Option<T> {
+        match self.entries.get(&(scope.to_string(), request_id.to_string()))? {
+            Entry::Done(at, response) => (at.elapsed() < self.ttl).then(|| response.clone()),
+            Entry::Pending(_) => None,
+        }
+    }
+
+    pub fn insert(&mut self, scope: &str, request_id: &str, response: T) {
+        if !self.enabled() {
+            return;
+        }
+        let now = Instant::now();
+        self.entries.retain(|_, entry| match entry {
+            Entry::Done(at, _) => now.duration_since(*at) < self.ttl,
+            Entry::Pending(_) => true,
+        });
+        self.entries.insert((scope.to_string(), request_id.to_string()), Entry::Done(now, response));
+    }
+
+    // 在同一次加锁中查找并登记处理中的请求，同时到达的重试不会各自处理
+    pub fn lookup_or_begin(&mut self, scope: &str, request_id: &str) -> Lookup<T> {
+        let key = (scope.to_string(), request_id.to_string());
+        match self.entries.get(&key) {
+            Some(Entry::Done(at, response)) if at.elapsed() < self.ttl => return Lookup::Cached(response.clone()),
+            Some(Entry::Pending(rx)) => return Lookup::Wait(rx.clone()),
+            _ => {}
+        }
+        let (tx, rx) = watch::channel(None);
+        self.entries.insert(key, Entry::Pending(rx));
+        Lookup::Begin(tx)
+    }
+
+    // 处理完成，缓存响应并通知等待的重试
+    pub fn finish(&mut self, scope: &str, request_id: &str, tx: watch::Sender<Option<T>>, response: T) {
+        tx.send_replace(Some(response.clone()));
+        self.insert(scope, request_id, response);
+    }
+
+    // 处理失败或被取消，移除处理中的登记，等待的重试重新处理
+    pub fn abandon(&mut self, scope: &str, request_id: &str) {
+        let key = (scope.to_string(), request_id.to_string());
+        if matches!(self.entries.get(&key), Some(Entry::Pending(_))) {
+            self.entries.remove(&key);
+        }
+    }
+}
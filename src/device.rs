@@ -0,0 +1,347 @@
+use jiff::{civil::Date, tz::TimeZone, SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+// 超过该时长未请求的设备视为空闲
+const ACTIVE_WINDOW: SignedDuration = SignedDuration::from_secs(600);
+
+// 最近失败率的平滑系数，每次确认时新批次的失败率所占权重
+const RECENT_WEIGHT: f64 = 0.3;
+
+// 设备注册和配额
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceConfig {
+    // 严格模式：只有 allowed_udids 中的设备可以注册，未注册的设备不能取号
+    #[serde(default)]
+    pub strict: bool,
+    #[serde(default)]
+    pub allowed_udids: Vec<String>,
+    // 每台设备每天最多下发的号码数，0 表示不限制；注册时声明的 max_daily 更小时以其为准
+    #[serde(default)]
+    pub daily_quota: usize,
+    // 设备分组数：注册设备按 UDID 固定分到一个分组，号码按号码分到各分组，设备只取本分组的号码
+    #[serde(default = "default_shards")]
+    pub shards: usize,
+    // 新设备预热：首次请求后第 1 小时最多下发 ramp[0] 个号码，第 2 小时 ramp[1] 个……之后不再限制
+    #[serde(default)]
+    pub ramp: Vec<usize>,
+}
+
+fn default_shards() -> usize {
+    1
+}
+
+impl Default for DeviceConfig {
+    fn default() -> DeviceConfig {
+        DeviceConfig {
+            strict: false,
+            allowed_udids: Vec::new(),
+            daily_quota: 0,
+            shards: default_shards(),
+            ramp: Vec::new(),
+        }
+    }
+}
+
+// 按号码或 UDID 计算所在分组，结果只取决于内容，重启后不变
+pub fn shard_of(key: &str, shards: usize) -> usize {
+    if shards <= 1 {
+        return 0;
+    }
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    (hash % shards as u64) as usize
+}
+
+// 设备注册时声明的信息及分配结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Registration {
+    pub udid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ios_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub carrier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_daily: Option<usize>,
+    #[serde(default)]
+    pub shard: usize,
+    pub registered_at: Timestamp,
+}
+
+// 设备统计，设备通过 /fetch 的 device 参数标识
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceInfo {
+    pub device: String,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+    pub batches: u64,
+    pub issued: u64,
+    pub acked: u64,
+    pub failed: u64,
+    // 最近几批的失败率（指数加权），用于按失败率缩小批次
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent_failure_rate: Option<f64>,
+    // 设备声明的语言，用于选择消息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    // 最近一次心跳及设备上报的状态（电量等）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heartbeat: Option<Heartbeat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registration: Option<Registration>,
+    // 当天（按配置时区）已下发的号码数
+    pub issued_today: u64,
+    #[serde(skip)]
+    today: Option<Date>,
+    // 预热期间当前小时（从首次请求起算）已下发的号码数
+    #[serde(skip)]
+    ramp_hour: Option<i64>,
+    #[serde(skip)]
+    ramp_issued: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heartbeat {
+    pub at: Timestamp,
+    pub meta: HashMap<String, Value>,
+}
+
+impl DeviceInfo {
+    // 已确认号码中的失败占比
+    pub fn failure_rate(&self) -> Option<f64> {
+        (self.acked > 0).then(|| self.failed as f64 / self.acked as f64)
+    }
+
+    // 最近一次心跳上报的状态字段，如 battery
+    pub fn reported(&self, key: &str) -> Option<&Value> {
+        self.heartbeat.as_ref()?.meta.get(key)
+    }
+
+    pub fn status(&self, now: Timestamp) -> &'static str {
+        if now.duration_since(self.last_seen) <= ACTIVE_WINDOW {
+            "active"
+        } else {
+            "idle"
+        }
+    }
+}
+
+// 注册被拒绝：严格模式下 UDID 不在允许列表中
+#[derive(Debug)]
+pub struct NotAllowed;
+
+pub struct Devices {
+    devices: BTreeMap<String, DeviceInfo>,
+    config: DeviceConfig,
+    // 用于按当地日期统计每日配额
+    timezone: TimeZone,
+    // UDID 对应的设备号，重复注册时沿用
+    by_udid: HashMap<String, String>,
+}
+
+impl Devices {
+    pub fn new(config: &DeviceConfig, timezone: TimeZone) -> Devices {
+        if config.shards == 0 {
+            panic!("devices.shards must be at least 1");
+        }
+        Devices {
+            devices: BTreeMap::new(),
+            config: config.clone(),
+            timezone,
+            by_udid: HashMap::new(),
+        }
+    }
+
+    fn entry(&mut self, device: &str, now: Timestamp) -> &mut DeviceInfo {
+        let info = self.devices.entry(device.to_string()).or_insert_with(|| DeviceInfo {
+            device: device.to_string(),
+            first_seen: now,
+            last_seen: now,
+            batches: 0,
+            issued: 0,
+            acked: 0,
+            failed: 0,
+            recent_failure_rate: None,
+            locale: None,
+            heartbeat: None,
+            registration: None,
+            issued_today: 0,
+            today: None,
+            ramp_hour: None,
+            ramp_issued: 0,
+        });
+        info.last_seen = now;
+        info
+    }
+
+    pub fn record_fetch(&mut self, device: &str, issued: usize, now: Timestamp) {
+        let today = now.to_zoned(self.timezone.clone()).date();
+        let ramp_hour = self.ramp_stage(device, now).map(|(hour, _)| hour);
+        let info = self.entry(device, now);
+        if info.today != Some(today) {
+            info.today = Some(today);
+            info.issued_today = 0;
+        }
+        if info.ramp_hour != ramp_hour {
+            info.ramp_hour = ramp_hour;
+            info.ramp_issued = 0;
+        }
+        if issued > 0 {
+            info.batches += 1;
+            info.issued += issued as u64;
+            info.issued_today += issued as u64;
+            info.ramp_issued += issued as u64;
+        }
+    }
+
+    // 注册设备，同一 UDID 重复注册时沿用原设备号和分组，更新声明的信息；设备号随机生成，严格模式下不能猜测其他设备的设备号
+    pub fn register(
+        &mut self,
+        udid: &str,
+        ios_version: Option<String>,
+        carrier: Option<String>,
+        max_daily: Option<usize>,
+        now: Timestamp,
+    ) -> Result<(String, Registration), NotAllowed> {
+        if self.config.strict && !self.config.allowed_udids.iter().any(|u| u == udid) {
+            return Err(NotAllowed);
+        }
+        let device_id = match self.by_udid.get(udid) {
+            Some(id) => id.clone(),
+            None => {
+                let id = format!("dev-{:032x}", rand::random::<u128>());
+                self.by_udid.insert(udid.to_string(), id.clone());
+                id
+            }
+        };
+        let registration = Registration {
+            udid: udid.to_string(),
+            ios_version,
+            carrier,
+            max_daily,
+            shard: shard_of(udid, self.config.shards),
+            registered_at: now,
+        };
+        self.entry(&device_id, now).registration = Some(registration.clone());
+        Ok((device_id, registration))
+    }
+
+    // 已注册的设备及其注册信息，保存进度时使用
+    pub fn registrations(&self) -> impl Iterator<Item = (&str, &Registration)> {
+        self.devices.iter().filter_map(|(id, info)| Some((id.as_str(), info.registration.as_ref()?)))
+    }
+
+    // 恢复保存的注册信息，已注册的设备重启后沿用原设备号；分组按当前的分组数重新计算
+    pub fn restore_registrations(&mut self, registrations: Vec<(String, Registration)>) {
+        for (device, mut registration) in registrations {
+            registration.shard = shard_of(&registration.udid, self.config.shards);
+            self.by_udid.insert(registration.udid.clone(), device.clone());
+            let registered_at = registration.registered_at;
+            self.entry(&device, registered_at).registration = Some(registration);
+        }
+    }
+
+    // 各设备首次请求的时间，保存进度时使用，重启后预热不会重新开始
+    pub fn first_seen(&self) -> impl Iterator<Item = (&str, Timestamp)> {
+        self.devices.iter().map(|(id, info)| (id.as_str(), info.first_seen))
+    }
+
+    pub fn restore_first_seen(&mut self, first_seen: HashMap<String, Timestamp>) {
+        for (device, at) in first_seen {
+            let info = self.entry(&device, at);
+            info.first_seen = at;
+        }
+    }
+
+    pub fn strict(&self) -> bool {
+        self.config.strict
+    }
+
+    pub fn is_registered(&self, device: &str) -> bool {
+        self.devices.get(device).is_some_and(|info| info.registration.is_some())
+    }
+
+    // 已注册设备所在的分组，未启用分组或设备未注册时为 None，可取任意分组的号码
+    pub fn shard(&self, device: &str) -> Option<usize> {
+        let registration = self.devices.get(device)?.registration.as_ref()?;
+        (self.config.shards > 1).then_some(registration.shard)
+    }
+
+    // 设备每天的配额：全局配额与注册时声明的 max_daily 取较小者，都未配置时为 None
+    pub fn daily_quota(&self, device: &str) -> Option<usize> {
+        let global = (self.config.daily_quota > 0).then_some(self.config.daily_quota);
+        let declared = self.devices.get(device).and_then(|info| info.registration.as_ref()?.max_daily);
+        match (global, declared) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    // 设备当天剩余的配额，不限制时为 None
+    pub fn quota_remaining(&self, device: &str, now: Timestamp) -> Option<usize> {
+        let quota = self.daily_quota(device)?;
+        Some(quota.saturating_sub(self.issued_today(device, now) as usize))
+    }
+
+    // 设备当天已下发的号码数，跨天后未再取号时为 0
+    pub fn issued_today(&self, device: &str, now: Timestamp) -> u64 {
+        let today = now.to_zoned(self.timezone.clone()).date();
+        self.devices
+            .get(device)
+            .filter(|info| info.today == Some(today))
+            .map_or(0, |info| info.issued_today)
+    }
+
+    // 设备所处的预热小时及该小时的上限，预热已结束时为 None；未请求过的设备按第 1 小时计
+    fn ramp_stage(&self, device: &str, now: Timestamp) -> Option<(i64, usize)> {
+        let first_seen = self.devices.get(device).map_or(now, |info| info.first_seen);
+        let hour = now.duration_since(first_seen).as_secs() / 3600;
+        self.config.ramp.get(hour as usize).map(|&limit| (hour, limit))
+    }
+
+    // 预热期间当前小时的上限
+    pub fn ramp_limit(&self, device: &str, now: Timestamp) -> Option<usize> {
+        self.ramp_stage(device, now).map(|(_, limit)| limit)
+    }
+
+    // 预热期间当前小时剩余可下发的号码数及距下一小时的秒数，不在预热期时为 None
+    pub fn ramp_remaining(&self, device: &str, now: Timestamp) -> Option<(usize, u64)> {
+        let (hour, limit) = self.ramp_stage(device, now)?;
+        let info = self.devices.get(device);
+        let issued = info.filter(|info| info.ramp_hour == Some(hour)).map_or(0, |info| info.ramp_issued);
+        let elapsed = info.map_or(0, |info| now.duration_since(info.first_seen).as_secs());
+        Some((limit.saturating_sub(issued as usize), ((hour + 1) * 3600 - elapsed).max(1) as u64))
+    }
+
+    pub fn record_ack(&mut self, device: &str, count: usize, failed: usize, now: Timestamp) {
+        let info = self.entry(device, now);
+        info.acked += count as u64;
+        info.failed += failed as u64;
+        if count > 0 {
+            let rate = failed as f64 / count as f64;
+            info.recent_failure_rate =
+                Some(info.recent_failure_rate.map_or(rate, |recent| RECENT_WEIGHT * rate + (1.0 - RECENT_WEIGHT) * recent));
+        }
+    }
+
+    // 最近的失败率，累计确认的号码数不足 min_acked 时为 None
+    pub fn recent_failure_rate(&self, device: &str, min_acked: u64) -> Option<f64> {
+        self.devices.get(device).filter(|info| info.acked >= min_acked).and_then(|info| info.recent_failure_rate)
+    }
+
+    pub fn set_locale(&mut self, device: &str, locale: &str, now: Timestamp) {
+        self.entry(device, now).locale = Some(locale.to_string());
+    }
+
+    pub fn record_heartbeat(&mut self, device: &str, meta: HashMap<String, Value>, now: Timestamp) {
+        self.entry(device, now).heartbeat = Some(Heartbeat { at: now, meta });
+    }
+
+    pub fn locale(&self, device: &str) -> Option<&str> {
+        self.devices.get(device).and_then(|info| info.locale.as_deref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DeviceInfo> {
+        self.devices.values()
+    }
+}
@@ -0,0 +1,92 @@
+use hmac::{Hmac, Mac};
+use jiff::{SignedDuration, Timestamp};
+use serde::Deserialize;
+use sha2::Sha256;
+
+// 导出文件的限时下载链接：签名为 HMAC-SHA256(secret, "文件名\n到期时间")，服务端不保存链接，重启后仍然有效
+#[derive(Debug, Clone, Deserialize)]
+pub struct DownloadConfig {
+    pub secret: String,
+    // 链接默认有效期（秒）
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    // 请求中 ttl_secs 的上限，超出时返回 400
+    #[serde(default = "default_max_ttl_secs")]
+    pub max_ttl_secs: u64,
+    // 链接前缀，即设备接口的对外地址，如 "http://1.2.3.4:3000"，不配置时返回相对路径
+    pub base_url: Option<String>,
+}
+
+fn default_ttl_secs() -> u64 {
+    86400
+}
+
+fn default_max_ttl_secs() -> u64 {
+    7 * 86400
+}
+
+// 链接校验失败的原因
+#[derive(Debug, PartialEq)]
+pub enum Invalid {
+    Signature,
+    Expired,
+}
+
+impl DownloadConfig {
+    pub fn validate(&self) {
+        if self.secret.is_empty() {
+            panic!("downloads.secret must not be empty");
+        }
+        if self.ttl_secs > self.max_ttl_secs {
+            panic!("downloads.ttl_secs ({}) exceeds downloads.max_ttl_secs ({})", self.ttl_secs, self.max_ttl_secs);
+        }
+    }
+
+    // now 之后 ttl 秒的到期时间，ttl 超出 max_ttl_secs 时返回 None
+    pub fn expires_at(&self, ttl: u64, now: Timestamp) -> Option<Timestamp> {
+        if ttl > self.max_ttl_secs {
+            return None;
+        }
+        now.checked_add(SignedDuration::from_secs(i64::try_from(ttl).ok()?)).ok()
+    }
+
+    // 导出目录中 name 文件的下载链接
+    pub fn link(&self, name: &str, expires: Timestamp) -> String {
+        let expires = expires.as_second();
+        format!(
+            "{}/download/{}?expires={}&sig={}",
+            self.base_url.as_deref().unwrap_or_default().trim_end_matches('/'),
+            name,
+            expires,
+            self.mac(name, expires).finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect::<String>()
+        )
+    }
+
+    pub fn verify(&self, name: &str, expires: i64, sig: &str, now: Timestamp) -> Result<(), Invalid> {
+        // verify_slice 的比较耗时与签名在哪一位不同无关
+        let sig = decode_hex(sig).ok_or(Invalid::Signature)?;
+        self.mac(name, expires).verify_slice(&sig).map_err(|_| Invalid::Signature)?;
+        if now.as_second() > expires {
+            return Err(Invalid::Expired);
+        }
+        Ok(())
+    }
+
+    fn mac(&self, name: &str, expires: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}\n{}", name, expires).as_bytes());
+        mac
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok()).collect()
+}
+
+// 下载链接中的文件名只能是导出目录下的文件，不能包含路径
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+// 内置常用 emoji 短代码
+const BUILTIN: &[(&str, &str)] = &[
+    ("smile", "😄"),
+    ("grin", "😁"),
+    ("joy", "😂"),
+    ("blush", "😊"),
+    ("wink", "😉"),
+    ("heart_eyes", "😍"),
+    ("thinking", "🤔"),
+    ("heart", "❤️"),
+    ("tada", "🎉"),
+    ("confetti_ball", "🎊"),
+    ("gift", "🎁"),
+    ("balloon", "🎈"),
+    ("fire", "🔥"),
+    ("star", "⭐"),
+    ("sparkles", "✨"),
+    ("+1", "👍"),
+    ("thumbsup", "👍"),
+    ("ok_hand", "👌"),
+    ("pray", "🙏"),
+    ("clap", "👏"),
+    ("wave", "👋"),
+    ("point_right", "👉"),
+    ("point_down", "👇"),
+    ("rocket", "🚀"),
+    ("moneybag", "💰"),
+    ("red_envelope", "🧧"),
+    ("bell", "🔔"),
+    ("calendar", "📅"),
+    ("alarm_clock", "⏰"),
+    ("hourglass", "⌛"),
+    ("warning", "⚠️"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("red_circle", "🔴"),
+    ("phone", "☎️"),
+    ("iphone", "📱"),
+    ("email", "📧"),
+    ("link", "🔗"),
+    ("new", "🆕"),
+    ("free", "🆓"),
+    ("hot_pepper", "🌶️"),
+    ("coffee", "☕"),
+    ("cake", "🍰"),
+    ("sunny", "☀️"),
+];
+
+// 将消息中的 :name: 短代码展开为 emoji，自定义表优先于内置表，未知短代码原样保留
+pub fn expand(text: &str, custom: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(open) = rest.find(':') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let code = after.find(':').map(|close| &after[..close]);
+        match code.filter(|c| is_code(c)).and_then(|c| lookup(c, custom).map(|e| (c, e))) {
+            Some((code, emoji)) => {
+                out.push_str(emoji);
+                rest = &after[code.len() + 1..];
+            }
+            None => {
+                out.push(':');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn lookup<'a>(code: &str, custom: &'a HashMap<String, String>) -> Option<&'a str> {
+    custom.get(code).map(String::as_str).or_else(|| {
+        BUILTIN.iter().find(|(name, _)| *name == code).map(|(_, emoji)| *emoji)
+    })
+}
+
+fn is_code(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_+-".contains(c))
+}
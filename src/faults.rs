@@ -0,0 +1,62 @@
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+
+// 测试模式下注入的故障，用于验证设备端的错误处理
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Faults {
+    // /fetch 返回号码已取完，不动号码池
+    pub exhausted: bool,
+    // 设备接口在处理前等待的毫秒数
+    pub latency_ms: u64,
+    // /fetch 返回无法解析的响应
+    pub malformed: bool,
+    // 熔断打开到该时间，期间设备接口返回 503
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breaker_until: Option<Timestamp>,
+    // 服务时钟比系统时间快的秒数，清除故障时保留，时钟不回拨
+    #[serde(skip_serializing_if = "is_zero")]
+    pub clock_offset_secs: i64,
+}
+
+fn is_zero(secs: &i64) -> bool {
+    *secs == 0
+}
+
+// 修改故障设置，未提供的字段保持不变
+#[derive(Debug, Deserialize)]
+pub struct FaultsUpdate {
+    pub exhausted: Option<bool>,
+    pub latency_ms: Option<u64>,
+    pub malformed: Option<bool>,
+    // 打开熔断的秒数，0 表示关闭
+    pub breaker_secs: Option<u64>,
+    // 服务时钟前进的秒数，等待中的活动截止和号码源计划随之到期
+    pub advance_secs: Option<u64>,
+}
+
+impl Faults {
+    pub fn apply(&mut self, update: FaultsUpdate, now: Timestamp) {
+        if let Some(exhausted) = update.exhausted {
+            self.exhausted = exhausted;
+        }
+        if let Some(latency_ms) = update.latency_ms {
+            self.latency_ms = latency_ms;
+        }
+        if let Some(malformed) = update.malformed {
+            self.malformed = malformed;
+        }
+        if let Some(secs) = update.breaker_secs {
+            self.breaker_until = now.checked_add(SignedDuration::from_secs(secs as i64)).ok().filter(|_| secs > 0);
+        }
+        if let Some(secs) = update.advance_secs {
+            crate::clock::advance(secs as i64);
+            self.clock_offset_secs = crate::clock::offset();
+        }
+    }
+
+    // 熔断剩余秒数，未打开时为 None
+    pub fn breaker_remaining(&self, now: Timestamp) -> Option<u64> {
+        let until = self.breaker_until.filter(|&until| until > now)?;
+        Some(until.duration_since(now).as_secs().max(1) as u64)
+    }
+}
@@ -0,0 +1,48 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+// 功能开关：可选子系统已编译在内，可按部署单独关闭
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FeaturesConfig {
+    // 每批开头插入测试号
+    #[serde(default = "enabled")]
+    pub test_number: bool,
+    // 使用导入号码的附加信息渲染个性化消息
+    #[serde(default = "enabled")]
+    pub personalization: bool,
+    // 按 [notify] 配置推送事件
+    #[serde(default = "enabled")]
+    pub webhooks: bool,
+    // GET /metrics
+    #[serde(default = "enabled")]
+    pub metrics: bool,
+    // GET /dashboard
+    #[serde(default = "enabled")]
+    pub dashboard: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> FeaturesConfig {
+        FeaturesConfig { test_number: true, personalization: true, webhooks: true, metrics: true, dashboard: true }
+    }
+}
+
+impl FeaturesConfig {
+    pub fn log_disabled(&self) {
+        let flags = [
+            ("test_number", self.test_number),
+            ("personalization", self.personalization),
+            ("webhooks", self.webhooks),
+            ("metrics", self.metrics),
+            ("dashboard", self.dashboard),
+        ];
+        let disabled: Vec<&str> = flags.iter().filter(|(_, on)| !on).map(|(name, _)| *name).collect();
+        if !disabled.is_empty() {
+            info!("已关闭功能: {}", disabled.join(", "));
+        }
+    }
+}
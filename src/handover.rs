@@ -0,0 +1,134 @@
+use log::{info, warn};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    env, fs,
+    net::{TcpListener, ToSocketAddrs},
+    os::fd::FromRawFd,
+    time::{Duration, Instant},
+};
+
+// systemd socket activation 传入的第一个描述符
+const LISTEN_FDS_START: i32 = 3;
+
+// 监听套接字：优先使用 systemd socket activation 传入的描述符，按 FileDescriptorName 匹配 name，
+// 未匹配时按顺序（第 0 个为设备接口，第 1 个为管理接口）；否则自行绑定，reuse_port 时设置 SO_REUSEPORT
+pub fn listen(addr: &str, name: &str, index: usize, reuse_port: bool) -> tokio::net::TcpListener {
+    let listener = match inherited(name, index) {
+        Some(listener) => {
+            info!("使用 systemd 传入的 {} 监听套接字", name);
+            listener
+        }
+        None => bind(addr, reuse_port).unwrap_or_else(|e| match e.kind() {
+            std::io::ErrorKind::AddrInUse if !reuse_port => {
+                panic!("Failed to bind {}: {} (set reuse_port = true to start alongside the running process)", addr, e)
+            }
+            _ => panic!("Failed to bind {}: {}", addr, e),
+        }),
+    };
+    listener.set_nonblocking(true).expect("设置非阻塞失败");
+    tokio::net::TcpListener::from_std(listener).expect("注册监听套接字失败")
+}
+
+// 是否由 systemd socket activation 启动，监听套接字由 systemd 持有
+pub fn activated() -> bool {
+    env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id())
+}
+
+fn inherited(name: &str, index: usize) -> Option<TcpListener> {
+    if !activated() {
+        return None;
+    }
+    let count: usize = env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let position = names.split(':').position(|n| n == name).unwrap_or(index);
+    if position >= count {
+        return None;
+    }
+    // systemd 保证 LISTEN_FDS_START 起的 count 个描述符属于本进程
+    Some(unsafe { TcpListener::from_raw_fd(LISTEN_FDS_START + position as i32) })
+}
+
+fn bind(addr: &str, reuse_port: bool) -> std::io::Result<TcpListener> {
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no address"))?;
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+// 接管 pid_file 中记录的旧进程：发送 SIGTERM，旧进程停止接受新连接、处理完进行中的请求并保存进度后退出
+// 新进程的监听套接字须已绑定（reuse_port 或 systemd 传入），等待期间新连接在新进程的队列中等待
+// 未配置 [state] 时拒绝接管，否则新进程从头下发，已发送的号码会重复发送
+pub async fn take_over(pid_file: &str, timeout: Duration, has_state: bool) {
+    let Some(pid) = fs::read_to_string(pid_file).ok().and_then(|s| s.trim().parse::<i32>().ok()) else {
+        return;
+    };
+    if pid as u32 == std::process::id() || !alive(pid) {
+        return;
+    }
+    // pid_file 可能已过期，进程号被其他程序复用，只接管运行同一可执行文件的进程
+    if !same_executable(pid) {
+        warn!("{} 中的进程 {} 不是本程序，不接管", pid_file, pid);
+        return;
+    }
+    if !has_state {
+        panic!("Refusing to take over process {} without [state]: progress would be lost", pid);
+    }
+    info!("接管旧进程 {}，等待其处理完进行中的请求并保存进度", pid);
+    unsafe {
+        libc::kill(pid, libc::SIGTERM);
+    }
+    let start = Instant::now();
+    while alive(pid) {
+        if start.elapsed() > timeout {
+            panic!("Old process {} did not exit within {} seconds", pid, timeout.as_secs());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    info!("旧进程 {} 已退出，用时 {} 毫秒", pid, start.elapsed().as_millis());
+}
+
+// 进程运行的可执行文件是否与本进程相同；升级时旧文件被替换，/proc/<pid>/exe 带 " (deleted)" 后缀
+fn same_executable(pid: i32) -> bool {
+    let Ok(exe) = fs::read_link(format!("/proc/{}/exe", pid)) else { return false };
+    let Ok(current) = env::current_exe() else { return false };
+    let exe = exe.to_string_lossy();
+    let current = current.to_string_lossy();
+    exe.strip_suffix(" (deleted)").unwrap_or(&exe) == current.strip_suffix(" (deleted)").unwrap_or(&current)
+}
+
+// 进程是否存在（信号 0 只检查权限和存在性）
+fn alive(pid: i32) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+pub fn write_pid(pid_file: &str) {
+    if let Err(e) = fs::write(pid_file, std::process::id().to_string()) {
+        warn!("写入 {} 失败: {}", pid_file, e);
+    }
+}
+
+// 退出时删除 pid_file，已被新进程改写时保留
+pub fn remove_pid(pid_file: &str) {
+    let own = fs::read_to_string(pid_file).is_ok_and(|s| s.trim() == std::process::id().to_string());
+    if own {
+        let _ = fs::remove_file(pid_file);
+    }
+}
+
+// 收到 SIGTERM 或 Ctrl-C 时返回
+pub async fn shutdown_signal() {
+    let mut terminate =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).expect("注册 SIGTERM 失败");
+    tokio::select! {
+        _ = terminate.recv() => info!("收到 SIGTERM，停止接受新连接"),
+        _ = tokio::signal::ctrl_c() => info!("收到 Ctrl-C，停止接受新连接"),
+    }
+}
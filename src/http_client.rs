@@ -0,0 +1,86 @@
+use reqwest::{Client, Method, Url};
+use std::{sync::LazyLock, time::Duration};
+
+// 出站 HTTP 客户端（YOURLS、Bitly、Webhook、Slack、Telegram、号码源等），支持 http:// 和 https://，
+// TLS 使用 rustls 和内置的根证书
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .use_rustls_tls()
+        .user_agent(concat!("ios_sms_rpa/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build http client")
+});
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+pub async fn post(
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+    timeout: Duration,
+) -> Result<Response, String> {
+    request("POST", url, headers, Some(body), timeout).await
+}
+
+pub async fn request(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<&str>,
+    timeout: Duration,
+) -> Result<Response, String> {
+    let method = Method::from_bytes(method.as_bytes()).map_err(|e| format!("invalid method {}: {}", method, e))?;
+    let mut req = CLIENT.request(method, check_url(url)?).timeout(timeout);
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    if let Some(body) = body {
+        req = req.body(body.to_string());
+    }
+    let resp = req.send().await.map_err(|e| describe(url, e))?;
+    let status = resp.status().as_u16();
+    let body = resp.text().await.map_err(|e| describe(url, e))?;
+    Ok(Response { status, body })
+}
+
+// 校验 http:// 或 https:// 地址
+pub fn check_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("invalid url {}: {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("unsupported url (only http:// and https:// are supported): {}", url));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(format!("missing host in url: {}", url));
+    }
+    Ok(parsed)
+}
+
+fn describe(url: &str, e: reqwest::Error) -> String {
+    if e.is_timeout() {
+        format!("request to {} timed out", url)
+    } else {
+        format!("request to {} failed: {}", url, e)
+    }
+}
+
+// URL 查询参数编码
+pub fn encode_component(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
@@ -0,0 +1,100 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// 导入的单个号码及其附加信息（可作为消息模板变量）
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub number: String,
+    pub meta: HashMap<String, String>,
+}
+
+// 导入结果汇总
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Summary {
+    pub received: usize,
+    pub accepted: usize,
+    pub duplicates: usize,
+    pub invalid: usize,
+    pub blacklisted: usize,
+    // 前若干条错误详情
+    pub errors: Vec<String>,
+}
+
+const MAX_ERRORS: usize = 20;
+
+impl Summary {
+    pub fn error(&mut self, message: String) {
+        if self.errors.len() < MAX_ERRORS {
+            self.errors.push(message);
+        }
+    }
+}
+
+// 解析 JSON 数组或 NDJSON（每行一个号码字符串或对象），无法解析的条目计入 invalid
+pub fn parse(body: &str, summary: &mut Summary) -> Vec<Record> {
+    let body = body.trim_start_matches('\u{feff}').trim();
+    let values: Vec<(usize, Result<Value, String>)> = if body.starts_with('[') {
+        match serde_json::from_str::<Vec<Value>>(body) {
+            Ok(values) => values.into_iter().enumerate().map(|(i, v)| (i + 1, Ok(v))).collect(),
+            Err(e) => {
+                summary.invalid += 1;
+                summary.error(format!("invalid json array: {}", e));
+                return Vec::new();
+            }
+        }
+    } else {
+        body.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| (i + 1, serde_json::from_str(line).map_err(|e| e.to_string())))
+            .collect()
+    };
+
+    let mut records = Vec::with_capacity(values.len());
+    for (line, value) in values {
+        summary.received += 1;
+        match value.and_then(to_record) {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                summary.invalid += 1;
+                summary.error(format!("#{}: {}", line, e));
+            }
+        }
+    }
+    records
+}
+
+fn to_record(value: Value) -> Result<Record, String> {
+    let (raw, meta) = match value {
+        Value::String(number) => (number, HashMap::new()),
+        Value::Number(number) => (number.to_string(), HashMap::new()),
+        Value::Object(mut obj) => {
+            let number = match obj.remove("number") {
+                Some(Value::String(s)) => s,
+                Some(Value::Number(n)) => n.to_string(),
+                _ => return Err("missing \"number\" field".to_string()),
+            };
+            let meta = obj
+                .into_iter()
+                .map(|(k, v)| match v {
+                    Value::String(s) => (k, s),
+                    other => (k, other.to_string()),
+                })
+                .collect();
+            (number, meta)
+        }
+        other => return Err(format!("unexpected entry {}", other)),
+    };
+
+    let number = normalize(&raw).ok_or_else(|| format!("invalid number {:?}", raw))?;
+    Ok(Record { number, meta })
+}
+
+// 去掉空格和连字符，号码须为 5-15 位数字，可带 + 前缀
+pub fn normalize(raw: &str) -> Option<String> {
+    let number: String = raw.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+    let digits = number.strip_prefix('+').unwrap_or(&number);
+    let valid = (5..=15).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit());
+    valid.then_some(number)
+}
@@ -0,0 +1,244 @@
+use jiff::Timestamp;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::watch;
+
+// 持久化保留的任务记录上限，超出时丢弃最早结束的记录
+const MAX_RECORDS: usize = 500;
+
+// 后台任务状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    // import / export / report / archive
+    pub kind: String,
+    // running / completed / failed / cancelled / interrupted（服务重启时仍在运行）
+    pub status: String,
+    pub processed: usize,
+    pub total: usize,
+    // 已请求取消，任务在下一批处理前停止
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancel_requested: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // 创建任务的请求的追踪号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    pub created_at: Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<Timestamp>,
+}
+
+impl Job {
+    pub fn is_running(&self) -> bool {
+        self.status == "running"
+    }
+}
+
+// 运行中任务持有的句柄，用于检查是否已被取消
+#[derive(Debug, Clone)]
+pub struct JobHandle {
+    pub id: String,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// 取消请求的结果
+pub enum Cancel {
+    Requested(Box<Job>),
+    Finished,
+    NotFound,
+}
+
+pub struct Jobs {
+    jobs: BTreeMap<String, Job>,
+    seq: u64,
+    cancels: HashMap<String, Arc<AtomicBool>>,
+    // 任务记录文件，不配置则只保存在内存中
+    file: Option<String>,
+    // 后台写入任务记录文件，首次保存时启动，只写入最新的内容
+    writer: Option<watch::Sender<String>>,
+}
+
+impl Jobs {
+    // 加载任务记录，上次退出时仍在运行的任务标记为 interrupted
+    pub fn load(file: Option<&str>) -> Jobs {
+        let mut jobs = BTreeMap::new();
+        if let Some(path) = file {
+            match fs::read_to_string(path) {
+                Ok(content) => {
+                    let records: Vec<Job> = serde_json::from_str(&content)
+                        .unwrap_or_else(|e| panic!("任务记录文件 {} 格式错误: {}", path, e));
+                    for mut job in records {
+                        if job.is_running() {
+                            job.status = "interrupted".to_string();
+                            job.error = Some("server restarted while the job was running".to_string());
+                        }
+                        jobs.insert(job.id.clone(), job);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => panic!("无法读取任务记录文件 {}: {}", path, e),
+            }
+        }
+        let seq = jobs
+            .keys()
+            .filter_map(|id| id.rsplit('-').next().and_then(|n| n.parse::<u64>().ok()))
+            .max()
+            .unwrap_or(0);
+        Jobs { jobs, seq, cancels: HashMap::new(), file: file.map(str::to_string), writer: None }
+    }
+
+    pub fn create(&mut self, kind: &str, trace_id: Option<String>) -> JobHandle {
+        self.seq += 1;
+        let id = format!("{}-{}", kind, self.seq);
+        self.jobs.insert(
+            id.clone(),
+            Job {
+                id: id.clone(),
+                kind: kind.to_string(),
+                status: "running".to_string(),
+                processed: 0,
+                total: 0,
+                cancel_requested: false,
+                summary: None,
+                error: None,
+                trace_id,
+                created_at: crate::clock::now(),
+                finished_at: None,
+            },
+        );
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancels.insert(id.clone(), cancelled.clone());
+        self.save();
+        JobHandle { id, cancelled }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Job> {
+        self.jobs.get(id)
+    }
+
+    // 按创建时间排序列出所有任务
+    pub fn list(&self) -> Vec<&Job> {
+        let mut list: Vec<&Job> = self.jobs.values().collect();
+        list.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        list
+    }
+
+    pub fn running(&self) -> usize {
+        self.jobs.values().filter(|job| job.is_running()).count()
+    }
+
+    // 进度只更新内存，不写文件
+    pub fn progress(&mut self, id: &str, processed: usize, total: usize) {
+        if let Some(job) = self.jobs.get_mut(id) {
+            job.processed = processed;
+            job.total = total;
+        }
+    }
+
+    pub fn cancel(&mut self, id: &str) -> Cancel {
+        let Some(job) = self.jobs.get_mut(id) else {
+            return Cancel::NotFound;
+        };
+        if !job.is_running() {
+            return Cancel::Finished;
+        }
+        if let Some(flag) = self.cancels.get(id) {
+            flag.store(true, Ordering::Relaxed);
+        }
+        job.cancel_requested = true;
+        Cancel::Requested(Box::new(job.clone()))
+    }
+
+    pub fn complete(&mut self, id: &str, summary: Value) {
+        self.finish(id, "completed", Some(summary), None);
+    }
+
+    pub fn fail(&mut self, id: &str, error: String) {
+        self.finish(id, "failed", None, Some(error));
+    }
+
+    // 任务响应取消后停止，保留已完成部分的结果
+    pub fn cancelled(&mut self, id: &str, summary: Option<Value>) {
+        self.finish(id, "cancelled", summary, None);
+    }
+
+    fn finish(&mut self, id: &str, status: &str, summary: Option<Value>, error: Option<String>) {
+        self.cancels.remove(id);
+        if let Some(job) = self.jobs.get_mut(id) {
+            job.status = status.to_string();
+            job.summary = summary;
+            job.error = error;
+            job.finished_at = Some(crate::clock::now());
+        }
+        self.prune();
+        self.save();
+    }
+
+    fn prune(&mut self) {
+        let excess = self.jobs.len().saturating_sub(MAX_RECORDS);
+        let mut finished: Vec<(Timestamp, String)> = self
+            .jobs
+            .values()
+            .filter(|job| !job.is_running())
+            .map(|job| (job.finished_at.unwrap_or(job.created_at), job.id.clone()))
+            .collect();
+        finished.sort();
+        for (_, id) in finished.into_iter().take(excess) {
+            self.jobs.remove(&id);
+        }
+    }
+
+    // 在锁内序列化，交给后台写入，不在持锁时做文件 IO
+    fn save(&mut self) {
+        let Some(path) = &self.file else {
+            return;
+        };
+        let content = serde_json::to_string_pretty(&self.list()).expect("序列化任务记录失败");
+        match &self.writer {
+            Some(writer) => {
+                writer.send_replace(content);
+            }
+            None => {
+                let (writer, rx) = watch::channel(content);
+                tokio::spawn(write_records(path.clone(), rx));
+                self.writer = Some(writer);
+            }
+        }
+    }
+}
+
+// 先写临时文件再改名，避免写到一半退出导致记录损坏；写入期间的多次更新合并为一次
+async fn write_records(path: String, mut rx: watch::Receiver<String>) {
+    let tmp = format!("{}.tmp", path);
+    loop {
+        let content = rx.borrow_and_update().clone();
+        let written = async {
+            tokio::fs::write(&tmp, content).await?;
+            tokio::fs::rename(&tmp, &path).await
+        };
+        if let Err(e) = written.await {
+            warn!("写入任务记录文件 {} 失败: {}", path, e);
+        }
+        if rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
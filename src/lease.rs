@@ -0,0 +1,189 @@
+use jiff::{SignedDuration, Timestamp};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// 租约配置：下发的批次需设备确认（/ack），超时未确认的号码回收重发
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeaseConfig {
+    // 租约时长（秒）
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    // 每个活动同时未确认的批次数上限，0 表示不限制；[[campaigns]] 中可各自设置
+    #[serde(default)]
+    pub max_outstanding: usize,
+}
+
+fn default_ttl_secs() -> u64 {
+    1800
+}
+
+pub struct Lease {
+    pub numbers: Vec<String>,
+    pub device: Option<String>,
+    pub issued_at: Timestamp,
+    pub expires_at: Timestamp,
+    // 下发该批次的请求的追踪号
+    pub trace_id: Option<String>,
+    // 批次中号码所属的活动，批次计入这些活动的未确认批次数
+    pub campaigns: Vec<usize>,
+}
+
+pub struct Leases {
+    ttl: SignedDuration,
+    // 各活动的未确认批次上限，按活动序号
+    max_outstanding: Vec<usize>,
+    leases: HashMap<String, Lease>,
+    // 各活动已取号、正在渲染尚未登记的批次数，与未确认批次一起计入上限
+    reserved: Vec<usize>,
+    // 批次号前缀（启动时间），避免重启后批次号重复
+    epoch: i64,
+    seq: u64,
+}
+
+impl Leases {
+    // max_outstanding 为各活动的上限
+    pub fn new(config: &LeaseConfig, max_outstanding: Vec<usize>) -> Leases {
+        Leases {
+            ttl: SignedDuration::from_secs(config.ttl_secs as i64),
+            reserved: vec![0; max_outstanding.len()],
+            max_outstanding,
+            leases: HashMap::new(),
+            epoch: Timestamp::now().as_second(),
+            seq: 0,
+        }
+    }
+
+    pub fn outstanding(&self) -> usize {
+        self.leases.len()
+    }
+
+    // 某个活动的未确认批次数
+    pub fn outstanding_in(&self, campaign: usize) -> usize {
+        self.leases.values().filter(|lease| lease.campaigns.contains(&campaign)).count()
+    }
+
+    // 活动的未确认批次（含预留的）是否已达上限
+    pub fn is_full(&self, campaign: usize) -> bool {
+        let max = self.max_outstanding[campaign];
+        max > 0 && self.outstanding_in(campaign) + self.reserved[campaign] >= max
+    }
+
+    // 为活动预留一个批次名额，之后 release 归还并 issue 登记批次；须与 is_full 在同一次加锁中调用
+    pub fn reserve(&mut self, campaign: usize) {
+        self.reserved[campaign] += 1;
+    }
+
+    pub fn release(&mut self, campaign: usize) {
+        self.reserved[campaign] = self.reserved[campaign].saturating_sub(1);
+    }
+
+    // 登记新批次，返回批次号和租约到期时间
+    pub fn issue(
+        &mut self,
+        numbers: Vec<String>,
+        campaigns: Vec<usize>,
+        device: Option<String>,
+        trace_id: Option<String>,
+        now: Timestamp,
+    ) -> (String, Timestamp) {
+        self.seq += 1;
+        let id = format!("{}-{}", self.epoch, self.seq);
+        let expires_at = now.checked_add(self.ttl).expect("lease expiry overflow");
+        let lease = Lease { numbers, device, issued_at: now, expires_at, trace_id, campaigns };
+        self.leases.insert(id.clone(), lease);
+        (id, expires_at)
+    }
+
+    // 按下发时间排序的未确认批次
+    pub fn iter(&self) -> Vec<(&str, &Lease)> {
+        let mut leases: Vec<(&str, &Lease)> = self.leases.iter().map(|(id, l)| (id.as_str(), l)).collect();
+        leases.sort_by_key(|(_, lease)| lease.issued_at);
+        leases
+    }
+
+    // 恢复重启前保存的批次
+    pub fn restore(&mut self, id: String, lease: Lease) {
+        self.leases.insert(id, lease);
+    }
+
+    pub fn clear(&mut self) {
+        self.leases.clear();
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Lease> {
+        self.leases.get(id)
+    }
+
+    pub fn ack(&mut self, id: &str) -> Option<Lease> {
+        self.leases.remove(id)
+    }
+
+    // 回收所有已过期的租约，按下发时间排序返回
+    pub fn reclaim_expired(&mut self, now: Timestamp) -> Vec<(String, Lease)> {
+        let expired: Vec<String> = self
+            .leases
+            .iter()
+            .filter(|(_, lease)| lease.expires_at <= now)
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut reclaimed: Vec<(String, Lease)> = expired
+            .into_iter()
+            .filter_map(|id| self.leases.remove(&id).map(|lease| (id, lease)))
+            .collect();
+        reclaimed.sort_by_key(|(_, lease)| lease.issued_at);
+        reclaimed
+    }
+}
+
+// 重发队列与新号码的取号顺序
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetryConfig {
+    // retries_first / fresh_first / interleave
+    #[serde(default = "default_retry_policy")]
+    pub policy: String,
+    // interleave 模式下每批中重发号码的占比
+    #[serde(default = "default_retry_share")]
+    pub share: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig { policy: default_retry_policy(), share: default_retry_share() }
+    }
+}
+
+fn default_retry_policy() -> String {
+    "retries_first".to_string()
+}
+
+fn default_retry_share() -> f64 {
+    0.5
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RetryPolicy {
+    RetriesFirst,
+    FreshFirst,
+    Interleave(f64),
+}
+
+impl RetryPolicy {
+    pub fn new(config: &RetryConfig) -> RetryPolicy {
+        match config.policy.as_str() {
+            "retries_first" => RetryPolicy::RetriesFirst,
+            "fresh_first" => RetryPolicy::FreshFirst,
+            "interleave" if (0.0..=1.0).contains(&config.share) => RetryPolicy::Interleave(config.share),
+            "interleave" => panic!("retry.share must be between 0 and 1"),
+            other => panic!("Unknown retry policy '{}', expected retries_first, fresh_first or interleave", other),
+        }
+    }
+
+    // n 个空位中优先分给重发号码的数量
+    pub fn retry_quota(&self, slots: usize) -> usize {
+        match self {
+            RetryPolicy::RetriesFirst => slots,
+            RetryPolicy::FreshFirst => 0,
+            RetryPolicy::Interleave(share) => (slots as f64 * share).round() as usize,
+        }
+    }
+}
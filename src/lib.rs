@@ -0,0 +1,50 @@
+// 服务本身（server::main 为 ios_sms_rpa 程序的入口，server::build_app 在当前进程中构建服务），
+// 以及供其他 Rust 工具复用的部分：简易 HTTP 客户端，启用 client 功能后的设备协议客户端，
+// 启用 test-util 功能后启动临时服务的集成测试支持
+pub mod http_client;
+pub mod server;
+
+#[cfg(feature = "client")]
+pub mod client;
+
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+mod append_log;
+mod backpressure;
+mod campaign;
+mod changelog;
+pub mod clock;
+mod cron;
+mod datetime;
+mod dedup;
+mod download;
+mod device;
+mod emoji;
+mod faults;
+mod features;
+mod handover;
+mod import;
+mod jobs;
+mod lease;
+mod message;
+mod notify;
+mod pacing;
+mod prerender;
+mod quarantine;
+mod region;
+mod replies;
+mod runtime;
+mod segments;
+mod shed;
+mod shortener;
+mod smtp;
+mod source;
+mod store;
+mod strings;
+mod summary;
+mod template;
+mod trace;
+mod usage;
+mod verify;
+mod watch;
\ No newline at end of file
@@ -0,0 +1,97 @@
+use crate::{
+    datetime,
+    shortener::{ShortLink, Shortener},
+    template,
+};
+use jiff::{tz::TimeZone, Zoned};
+use log::warn;
+use std::{collections::HashMap, sync::Arc};
+
+// 消息渲染上下文，从 AppState 中取出后在锁外异步渲染
+pub struct Renderer {
+    pub template: String,
+    pub vars: HashMap<String, String>,
+    pub timezone: TimeZone,
+    pub locale: datetime::Locale,
+    pub shortener: Option<Arc<Shortener>>,
+}
+
+// 渲染结果：消息及其中为号码生成的短链，号码下发后再记录短链
+#[derive(Debug, Clone)]
+pub struct Rendered {
+    pub message: String,
+    pub link: Option<ShortLink>,
+}
+
+impl Renderer {
+    pub fn now(&self) -> Zoned {
+        crate::clock::now().to_zoned(self.timezone.clone())
+    }
+
+    // 为单个号码渲染消息：{link} 按号码生成短链，其余依次查号码附加信息、模板变量、日期占位符
+    // 短链生成失败时使用长链接
+    pub async fn render(&self, number: &str, meta: Option<&HashMap<String, String>>, now: &Zoned) -> Rendered {
+        match self.link(number, meta, now).await {
+            Ok(link) => Rendered { message: self.fill(link.as_ref().map(|l| l.short.clone()), meta, now), link },
+            Err((long_url, e)) => {
+                warn!("号码 {} 生成短链失败，使用原链接: {}", number, e);
+                Rendered { message: self.fill(Some(long_url), meta, now), link: None }
+            }
+        }
+    }
+
+    // 预渲染使用：短链生成失败时返回 None，不缓存退回长链接的消息，取号时再重试
+    pub async fn prerender(&self, number: &str, meta: Option<&HashMap<String, String>>, now: &Zoned) -> Option<Rendered> {
+        match self.link(number, meta, now).await {
+            Ok(link) => Some(Rendered { message: self.fill(link.as_ref().map(|l| l.short.clone()), meta, now), link }),
+            Err((_, e)) => {
+                warn!("号码 {} 预渲染时生成短链失败，取号时重试: {}", number, e);
+                None
+            }
+        }
+    }
+
+    // {link} 的短链，模板中没有 {link} 或未配置短链服务时为 None；生成失败时返回长链接和原因
+    async fn link(
+        &self,
+        number: &str,
+        meta: Option<&HashMap<String, String>>,
+        now: &Zoned,
+    ) -> Result<Option<ShortLink>, (String, String)> {
+        let Some(shortener) = self.shortener.as_ref().filter(|_| self.template.contains("{link}")) else {
+            return Ok(None);
+        };
+        let long_url = template::render(shortener.target(), |key| match key {
+            "number" => Some(number.to_string()),
+            _ => meta.and_then(|m| m.get(key).cloned()).or_else(|| self.lookup(key, now)),
+        });
+        shortener.shorten_for(number, &long_url).await.map(Some).map_err(|e| (long_url, e))
+    }
+
+    fn fill(&self, link: Option<String>, meta: Option<&HashMap<String, String>>, now: &Zoned) -> String {
+        template::render(&self.template, |key| match key {
+            "link" => link.clone(),
+            _ => meta.and_then(|m| m.get(key).cloned()).or_else(|| self.lookup(key, now)),
+        })
+    }
+
+    // 替换模板，其余渲染上下文不变
+    pub fn with_template(&self, template: String) -> Renderer {
+        Renderer {
+            template,
+            vars: self.vars.clone(),
+            timezone: self.timezone.clone(),
+            locale: self.locale,
+            shortener: self.shortener.clone(),
+        }
+    }
+
+    // 不依赖号码的占位符，渲染结果对所有号码相同
+    pub fn render_static(&self, now: &Zoned) -> String {
+        template::render(&self.template, |key| self.lookup(key, now))
+    }
+
+    fn lookup(&self, key: &str, now: &Zoned) -> Option<String> {
+        self.vars.get(key).cloned().or_else(|| datetime::lookup(key, now, self.locale))
+    }
+}
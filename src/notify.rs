@@ -0,0 +1,220 @@
+use crate::{http_client, smtp};
+use jiff::Timestamp;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+
+// 推送的事件
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    #[serde(rename = "event")]
+    pub name: String,
+    pub timestamp: Timestamp,
+    pub data: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+impl Event {
+    pub fn new(name: &str, data: Value) -> Event {
+        Event { name: name.to_string(), timestamp: crate::clock::now(), data, trace_id: crate::trace::current() }
+    }
+
+    // 聊天和邮件中显示的一行文字
+    pub fn text(&self) -> String {
+        format!("[ios_sms_rpa] {} {}", self.name, self.data)
+    }
+}
+
+// 通知通道：后台发送事件，失败只记录日志，不影响主流程
+pub trait Notifier: Send + Sync {
+    fn send(&self, event: &Event);
+}
+
+// 通知配置：channels 定义通道，routes 按事件选择通道，新增通道类型只需实现 Notifier
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NotifyConfig {
+    #[serde(default)]
+    pub channels: BTreeMap<String, ChannelConfig>,
+    // 事件 => 通道名，"*" 表示全部通道，[] 表示不推送
+    #[serde(default)]
+    pub routes: HashMap<String, Vec<String>>,
+    // 未在 routes 中列出的事件推送到的通道，默认不推送
+    #[serde(default)]
+    pub default: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChannelConfig {
+    Webhook(WebhookConfig),
+    Slack(SlackConfig),
+    Telegram(TelegramConfig),
+    Email(smtp::EmailConfig),
+}
+
+// 事件以 JSON POST 到 url，推送哪些事件由 routes 决定
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+// Slack 兼容的 incoming webhook（Mattermost、Rocket.Chat 等），POST {"text": ...}
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlackConfig {
+    pub url: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+// Telegram Bot API 的 sendMessage，api_url 默认为官方地址，也可指向自建 Bot API 服务
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramConfig {
+    #[serde(default = "default_telegram_api_url")]
+    pub api_url: String,
+    pub bot_token: String,
+    pub chat_id: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+pub(crate) fn default_timeout_secs() -> u64 {
+    5
+}
+
+fn default_telegram_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+// 按事件分发到各通道
+pub struct Dispatcher {
+    channels: BTreeMap<String, Arc<dyn Notifier>>,
+    routes: HashMap<String, Vec<String>>,
+    default: Vec<String>,
+}
+
+impl Dispatcher {
+    pub fn new(config: NotifyConfig) -> Dispatcher {
+        let mut channels: BTreeMap<String, Arc<dyn Notifier>> = BTreeMap::new();
+        for (name, channel) in config.channels {
+            let notifier: Arc<dyn Notifier> = match channel {
+                ChannelConfig::Webhook(c) => Arc::new(Webhook::new(c)),
+                ChannelConfig::Slack(c) => Arc::new(Slack::new(c)),
+                ChannelConfig::Telegram(c) => Arc::new(Telegram::new(c)),
+                ChannelConfig::Email(c) => Arc::new(smtp::Email::new(c)),
+            };
+            channels.insert(name, notifier);
+        }
+        for (event, targets) in config.routes.iter().map(|(e, t)| (e.as_str(), t)).chain([("default", &config.default)]) {
+            if let Some(unknown) = targets.iter().find(|t| *t != "*" && !channels.contains_key(*t)) {
+                panic!("Unknown notify channel {:?} in route for {}", unknown, event);
+            }
+        }
+        info!("通知通道 => {:?}，路由 {:?}，其余事件 => {:?}", channels.keys().collect::<Vec<_>>(), config.routes, config.default);
+        Dispatcher { channels, routes: config.routes, default: config.default }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    pub fn dispatch(&self, name: &str, data: Value) {
+        let targets = self.routes.get(name).unwrap_or(&self.default);
+        let event = Event::new(name, data);
+        for (channel, notifier) in &self.channels {
+            if targets.iter().any(|t| t == "*" || t == channel) {
+                notifier.send(&event);
+            }
+        }
+    }
+}
+
+// 后台 POST JSON，name 用于日志，headers 为 Content-Type 以外的请求头
+pub(crate) fn post_json(
+    name: &'static str,
+    url: String,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+    timeout: Duration,
+    event: &Event,
+) {
+    let event = event.name.clone();
+    tokio::spawn(async move {
+        let headers: Vec<(&str, &str)> =
+            std::iter::once(("Content-Type", "application/json")).chain(headers.iter().map(|(k, v)| (*k, v.as_str()))).collect();
+        match http_client::post(&url, &headers, &body, timeout).await {
+            Ok(resp) if resp.is_success() => debug!("{} 事件 {} 已推送", name, event),
+            Ok(resp) => warn!("{} 事件 {} 推送失败: HTTP {}", name, event, resp.status),
+            Err(e) => warn!("{} 事件 {} 推送失败: {}", name, event, e),
+        }
+    });
+}
+
+pub struct Webhook {
+    config: WebhookConfig,
+}
+
+impl Webhook {
+    fn new(config: WebhookConfig) -> Webhook {
+        if let Err(e) = http_client::check_url(&config.url) {
+            panic!("Invalid webhook url: {}", e);
+        }
+        Webhook { config }
+    }
+}
+
+impl Notifier for Webhook {
+    fn send(&self, event: &Event) {
+        let body = serde_json::to_string(event).unwrap_or_default();
+        let headers = event.trace_id.iter().map(|id| ("X-Trace-Id", id.clone())).collect();
+        post_json("Webhook", self.config.url.clone(), headers, body, Duration::from_secs(self.config.timeout_secs), event);
+    }
+}
+
+pub struct Slack {
+    config: SlackConfig,
+}
+
+impl Slack {
+    fn new(config: SlackConfig) -> Slack {
+        if let Err(e) = http_client::check_url(&config.url) {
+            panic!("Invalid slack url: {}", e);
+        }
+        Slack { config }
+    }
+}
+
+impl Notifier for Slack {
+    fn send(&self, event: &Event) {
+        let body = serde_json::json!({ "text": event.text() }).to_string();
+        post_json("Slack", self.config.url.clone(), Vec::new(), body, Duration::from_secs(self.config.timeout_secs), event);
+    }
+}
+
+pub struct Telegram {
+    config: TelegramConfig,
+}
+
+impl Telegram {
+    fn new(config: TelegramConfig) -> Telegram {
+        if let Err(e) = http_client::check_url(&config.api_url) {
+            panic!("Invalid telegram api_url: {}", e);
+        }
+        Telegram { config }
+    }
+}
+
+impl Notifier for Telegram {
+    fn send(&self, event: &Event) {
+        let url = format!("{}/bot{}/sendMessage", self.config.api_url.trim_end_matches('/'), self.config.bot_token);
+        let body = serde_json::json!({ "chat_id": self.config.chat_id, "text": event.text() }).to_string();
+        post_json("Telegram", url, Vec::new(), body, Duration::from_secs(self.config.timeout_secs), event);
+    }
+}
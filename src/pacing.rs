@@ -0,0 +1,63 @@
+use serde::Deserialize;
+use std::time::Instant;
+
+// 令牌桶配置：所有设备合计的下发速率
+#[derive(Debug, Clone, Deserialize)]
+pub struct PacingConfig {
+    // 每分钟补充的号码数
+    pub rate_per_minute: f64,
+    // 桶容量，即允许的瞬时突发量，默认等于每分钟速率
+    pub burst: Option<f64>,
+}
+
+pub struct TokenBucket {
+    rate_per_sec: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(config: &PacingConfig) -> TokenBucket {
+        if config.rate_per_minute <= 0.0 {
+            panic!("pacing.rate_per_minute must be positive");
+        }
+        let capacity = config.burst.unwrap_or(config.rate_per_minute).max(1.0);
+        TokenBucket {
+            rate_per_sec: config.rate_per_minute / 60.0,
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_sec).min(self.capacity);
+        self.last = now;
+    }
+
+    // 申请最多 n 个令牌，返回实际获得的数量
+    pub fn take(&mut self, n: usize) -> usize {
+        self.refill();
+        let granted = (self.tokens.floor() as usize).min(n);
+        self.tokens -= granted as f64;
+        granted
+    }
+
+    // 归还未使用的令牌
+    pub fn refund(&mut self, n: usize) {
+        self.tokens = (self.tokens + n as f64).min(self.capacity);
+    }
+
+    // 距离下一个令牌可用的秒数
+    pub fn retry_after_secs(&mut self) -> u64 {
+        self.refill();
+        if self.tokens >= 1.0 {
+            0
+        } else {
+            ((1.0 - self.tokens) / self.rate_per_sec).ceil() as u64
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::message::{Rendered, Renderer};
+use jiff::Zoned;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::{BuildHasher, BuildHasherDefault, DefaultHasher},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+// 预渲染：后台为接下来的若干批号码提前渲染消息（个性化变量、短链），/fetch 命中时直接使用，
+// 启用外部短链服务时避免取号请求等待逐个生成短链
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrerenderConfig {
+    // 提前准备的批次数，按 default_fetch_count 计算号码数
+    #[serde(default = "default_batches")]
+    pub batches: usize,
+    // 检查游标位置并补足预渲染的间隔（毫秒）
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+}
+
+fn default_batches() -> usize {
+    3
+}
+
+fn default_interval_ms() -> u64 {
+    200
+}
+
+// 待渲染的号码、所用的消息和号码附加信息
+pub type Upcoming = (String, Arc<Renderer>, Option<HashMap<String, String>>);
+
+// 预渲染的消息，渲染所用的消息模板、号码附加信息或日期占位符的取值变化后失效
+struct Entry {
+    renderer: Arc<Renderer>,
+    meta: u64,
+    stamp: String,
+    rendered: Rendered,
+}
+
+pub struct Prerender {
+    pub config: PrerenderConfig,
+    // 号码 => 预渲染的消息
+    entries: Mutex<HashMap<String, Entry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Prerender {
+    pub fn new(config: PrerenderConfig) -> Prerender {
+        Prerender { config, entries: Mutex::new(HashMap::new()), hits: AtomicU64::new(0), misses: AtomicU64::new(0) }
+    }
+
+    // 取出号码的预渲染消息，没有或已失效时返回 None，由调用方现场渲染
+    pub fn take(
+        &self,
+        renderer: &Arc<Renderer>,
+        number: &str,
+        meta: Option<&HashMap<String, String>>,
+        now: &Zoned,
+    ) -> Option<Rendered> {
+        let entry = self.entries.lock().unwrap().remove(number);
+        let rendered = entry.filter(|e| e.matches(renderer, meta, now)).map(|e| e.rendered);
+        let counter = if rendered.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        rendered
+    }
+
+    // 接下来的号码中还需要渲染的，同时丢弃已不在其中的号码
+    pub fn pending(&self, upcoming: Vec<Upcoming>, now: &Zoned) -> Vec<Upcoming> {
+        let mut entries = self.entries.lock().unwrap();
+        let keep: HashSet<&str> = upcoming.iter().map(|(number, _, _)| number.as_str()).collect();
+        entries.retain(|number, _| keep.contains(number.as_str()));
+        upcoming
+            .into_iter()
+            .filter(|(number, renderer, meta)| !entries.get(number).is_some_and(|e| e.matches(renderer, meta.as_ref(), now)))
+            .collect()
+    }
+
+    pub fn insert(
+        &self,
+        number: String,
+        renderer: Arc<Renderer>,
+        meta: Option<&HashMap<String, String>>,
+        now: &Zoned,
+        rendered: Rendered,
+    ) {
+        let entry = Entry { meta: hash_meta(meta), stamp: stamp(&renderer, now), renderer, rendered };
+        self.entries.lock().unwrap().insert(number, entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    // (命中次数, 未命中次数)
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+}
+
+impl Entry {
+    fn matches(&self, renderer: &Arc<Renderer>, meta: Option<&HashMap<String, String>>, now: &Zoned) -> bool {
+        Arc::ptr_eq(&self.renderer, renderer) && self.meta == hash_meta(meta) && self.stamp == stamp(renderer, now)
+    }
+}
+
+// 日期占位符的取值范围：模板或短链目标中有 {time} 时按分钟，否则按日期
+fn stamp(renderer: &Renderer, now: &Zoned) -> String {
+    let target = renderer.shortener.as_ref().map_or("", |s| s.target());
+    if renderer.template.contains("{time}") || target.contains("{time}") {
+        now.strftime("%Y-%m-%d %H:%M").to_string()
+    } else {
+        now.date().to_string()
+    }
+}
+
+fn hash_meta(meta: Option<&HashMap<String, String>>) -> u64 {
+    let sorted: Option<BTreeMap<_, _>> = meta.map(|m| m.iter().collect());
+    BuildHasherDefault::<DefaultHasher>::default().hash_one(sorted)
+}
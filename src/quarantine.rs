@@ -0,0 +1,111 @@
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+// 隔离配置：同一号码在多台不同设备上都发送失败时不再重发
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuarantineConfig {
+    // 失败设备数达到该值即隔离
+    #[serde(default = "default_distinct_devices")]
+    pub distinct_devices: usize,
+}
+
+fn default_distinct_devices() -> usize {
+    3
+}
+
+// 号码的失败记录
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailureRecord {
+    pub failures: usize,
+    pub devices: BTreeSet<String>,
+    pub reasons: Vec<String>,
+    pub last_failed_at: Option<Timestamp>,
+}
+
+// 被隔离号码的详情
+#[derive(Debug, Clone, Serialize)]
+pub struct QuarantinedNumber {
+    pub number: String,
+    pub quarantined_at: Timestamp,
+    #[serde(flatten)]
+    pub record: FailureRecord,
+}
+
+pub struct Quarantine {
+    threshold: Option<usize>,
+    failures: HashMap<String, FailureRecord>,
+    quarantined: HashMap<String, Timestamp>,
+}
+
+impl Quarantine {
+    pub fn new(config: Option<&QuarantineConfig>) -> Quarantine {
+        Quarantine {
+            threshold: config.map(|c| c.distinct_devices.max(1)),
+            failures: HashMap::new(),
+            quarantined: HashMap::new(),
+        }
+    }
+
+    // 记录一次失败，返回号码是否因此被隔离
+    pub fn record_failure(&mut self, number: &str, device: &str, reason: Option<&str>, now: Timestamp) -> bool {
+        let record = self.failures.entry(number.to_string()).or_default();
+        record.failures += 1;
+        record.devices.insert(device.to_string());
+        if let Some(reason) = reason {
+            record.reasons.push(reason.to_string());
+        }
+        record.last_failed_at = Some(now);
+
+        match self.threshold {
+            Some(threshold) if record.devices.len() >= threshold => {
+                self.quarantined.insert(number.to_string(), now);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_quarantined(&self, number: &str) -> bool {
+        self.quarantined.contains_key(number)
+    }
+
+    pub fn len(&self) -> usize {
+        self.quarantined.len()
+    }
+
+    // 按隔离时间排序列出所有被隔离的号码
+    pub fn list(&self) -> Vec<QuarantinedNumber> {
+        let mut list: Vec<QuarantinedNumber> = self
+            .quarantined
+            .iter()
+            .map(|(number, at)| QuarantinedNumber {
+                number: number.clone(),
+                quarantined_at: *at,
+                record: self.failures.get(number).cloned().unwrap_or_default(),
+            })
+            .collect();
+        list.sort_by(|a, b| a.quarantined_at.cmp(&b.quarantined_at).then_with(|| a.number.cmp(&b.number)));
+        list
+    }
+
+    pub fn failures(&self) -> &HashMap<String, FailureRecord> {
+        &self.failures
+    }
+
+    pub fn quarantined(&self) -> &HashMap<String, Timestamp> {
+        &self.quarantined
+    }
+
+    // 用保存的失败记录和隔离名单替换当前的
+    pub fn restore(&mut self, failures: HashMap<String, FailureRecord>, quarantined: HashMap<String, Timestamp>) {
+        self.failures = failures;
+        self.quarantined = quarantined;
+    }
+
+    // 解除隔离并清空失败记录，返回号码是否原本处于隔离中
+    pub fn release(&mut self, number: &str) -> bool {
+        self.failures.remove(number);
+        self.quarantined.remove(number).is_some()
+    }
+}
@@ -0,0 +1,188 @@
+use crate::datetime;
+use jiff::{
+    civil::{Date, Time},
+    tz::TimeZone,
+    Timestamp,
+};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+// 地区配置：按号码前缀识别地区，使用地区所在时区判断发送时段
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegionConfig {
+    pub name: String,
+    pub prefixes: Vec<String>,
+    // 地区时区，默认使用全局 timezone
+    pub timezone: Option<String>,
+    // 本地发送时段，如 "09:00-21:00"，默认使用全局 send_window
+    pub send_window: Option<String>,
+}
+
+// 节假日配置：节假日当天（地区本地日期）暂停或限流发送
+#[derive(Debug, Clone, Deserialize)]
+pub struct HolidayConfig {
+    // pause / throttle
+    #[serde(default = "default_holiday_mode")]
+    pub mode: String,
+    // throttle 模式下每个地区每小时最多下发的号码数
+    #[serde(default)]
+    pub throttle_per_hour: usize,
+    // 所有地区共用的节假日，如 "2024-10-01"
+    #[serde(default)]
+    pub dates: Vec<String>,
+    // 按地区名配置的节假日，默认地区名为 "default"
+    #[serde(default)]
+    pub regions: HashMap<String, Vec<String>>,
+}
+
+fn default_holiday_mode() -> String {
+    "pause".to_string()
+}
+
+// 未配置 [holidays] 时与空表相同
+impl Default for HolidayConfig {
+    fn default() -> HolidayConfig {
+        HolidayConfig { mode: default_holiday_mode(), throttle_per_hour: 0, dates: Vec::new(), regions: HashMap::new() }
+    }
+}
+
+pub struct Region {
+    pub name: String,
+    prefixes: Vec<String>,
+    timezone: TimeZone,
+    window: Option<SendWindow>,
+    holidays: HashSet<Date>,
+}
+
+// 地区当前的可发送状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Availability {
+    Open,
+    Closed,
+    // 节假日限流，每小时最多下发的号码数
+    Throttled(usize),
+}
+
+// 本地发送时段，支持跨零点（如 "22:00-02:00"）
+#[derive(Debug, Clone, Copy)]
+struct SendWindow {
+    start: Time,
+    end: Time,
+}
+
+impl SendWindow {
+    fn parse(s: &str) -> SendWindow {
+        let parse_time = |t: &str| {
+            t.trim()
+                .parse::<Time>()
+                .unwrap_or_else(|e| panic!("Invalid send window '{}': {}", s, e))
+        };
+        let (start, end) = s
+            .split_once('-')
+            .unwrap_or_else(|| panic!("Invalid send window '{}', expected HH:MM-HH:MM", s));
+        SendWindow { start: parse_time(start), end: parse_time(end) }
+    }
+
+    fn contains(&self, time: Time) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+// 全部地区，最后一个为未匹配任何前缀时使用的默认地区
+pub struct Regions {
+    regions: Vec<Region>,
+    holiday_throttle: Option<usize>,
+}
+
+impl Regions {
+    pub fn new(
+        configs: &[RegionConfig],
+        timezone: Option<&str>,
+        send_window: Option<&str>,
+        holidays: &HolidayConfig,
+    ) -> Regions {
+        let default_tz = datetime::load_timezone(timezone);
+        let default_window = send_window.map(SendWindow::parse);
+        let holiday_throttle = match holidays.mode.as_str() {
+            "pause" => None,
+            "throttle" => Some(holidays.throttle_per_hour),
+            other => panic!("Unknown holiday mode '{}', expected pause or throttle", other),
+        };
+        let holidays_for = |name: &str| -> HashSet<Date> {
+            holidays
+                .dates
+                .iter()
+                .chain(holidays.regions.get(name).into_iter().flatten())
+                .map(|d| d.parse().unwrap_or_else(|e| panic!("Invalid holiday date '{}': {}", d, e)))
+                .collect()
+        };
+
+        let mut regions: Vec<Region> = configs
+            .iter()
+            .map(|c| Region {
+                name: c.name.clone(),
+                prefixes: c.prefixes.clone(),
+                timezone: c
+                    .timezone
+                    .as_deref()
+                    .map(|tz| datetime::load_timezone(Some(tz)))
+                    .unwrap_or_else(|| default_tz.clone()),
+                window: c.send_window.as_deref().map(SendWindow::parse).or(default_window),
+                holidays: holidays_for(&c.name),
+            })
+            .collect();
+        regions.push(Region {
+            name: "default".to_string(),
+            prefixes: Vec::new(),
+            timezone: default_tz,
+            window: default_window,
+            holidays: holidays_for("default"),
+        });
+        Regions { regions, holiday_throttle }
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn get(&self, idx: usize) -> &Region {
+        &self.regions[idx]
+    }
+
+    // 按最长前缀匹配号码所属地区
+    pub fn classify(&self, number: &str) -> usize {
+        let number = number.trim_start_matches('+');
+        self.regions
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, r)| r.prefixes.iter().map(move |p| (idx, p.trim_start_matches('+'))))
+            .filter(|(_, p)| number.starts_with(p))
+            .max_by_key(|(_, p)| p.len())
+            .map(|(idx, _)| idx)
+            .unwrap_or(self.regions.len() - 1)
+    }
+
+    // 地区当前的可发送状态：不在本地发送时段则关闭，节假日按配置暂停或限流
+    pub fn availability(&self, idx: usize, now: Timestamp) -> Availability {
+        let region = &self.regions[idx];
+        let local = now.to_zoned(region.timezone.clone());
+        if region.window.is_some_and(|window| !window.contains(local.time())) {
+            return Availability::Closed;
+        }
+        if region.holidays.contains(&local.date()) {
+            return match self.holiday_throttle {
+                Some(per_hour) => Availability::Throttled(per_hour),
+                None => Availability::Closed,
+            };
+        }
+        Availability::Open
+    }
+
+    pub fn any_open(&self, now: Timestamp) -> bool {
+        (0..self.regions.len()).any(|idx| self.availability(idx, now) != Availability::Closed)
+    }
+}
@@ -0,0 +1,186 @@
+use jiff::Timestamp;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use crate::append_log::AppendLog;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+};
+
+// 每个会话在内存中保留的回复条数上限
+const MAX_REPLIES: usize = 50;
+
+// 回复处理：设备上传收到的回复，按关键词规则自动打标签、退订和通知
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RepliesConfig {
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    // 回复记录文件（每行一条 JSON），重启后会话和标签仍可查询，不配置则只保存在内存中
+    pub file: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuleConfig {
+    pub name: String,
+    // 回复包含任一关键词即命中，不区分大小写
+    pub keywords: Vec<String>,
+    // 命中后给会话打的标签，默认为规则名
+    pub tag: Option<String>,
+    // 命中后退订：号码加入黑名单，不再下发
+    #[serde(default = "default_opt_out")]
+    pub opt_out: bool,
+    // 命中后推送 reply_alert 事件通知运营人员
+    #[serde(default)]
+    pub alert: bool,
+}
+
+fn default_opt_out() -> bool {
+    true
+}
+
+// 一条回复及命中规则的处理结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reply {
+    pub number: String,
+    pub text: String,
+    pub at: Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    // 命中的规则
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub opt_out: bool,
+    // 命中的规则中有需要通知运营人员的
+    #[serde(default)]
+    pub alert: bool,
+}
+
+// 与某个号码的会话
+#[derive(Debug, Clone, Serialize)]
+pub struct Conversation {
+    pub number: String,
+    pub tags: BTreeSet<String>,
+    pub opted_out: bool,
+    pub last_reply_at: Timestamp,
+    pub replies: Vec<Reply>,
+}
+
+pub struct Replies {
+    rules: Vec<RuleConfig>,
+    conversations: BTreeMap<String, Conversation>,
+    file: Option<AppendLog>,
+}
+
+impl Replies {
+    // 从回复记录文件恢复会话
+    pub fn load(config: &RepliesConfig) -> Replies {
+        let mut rules = config.rules.clone();
+        for rule in &mut rules {
+            rule.keywords = rule.keywords.iter().map(|k| k.trim().to_lowercase()).filter(|k| !k.is_empty()).collect();
+            if rule.keywords.is_empty() {
+                panic!("Reply rule '{}' has no keywords", rule.name);
+            }
+        }
+        let mut replies = Replies { rules, conversations: BTreeMap::new(), file: config.file.as_deref().map(AppendLog::new) };
+        if let Some(content) = config.file.as_deref().and_then(|path| fs::read_to_string(path).ok()) {
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str::<Reply>(line) {
+                    Ok(reply) => replies.add(reply),
+                    Err(e) => warn!("忽略无法解析的回复记录: {}", e),
+                }
+            }
+        }
+        replies
+    }
+
+    // 记录一条回复，按规则打标签并判断是否退订
+    pub fn ingest(&mut self, number: &str, text: &str, device: Option<String>, at: Timestamp) -> Reply {
+        let content = text.to_lowercase();
+        let matched: Vec<&RuleConfig> =
+            self.rules.iter().filter(|rule| rule.keywords.iter().any(|k| contains_word(&content, k))).collect();
+        let reply = Reply {
+            number: number.to_string(),
+            text: text.to_string(),
+            at,
+            device,
+            rules: matched.iter().map(|rule| rule.name.clone()).collect(),
+            tags: matched.iter().map(|rule| rule.tag.clone().unwrap_or_else(|| rule.name.clone())).collect(),
+            opt_out: matched.iter().any(|rule| rule.opt_out),
+            alert: matched.iter().any(|rule| rule.alert),
+        };
+        self.append(&reply);
+        self.add(reply.clone());
+        reply
+    }
+
+    fn add(&mut self, reply: Reply) {
+        let conversation = self.conversations.entry(reply.number.clone()).or_insert_with(|| Conversation {
+            number: reply.number.clone(),
+            tags: BTreeSet::new(),
+            opted_out: false,
+            last_reply_at: reply.at,
+            replies: Vec::new(),
+        });
+        conversation.tags.extend(reply.tags.iter().cloned());
+        conversation.opted_out |= reply.opt_out;
+        conversation.last_reply_at = conversation.last_reply_at.max(reply.at);
+        conversation.replies.push(reply);
+        if conversation.replies.len() > MAX_REPLIES {
+            conversation.replies.remove(0);
+        }
+    }
+
+    // 已退订的号码，启动时加入黑名单
+    pub fn opted_out(&self) -> impl Iterator<Item = &str> {
+        self.conversations.values().filter(|c| c.opted_out).map(|c| c.number.as_str())
+    }
+
+    // 最近有回复的会话在前
+    pub fn list(&self) -> Vec<&Conversation> {
+        let mut conversations: Vec<&Conversation> = self.conversations.values().collect();
+        conversations.sort_by_key(|c| std::cmp::Reverse(c.last_reply_at));
+        conversations
+    }
+
+    fn append(&mut self, reply: &Reply) {
+        let Some(file) = &mut self.file else { return };
+        file.push(serde_json::to_string(reply).expect("序列化回复记录失败"));
+    }
+}
+
+// 按词匹配关键词：关键词以字母或数字开头（结尾）时，前（后）一个字符不能是字母或数字，"td" 不匹配 "ltd"；
+// 中文等没有词边界的关键词按包含匹配
+fn contains_word(content: &str, keyword: &str) -> bool {
+    let word = |c: char| c.is_alphanumeric() && c.is_ascii();
+    let check_start = keyword.chars().next().is_some_and(word);
+    let check_end = keyword.chars().next_back().is_some_and(word);
+    content.match_indices(keyword).any(|(i, _)| {
+        (!check_start || !content[..i].chars().next_back().is_some_and(word))
+            && (!check_end || !content[i + keyword.len()..].chars().next().is_some_and(word))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::contains_word;
+
+    #[test]
+    fn matches_whole_words_only() {
+        assert!(contains_word("please stop", "stop"));
+        assert!(contains_word("stop!", "stop"));
+        assert!(contains_word("td", "td"));
+        assert!(!contains_word("nonstop deals", "stop"));
+        assert!(!contains_word("acme ltd", "td"));
+        assert!(!contains_word("stopped", "stop"));
+    }
+
+    #[test]
+    fn matches_cjk_keywords_as_substrings() {
+        assert!(contains_word("请退订", "退订"));
+        assert!(contains_word("退订td", "退订"));
+        assert!(contains_word("回复td退订", "td"));
+    }
+}
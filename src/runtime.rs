@@ -0,0 +1,60 @@
+use log::info;
+use serde::Deserialize;
+
+// Tokio 运行时参数
+#[derive(Debug, Clone, Deserialize)]
+pub struct RuntimeConfig {
+    // 工作线程数，0 表示与 CPU 核数相同
+    #[serde(default)]
+    pub worker_threads: usize,
+    // 阻塞线程池上限
+    #[serde(default = "default_max_blocking_threads")]
+    pub max_blocking_threads: usize,
+    // 启动时加载号码、同步导入时解析请求体等大文件处理放到阻塞线程池，避免占住工作线程
+    #[serde(default = "enabled")]
+    pub blocking_io: bool,
+}
+
+fn default_max_blocking_threads() -> usize {
+    512
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> RuntimeConfig {
+        RuntimeConfig { worker_threads: 0, max_blocking_threads: default_max_blocking_threads(), blocking_io: true }
+    }
+}
+
+impl RuntimeConfig {
+    pub fn build(&self) -> tokio::runtime::Runtime {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all().max_blocking_threads(self.max_blocking_threads.max(1));
+        if self.worker_threads > 0 {
+            builder.worker_threads(self.worker_threads);
+        }
+        info!(
+            "运行时 => 工作线程 {}，阻塞线程上限 {}，大文件读写{}使用阻塞线程池",
+            if self.worker_threads > 0 { self.worker_threads.to_string() } else { "自动".to_string() },
+            self.max_blocking_threads,
+            if self.blocking_io { "" } else { "不" }
+        );
+        builder.build().expect("Failed to build tokio runtime")
+    }
+
+    // 按配置在阻塞线程池或当前线程上执行文件处理
+    pub async fn run_io<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.blocking_io {
+            crate::clock::spawn_blocking(f).await.expect("blocking task panicked")
+        } else {
+            f()
+        }
+    }
+}
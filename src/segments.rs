@@ -0,0 +1,32 @@
+// GSM 03.38 基本字符集
+const GSM_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+
+// GSM 扩展字符，每个占 2 个字符位
+const GSM_EXTENDED: &str = "^{}\\[~]|€\x0c";
+
+// 计算短信拆分的条数：GSM-7 单条 160 / 拼接 153，UCS-2 单条 70 / 拼接 67
+pub fn count(text: &str) -> usize {
+    if text.is_empty() {
+        return 1;
+    }
+
+    let gsm_len = text.chars().try_fold(0usize, |len, c| {
+        if GSM_BASIC.contains(c) {
+            Some(len + 1)
+        } else if GSM_EXTENDED.contains(c) {
+            Some(len + 2)
+        } else {
+            None
+        }
+    });
+
+    match gsm_len {
+        Some(len) if len <= 160 => 1,
+        Some(len) => len.div_ceil(153),
+        None => {
+            let len = text.encode_utf16().count();
+            if len <= 70 { 1 } else { len.div_ceil(67) }
+        }
+    }
+}
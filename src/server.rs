@@ -0,0 +1,4498 @@
+use axum::{
+    extract::{DefaultBodyLimit, Path, Query, Request},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{Arc, Mutex},
+};
+use axum::serve;
+use futures_util::{stream, StreamExt};
+use tokio::io::AsyncWriteExt;
+use log::{info, debug, warn};
+use crate::{
+    backpressure, campaign, changelog, clock, cron, datetime, dedup, device, download, emoji, faults, features,
+    handover, http_client, import, jobs, lease, message, notify, pacing, prerender, quarantine, region, replies,
+    runtime, segments, shed, shortener, source, store, strings, summary, trace, usage, verify, watch,
+};
+use crate::region::Availability;
+
+
+#[derive(Debug, Deserialize)]
+struct Config {
+    port: u16,
+    // 设备接口的监听地址，默认 0.0.0.0:{port}
+    listen: Option<String>,
+    // 管理接口（状态、导入导出、指标等）的独立监听地址，如 "127.0.0.1:3001"，不配置时与设备接口共用端口
+    admin_listen: Option<String>,
+    // 以 SO_REUSEPORT 绑定，新版本进程可在旧进程运行时绑定同一端口
+    #[serde(default)]
+    reuse_port: bool,
+    // 记录进程号的文件，启动时接管其中记录的旧进程（发送 SIGTERM 并等待其保存进度后退出）
+    pid_file: Option<String>,
+    // 收到 SIGTERM 后等待进行中的请求完成的最长秒数
+    #[serde(default = "default_shutdown_timeout_secs")]
+    shutdown_timeout_secs: u64,
+    default_fetch_count: usize,
+    test_number: String,
+    // 模板变量，替换消息中的 {name} 占位符
+    #[serde(default)]
+    vars: HashMap<String, String>,
+    // 日期占位符使用的时区（如 "Asia/Shanghai"），默认系统时区
+    timezone: Option<String>,
+    // 日期占位符的语言：zh / en
+    #[serde(default = "default_locale")]
+    locale: String,
+    // 自定义 emoji 短代码，msg.txt 中的 :name: 在加载时展开
+    #[serde(default)]
+    emoji: HashMap<String, String>,
+    // 单条消息允许的最大短信条数，渲染后超出的号码暂扣不发；[[campaigns]] 可各自覆盖
+    max_segments: Option<usize>,
+    // 短链服务，为消息中的 {link} 按号码生成短链
+    shortener: Option<shortener::ShortenerConfig>,
+    // 默认本地发送时段，如 "09:00-21:00"，不配置则不限制
+    send_window: Option<String>,
+    // 按号码前缀划分的地区，各自按本地时间判断发送时段
+    #[serde(default)]
+    regions: Vec<region::RegionConfig>,
+    // 节假日日历
+    #[serde(default)]
+    holidays: region::HolidayConfig,
+    // 令牌桶限速，所有设备合计
+    pacing: Option<pacing::PacingConfig>,
+    // 按设备最近的失败率缩小批次
+    backpressure: Option<backpressure::BackpressureConfig>,
+    // 批次租约，配置后批次需 /ack 确认，超时未确认的号码回收重发
+    leases: Option<lease::LeaseConfig>,
+    // 重发号码与新号码的取号顺序
+    #[serde(default)]
+    retry: lease::RetryConfig,
+    // 号码隔离：在多台设备上反复失败的号码不再重发
+    quarantine: Option<quarantine::QuarantineConfig>,
+    // 发送结果抽查，设备需为抽中的号码上传凭证后才能取下一批
+    verification: Option<verify::VerifyConfig>,
+    // 黑名单文件，每行一个号码，加载和导入时排除
+    blacklist_file: Option<String>,
+    // /import 请求体大小上限（MB）
+    #[serde(default = "default_import_max_mb")]
+    import_max_mb: usize,
+    // 后台任务记录文件，重启后仍可查询历史任务，不配置则只保存在内存中
+    jobs_file: Option<String>,
+    // 号码池变更记录文件（每行一条 JSON），重启后 GET /changelog 仍可查询，不配置则只保存在内存中
+    changelog_file: Option<String>,
+    // 导出、报告、归档文件的目录
+    #[serde(default = "default_export_dir")]
+    export_dir: String,
+    // 导出文件的限时下载链接，不配置则不提供 /download
+    downloads: Option<download::DownloadConfig>,
+    // 维护模式：设备接口返回 503，管理和状态接口照常可用
+    #[serde(default)]
+    maintenance: bool,
+    // 测试模式：开放 /test/faults 故障注入接口，不要在正式活动中开启
+    #[serde(default)]
+    test_mode: bool,
+    // 功能开关
+    #[serde(default)]
+    features: features::FeaturesConfig,
+    // 设备注册、每日配额和分组
+    #[serde(default)]
+    devices: device::DeviceConfig,
+    // 提前渲染接下来几批号码的消息，不配置则取号时逐个渲染
+    prerender: Option<prerender::PrerenderConfig>,
+    // 多租户部署的 API 密钥，按租户统计用量，不配置则不统计
+    tenants: Option<usage::TenantsConfig>,
+    // 事件推送：通知通道和按事件的路由
+    #[serde(default)]
+    notify: notify::NotifyConfig,
+    // 设备上传的回复及关键词规则（自动退订、打标签、通知）
+    #[serde(default)]
+    replies: replies::RepliesConfig,
+    // 默认活动按设备语言选择的消息文件，如 { en = "msg.en.txt" }
+    #[serde(default)]
+    message_variants: HashMap<String, String>,
+    // 返回给设备的提示文字，如 no_more_numbers = "号码已发完"
+    #[serde(default)]
+    strings: strings::Strings,
+    // 默认活动预加载的备选消息，如 { soft = "msg_soft.txt" }，/fetch 中用 message_id 指定
+    #[serde(default)]
+    messages: HashMap<String, String>,
+    // 允许在 /fetch 中指定 message_id 的请求头 X-Override-Token，不配置则不允许指定
+    message_override_token: Option<String>,
+    // 默认活动的截止时间，之后不再下发，未下发的号码自动导出
+    end_at: Option<jiff::Timestamp>,
+    // 远程号码源，按计划拉取并追加到号码池，每次拉取记录为后台任务
+    #[serde(default)]
+    sources: Vec<source::SourceConfig>,
+    // 活动：各自的消息文件和变量覆盖，号码通过 numbers_file 或导入时的 campaign 字段归属
+    #[serde(default)]
+    campaigns: Vec<campaign::CampaignConfig>,
+    // 按权重轮流为设备分配各活动的批次，不配置则一个批次可能混合多个活动的号码
+    scheduling: Option<campaign::SchedulingConfig>,
+    // 检查 msg.txt 是否修改的间隔（秒），修改后无需重启即生效，0 表示不检查
+    #[serde(default = "default_message_watch_secs")]
+    message_watch_secs: u64,
+    // /fetch?priority=high 在优先池取完后是否继续取普通号码，false 时只取优先池
+    #[serde(default = "default_priority_fallback")]
+    priority_fallback: bool,
+    // 按 X-Request-Id 缓存 /fetch 和 /ack 响应的时长（秒），重复请求直接返回缓存，0 表示不缓存
+    #[serde(default = "default_request_cache_secs")]
+    request_cache_secs: u64,
+    // 过载保护，设备请求排队过多或等锁过久时返回 503
+    shedding: Option<shed::ShedConfig>,
+    // Tokio 运行时线程数和大文件读写方式
+    #[serde(default)]
+    runtime: runtime::RuntimeConfig,
+    // 进度状态的存储后端，重启后恢复游标、重发队列和未确认批次，不配置则不保存
+    state: Option<store::StoreConfig>,
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_locale() -> String {
+    "zh".to_string()
+}
+
+fn default_import_max_mb() -> usize {
+    64
+}
+
+fn default_message_watch_secs() -> u64 {
+    2
+}
+
+fn default_priority_fallback() -> bool {
+    true
+}
+
+fn default_request_cache_secs() -> u64 {
+    120
+}
+
+fn default_export_dir() -> String {
+    "exports".to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ResponseData {
+    numbers: String,
+    message: String,
+    count: usize,
+    // 每个号码各自的消息，仅在消息因号码而不同（如短链）时返回，顺序与 numbers 一致
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    items: Vec<Item>,
+    // 下发数量少于请求数量时返回请求的数量和原因：
+    // daily_quota 当日配额、warm_up 预热上限、failure_rate 设备失败率偏高、pacing 限速、available 可发号码不足
+    #[serde(skip_serializing_if = "Option::is_none")]
+    requested: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+    // 被限速时建议的重试等待秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+    // 批次号，启用租约时用于 /ack 确认
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_id: Option<String>,
+    // 租约到期时间，到期未确认的号码将被回收重发，设备来不及处理完时可提前放弃
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<jiff::Timestamp>,
+    // 抽查的号码，发送后需 POST /verify 上传截图哈希或送达确认，否则不能取下一批
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    verify: Vec<String>,
+    // 没有下发号码时的原因，设备脚本按此判断，message 为可配置的提示文字
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<Code>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Code {
+    NoMoreNumbers,
+    NoNumbersAvailable,
+    NoPriorityNumbers,
+    RateLimited,
+    DailyQuotaReached,
+    WarmUpLimit,
+    TooManyOutstanding,
+    VerificationPending,
+    Draining,
+    CampaignEnded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Item {
+    number: String,
+    message: String,
+}
+
+struct AppState {
+    numbers: VecDeque<String>,
+    start_index: usize,
+    default_fetch_count: usize,
+    test_number: String,
+    // 活动列表，第 0 个为默认活动
+    campaigns: Vec<campaign::Campaign>,
+    // 不属于默认活动的号码所在活动
+    campaign_of: HashMap<String, usize>,
+    // 按权重轮流分配活动，启用后每个批次只含一个活动的号码
+    scheduler: Option<campaign::Scheduler>,
+    // 启用轮流分配时，取号途中遇到的其他活动的号码按活动排队，轮到该活动时优先下发
+    backlog: Vec<VecDeque<String>>,
+    // 启用设备分组时，取号途中遇到的其他分组的号码按分组排队，该分组的设备取号时优先下发
+    shard_backlog: Vec<VecDeque<String>>,
+    // 允许指定 message_id 的 X-Override-Token
+    message_override_token: Option<String>,
+    strings: Arc<strings::Strings>,
+    // 启动摘要，GET /status/config 返回
+    summary: Option<summary::Summary>,
+    held: Vec<HeldNumber>,
+    regions: region::Regions,
+    // 不在发送时段而延后的号码，按地区排队
+    deferred: Vec<VecDeque<String>>,
+    // 各地区节假日限流用量：(小时序号, 本小时已下发数)
+    throttle_usage: Vec<(i64, usize)>,
+    // 各活动的令牌桶，按活动序号，未限速的活动为 None
+    pacing: Vec<Option<pacing::TokenBucket>>,
+    backpressure: Option<backpressure::BackpressureConfig>,
+    prerender: Option<Arc<prerender::Prerender>>,
+    leases: Option<lease::Leases>,
+    // 租约过期回收或设备报告失败、等待重发的号码
+    retry: VecDeque<String>,
+    retry_policy: lease::RetryPolicy,
+    quarantine: quarantine::Quarantine,
+    verifier: Option<verify::Verifier>,
+    // 优先池：导入时标记 priority = "high" 的号码，/fetch?priority=high 时按导入顺序先取
+    // 号码同时留在 numbers 中，游标先到达时按普通顺序下发
+    priority: VecDeque<String>,
+    // 优先池中尚未下发的号码
+    priority_pending: HashSet<String>,
+    // 经优先池提前下发、游标尚未到达的号码，游标到达时跳过
+    priority_taken: HashSet<String>,
+    priority_fallback: bool,
+    // 号码在 numbers 中的位置
+    positions: HashMap<String, usize>,
+    // 启动时从号码文件（含各活动的 numbers_file）加载的号码数，之后的号码为运行中导入的
+    loaded: usize,
+    // 运行中导入的号码被归档或恢复时加一，保存时重写全部导入记录，否则只追加新导入的
+    imported_generation: u64,
+    // 各号码源上次成功拉取的开始时间，下次拉取时作为 since 传给数据源
+    source_since: HashMap<String, jiff::Timestamp>,
+    // 软删除的号码：保留在号码池中但不再下发
+    deleted: HashSet<String>,
+    // 取号时因软删除被跳过的号码，恢复后放回重发队列
+    deleted_skipped: HashSet<String>,
+    // 累计下发的号码数及首次下发时间，用于估算下发速度
+    issued: u64,
+    // 累计下发的批次数
+    batches: u64,
+    // 游标越过的重复号码（号码文件中同一号码的后续行），不下发
+    duplicates_skipped: usize,
+    first_issued_at: Option<jiff::Timestamp>,
+    devices: device::Devices,
+    blacklist: HashSet<String>,
+    // 号码回复的会话记录
+    replies: replies::Replies,
+    // 导入号码的附加信息，渲染消息时作为该号码的模板变量
+    meta: HashMap<String, HashMap<String, String>>,
+    jobs: jobs::Jobs,
+    // 号码池的运行时变更记录
+    changelog: changelog::Changelog,
+    export_dir: String,
+    downloads: Option<download::DownloadConfig>,
+    // 排空中：不再下发新批次，等待已下发批次确认后安全停止
+    draining: bool,
+    maintenance: bool,
+    // 测试模式下注入的故障
+    faults: faults::Faults,
+    features: features::FeaturesConfig,
+    notifier: Option<Arc<notify::Dispatcher>>,
+    tenants: Option<usage::TenantsConfig>,
+    // 各租户每月的用量
+    usage: usage::Usage,
+    // 管理版本号和管理修改锁，见 version_guard
+    version: u64,
+    admin_lock: Arc<tokio::sync::Mutex<()>>,
+    // 已推送 numbers_exhausted
+    exhausted: bool,
+    // 最近一次 /fetch 的时间，长时间没有设备取号时暂停预渲染
+    last_fetch: Option<jiff::Timestamp>,
+    // 最近的 /fetch 响应，按 (设备, X-Request-Id) 缓存
+    fetch_cache: dedup::RequestCache<ResponseData>,
+    // 最近的 /ack 响应，按 (批次号, X-Request-Id) 缓存
+    ack_cache: dedup::RequestCache<AckData>,
+    shedder: Option<Arc<shed::LoadShedder>>,
+    runtime: runtime::RuntimeConfig,
+}
+
+// 号码状态查询索引，批量判断状态时避免逐个扫描各队列
+struct StatusIndex<'a> {
+    held: HashSet<&'a str>,
+    leased: HashMap<&'a str, &'a str>,
+    retry: HashSet<&'a str>,
+    deferred: HashSet<&'a str>,
+    backlog: HashSet<&'a str>,
+}
+
+// 被暂扣的号码
+#[derive(Debug, Clone, Serialize)]
+struct HeldNumber {
+    number: String,
+    campaign: String,
+    segments: usize,
+    // 所属活动的短信条数预算
+    max_segments: usize,
+    reason: String,
+}
+
+// ios_sms_rpa 程序的入口：读取工作目录中的 config.toml、numbers.txt 和 msg.txt，收到 SIGTERM 或 Ctrl-C 后保存进度退出
+pub fn main() {
+    // --version 输出版本号后退出，test_util 启动服务进程前据此确认二进制与库的版本一致
+    if std::env::args().nth(1).is_some_and(|arg| arg == "--version") {
+        println!("ios_sms_rpa {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
+    // 初始化日志
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format(trace::format)
+        .init();
+
+    // 加载配置文件
+    let config = load_config("config.toml");
+    info!("加载配置文件 => 单次取号码 {} + 1 个, 测试号：{}", config.default_fetch_count, config.test_number);
+
+    // 按配置创建运行时
+    let runtime = config.runtime.build();
+    runtime.block_on(run(config));
+    // 服务已停止并保存进度，不再等待仍在运行的后台任务
+    runtime.shutdown_background();
+}
+
+// 在当前进程中构建的服务，供 test_util 等在测试中使用：config 为 config.toml 的内容，numbers 和 message 代替
+// numbers.txt 和 msg.txt（不读取这两个文件，也不监视 msg.txt），与时间有关的行为使用 clock，同一进程中的多个服务互不影响；
+// 不接管旧进程，admin_listen 不生效，管理接口与设备接口在 serve 的同一监听地址上提供；
+// 配置中的其他文件路径（消息文件、导出目录等）相对于当前进程的工作目录。配置无效时与服务进程一样 panic
+pub async fn build_app(config: &str, numbers: Vec<String>, message: String, clock: clock::Clock) -> App {
+    let config: Config = toml::from_str(config).expect("Failed to parse config.toml");
+    clock::scope(clock.clone(), async move {
+        let config = Arc::new(config);
+        let (state, loaded) = {
+            let config = config.clone();
+            let inputs = Inputs::Given { numbers, message };
+            config.runtime.clone().run_io(move || load_state(&config, inputs)).await
+        };
+        let state = Arc::new(Mutex::new(state));
+        let started = start(&config, &state, loaded, false).await;
+        let (device_app, app) = routes(&config, &state);
+        let router = device_app
+            .merge(app)
+            .with_state(state)
+            .layer(middleware::from_fn(trace::layer))
+            .layer(middleware::from_fn_with_state(clock, clock::layer));
+        App { router, started }
+    })
+    .await
+}
+
+pub struct App {
+    router: Router,
+    started: Started,
+}
+
+impl App {
+    // 在 listener 上提供服务，shutdown 完成后处理完进行中的请求，保存进度后返回
+    pub async fn serve(
+        self,
+        listener: tokio::net::TcpListener,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> std::io::Result<()> {
+        let result = serve(listener, self.router.into_make_service()).with_graceful_shutdown(shutdown).await;
+        self.started.stop().await;
+        result
+    }
+}
+
+// 号码和默认消息的来源
+enum Inputs {
+    // 工作目录中的 numbers.txt 和 msg.txt
+    Files,
+    Given { numbers: Vec<String>, message: String },
+}
+
+async fn run(config: Config) {
+    // 加载数据，号码文件可能很大，按配置放到阻塞线程池读取
+    let config = Arc::new(config);
+    let (state, loaded) = {
+        let config = config.clone();
+        config.runtime.clone().run_io(move || load_state(&config, Inputs::Files)).await
+    };
+    let state = Arc::new(Mutex::new(state));
+
+    // 先绑定监听套接字，再接管旧进程并等其保存进度后加载，期间新连接在队列中等待，不会被拒绝
+    // reuse_port 而没有 pid_file 时新旧进程会同时接受连接并各自下发号码
+    if config.reuse_port && config.pid_file.is_none() && !handover::activated() {
+        panic!("reuse_port = true requires pid_file so the old process is stopped after the new one binds");
+    }
+    let addr = config.listen.clone().unwrap_or_else(|| format!("0.0.0.0:{}", config.port));
+    let listener = handover::listen(&addr, "device", 0, config.reuse_port);
+    // 端口为 0 时由系统分配空闲端口，实际地址输出到标准输出，供 test_util 等启动方读取
+    let ephemeral = addr.ends_with(":0");
+    let addr = listener.local_addr().map_or(addr, |bound| bound.to_string());
+    if ephemeral {
+        println!("listening on {}", addr);
+    }
+    let admin_listener = config.admin_listen.as_deref().map(|addr| handover::listen(addr, "admin", 1, config.reuse_port));
+    if let Some(pid_file) = &config.pid_file {
+        let timeout = std::time::Duration::from_secs(config.shutdown_timeout_secs + 10);
+        handover::take_over(pid_file, timeout, config.state.is_some()).await;
+        handover::write_pid(pid_file);
+    }
+
+    let started = start(&config, &state, loaded, true).await;
+    let (device_app, app) = routes(&config, &state);
+
+    // 收到 SIGTERM 或 Ctrl-C 后停止接受新连接，处理完进行中的请求后退出
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    clock::spawn(async move {
+        handover::shutdown_signal().await;
+        let _ = stop_tx.send(true);
+    });
+
+    // 启动服务，配置了 admin_listen 时管理接口单独监听，可只绑定本机或内网地址
+    let (app, admin_server) = match (admin_listener, &config.admin_listen) {
+        (Some(admin_listener), Some(admin_addr)) => {
+            let admin_app = app.with_state(state.clone()).layer(middleware::from_fn(trace::layer));
+            info!("管理接口启动成功 => http://{}", admin_addr);
+            let stop = wait_stop(stop_rx.clone());
+            let admin_server = clock::spawn(async move {
+                serve(admin_listener, admin_app.into_make_service()).with_graceful_shutdown(stop).await.unwrap()
+            });
+            (device_app, Some(admin_server))
+        }
+        _ => (device_app.merge(app), None),
+    };
+    // 所有请求带追踪号，日志中附加并在响应头返回
+    let app = app.with_state(state).layer(middleware::from_fn(trace::layer));
+    info!("服务器启动成功 => http://{}", addr);
+
+    // 设备接口和管理接口都处理完进行中的请求后再保存进度退出
+    let server = serve(listener, app.into_make_service()).with_graceful_shutdown(wait_stop(stop_rx.clone()));
+    let servers = async {
+        server.await.unwrap();
+        if let Some(admin_server) = admin_server {
+            admin_server.await.unwrap();
+        }
+    };
+    let timeout = std::time::Duration::from_secs(config.shutdown_timeout_secs);
+    tokio::select! {
+        _ = servers => {}
+        _ = async { wait_stop(stop_rx).await; tokio::time::sleep(timeout).await } => {
+            warn!("{} 秒内仍有请求未完成，强制退出", config.shutdown_timeout_secs);
+        }
+    }
+
+    // 保存最后的进度，接管的新进程随后加载
+    started.stop().await;
+    if let Some(pid_file) = &config.pid_file {
+        handover::remove_pid(pid_file);
+    }
+    info!("服务器已停止");
+}
+
+// 已启动的后台任务，服务停止后通过 stop 保存最后的进度
+struct Started {
+    stopped: tokio::sync::watch::Sender<bool>,
+    persisting: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Started {
+    async fn stop(self) {
+        let _ = self.stopped.send(true);
+        if let Some(persisting) = self.persisting {
+            let _ = persisting.await;
+        }
+    }
+}
+
+// 恢复进度，启动后台任务并打印启动摘要；watch_msg_txt 为 false 时默认消息不是从 msg.txt 读取的，不监视该文件
+async fn start(
+    config: &Config,
+    state: &Arc<Mutex<AppState>>,
+    loaded: summary::Loaded,
+    watch_msg_txt: bool,
+) -> Started {
+    let mut restored_from = None;
+    // 从存储后端恢复上次的进度，之后定期保存并在退出前保存一次；只读副本定期重新加载，不保存
+    let (stopped_tx, stopped_rx) = tokio::sync::watch::channel(false);
+    let mut persisting = None;
+    let replica = config.state.as_ref().is_some_and(|s| s.replica);
+    if let Some(store_config) = config.state.clone() {
+        let (store, snapshot) = config
+            .runtime
+            .run_io(move || {
+                let mut store = store::open(&store_config);
+                let snapshot = store.load().unwrap_or_else(|e| panic!("Failed to load state from {}: {}", store.name(), e));
+                (store, snapshot)
+            })
+            .await;
+        match snapshot {
+            Some(snapshot) => {
+                restored_from = Some(snapshot.saved_at);
+                state.lock().unwrap().restore_logged(snapshot);
+            }
+            None => info!("状态存储 {} 中没有保存的进度，从头开始", store.name()),
+        }
+        let persist_secs = config.state.as_ref().unwrap().persist_secs;
+        if replica {
+            warn!("以只读副本模式启动，每 {} 秒从 {} 加载进度，不提供设备接口和修改操作", persist_secs, store.name());
+            clock::spawn(follow_state(state.clone(), store, persist_secs));
+        } else {
+            persisting = Some(clock::spawn(persist_state(state.clone(), store, persist_secs, stopped_rx)));
+        }
+    }
+
+    // 设置了截止时间的活动到期后自动结束，恢复的进度中已结束的活动不再重复结束
+    let end_times: Vec<(usize, jiff::Timestamp)> = state
+        .lock()
+        .unwrap()
+        .campaigns
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !c.ended)
+        .filter_map(|(idx, c)| Some((idx, c.end_at?)))
+        .collect();
+    for (idx, end_at) in end_times.into_iter().filter(|_| !replica) {
+        clock::spawn(end_campaign_at(state.clone(), idx, end_at));
+    }
+
+    // 后台预渲染接下来几批号码的消息
+    let prerender = state.lock().unwrap().prerender.clone();
+    if let Some(prerender) = prerender.filter(|_| !replica) {
+        info!("启用预渲染 => 提前准备 {} 批", prerender.config.batches);
+        clock::spawn(prerender_upcoming(state.clone(), prerender));
+    }
+
+    // 按计划从远程号码源补充号码
+    for source in config.sources.iter().filter(|_| !replica) {
+        let cron = cron::Cron::parse(&source.schedule)
+            .unwrap_or_else(|e| panic!("Invalid schedule for source '{}': {}", source.name, e));
+        if let Some(name) = source.campaign.as_ref().filter(|name| campaign::find(&state.lock().unwrap().campaigns, name).is_none()) {
+            panic!("Unknown campaign '{}' for source '{}'", name, source.name);
+        }
+        http_client::check_url(&source.url).unwrap_or_else(|e| panic!("Invalid url for source '{}': {}", source.name, e));
+        let timezone = datetime::load_timezone(config.timezone.as_deref());
+        clock::spawn(refresh_source(state.clone(), source.clone(), cron, timezone));
+    }
+
+    config.features.log_disabled();
+    if config.maintenance {
+        warn!("以维护模式启动，设备接口暂停服务，POST /maintenance 关闭");
+    }
+    if config.test_mode {
+        warn!("以测试模式启动，可通过 /test/faults 注入故障");
+    }
+
+    // 打印启动摘要，供开始发送前核对
+    {
+        let mut state = state.lock().unwrap();
+        let summary = startup_summary(config, &state, loaded, restored_from);
+        summary.log();
+        state.summary = Some(summary);
+    }
+
+    // 监视各活动的消息文件，修改后热更新
+    if config.message_watch_secs > 0 {
+        let files: Vec<(usize, campaign::Slot, String)> = {
+            let state = state.lock().unwrap();
+            let mut files = Vec::new();
+            for (idx, c) in state.campaigns.iter().enumerate() {
+                if idx > 0 || watch_msg_txt {
+                    files.push((idx, campaign::Slot::Default, c.message_file.clone()));
+                }
+                for (locale, variant) in &c.variants {
+                    files.push((idx, campaign::Slot::Variant(locale.clone()), variant.message_file.clone()));
+                }
+                for (id, message) in &c.messages {
+                    files.push((idx, campaign::Slot::Message(id.clone()), message.message_file.clone()));
+                }
+            }
+            files
+        };
+        for (idx, slot, file) in files {
+            let state = state.clone();
+            let emoji = config.emoji.clone();
+            clock::spawn(watch::watch_file(
+                file,
+                std::time::Duration::from_secs(config.message_watch_secs),
+                move |content| reload_message(&state, idx, &slot, &content, &emoji),
+            ));
+        }
+    }
+    Started { stopped: stopped_tx, persisting }
+}
+
+type Routes = Router<Arc<Mutex<AppState>>>;
+
+// 设备接口和管理接口的路由，由调用方决定是否分开监听
+fn routes(config: &Config, state: &Arc<Mutex<AppState>>) -> (Routes, Routes) {
+    let replica = config.state.as_ref().is_some_and(|s| s.replica);
+    // 设置路由，设备接口在维护模式下返回 503
+    let device_routes = Router::new()
+        .route("/fetch", get(fetch_handler))
+        .route("/ack", post(ack_handler))
+        .route("/nack", post(nack_handler))
+        .route("/verify", post(verify_handler))
+        .route("/heartbeat", post(heartbeat_handler))
+        .route("/replies", post(replies_handler))
+        .route("/devices/register", post(register_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), maintenance_guard));
+    let device_routes = if config.test_mode {
+        device_routes.route_layer(middleware::from_fn_with_state(state.clone(), fault_guard))
+    } else {
+        device_routes
+    };
+    let (shedder, strings) = {
+        let state = state.lock().unwrap();
+        (state.shedder.clone(), state.strings.clone())
+    };
+    let device_routes = match shedder {
+        Some(shedder) => {
+            device_routes.route_layer(middleware::from_fn_with_state((state.clone(), shedder, strings), shed_guard))
+        }
+        None => device_routes,
+    };
+    // 短链由收到短信的用户访问，与设备接口一起对外；只读副本不提供设备接口
+    // 导出文件的下载链接自带签名，同样对外提供
+    let device_app = if replica {
+        Router::new()
+    } else {
+        device_routes.route("/s/:code", get(redirect_handler))
+    };
+    let device_app = device_app.route("/download/:name", get(download_handler));
+    // 查询、导出和报告接口，只读副本只提供这些；会话、抽查和设备统计不随进度保存，只读副本不提供
+    let mut app = Router::new()
+        .route("/maintenance", get(maintenance_status_handler))
+        .route("/status", get(status_handler))
+        .route("/status/config", get(status_config_handler))
+        .route("/campaigns", get(campaigns_handler))
+        .route("/drain", get(drain_status_handler))
+        .route("/quarantine", get(quarantine_handler))
+        .route("/search", get(search_handler))
+        .route("/export", post(export_handler))
+        .route("/report", post(report_handler))
+        .route("/changelog", get(changelog_handler))
+        .route("/jobs", get(jobs_handler))
+        .route("/jobs/:id", get(job_handler))
+        .route("/jobs/:id/link", post(job_link_handler))
+        .route("/numbers", get(numbers_handler))
+        .route("/batches", get(batches_handler))
+        .route("/usage", get(usage_handler));
+    if !replica {
+        // 修改号码池和运行状态的接口带版本号检查，见 version_guard
+        let mutations = Router::new()
+            .route("/maintenance", post(maintenance_handler))
+            .route("/drain", post(drain_handler).delete(resume_handler))
+            .route("/quarantine/release", post(release_handler))
+            .route("/numbers/delete", post(delete_handler))
+            .route("/numbers/restore", post(restore_handler))
+            .route("/import", post(import_handler).layer(DefaultBodyLimit::max(config.import_max_mb * 1024 * 1024)))
+            .route("/archive", post(archive_handler))
+            .route("/jobs/:id/cancel", post(cancel_job_handler))
+            .route_layer(middleware::from_fn_with_state(state.clone(), version_guard));
+        app = app
+            .merge(mutations)
+            .route("/replies", get(conversations_handler))
+            .route("/verify/pending", get(verify_list_handler))
+            .route("/devices", get(devices_handler));
+    }
+    if config.features.metrics {
+        app = app.route("/metrics", get(metrics_handler));
+    }
+    // 进度页面随管理接口监听，页面中的请求发往同一端口
+    if config.features.dashboard {
+        app = app.route("/dashboard", get(dashboard_handler));
+    }
+    if config.test_mode && !replica {
+        app = app.route("/test/faults", get(faults_handler).post(faults_update_handler).delete(faults_reset_handler));
+    }
+    (device_app, app)
+}
+
+// 等待停止信号
+async fn wait_stop(mut stop: tokio::sync::watch::Receiver<bool>) {
+    let _ = stop.wait_for(|stopped| *stopped).await;
+}
+
+// 按 cron 计划拉取远程号码源，每次拉取作为 refresh 后台任务导入，已有号码和黑名单号码自动去重
+async fn refresh_source(state: Arc<Mutex<AppState>>, source: source::SourceConfig, cron: cron::Cron, timezone: jiff::tz::TimeZone) {
+    loop {
+        let now = clock::now();
+        let Some(next) = cron.next_after(now, &timezone) else {
+            warn!("号码源 {} 的计划 {} 一年内没有执行时间，停止拉取", source.name, source.schedule);
+            return;
+        };
+        debug!("号码源 {} 下次拉取时间 {}", source.name, next);
+        clock::sleep_until(next).await;
+
+        let source = source.clone();
+        spawn_job(&state, "refresh", move |state, job| async move {
+            let started = clock::now();
+            let last = state.lock().unwrap().source_since.get(&source.name).copied();
+            let body = source::fetch(&source, last).await?;
+            info!("号码源 {} 拉取 {} 字节", source.name, body.len());
+            let actor = Actor { operator: Some(format!("source:{}", source.name)), reason: None };
+            let mut summary = run_import_job(state.clone(), job, body, actor, source.campaign.clone()).await?;
+            state.lock().unwrap().source_since.insert(source.name.clone(), started);
+            summary["source"] = serde_json::Value::from(source.name);
+            Ok(summary)
+        });
+    }
+}
+
+// 到达截止时间后结束活动，并在后台把未下发的号码导出为 CSV
+async fn end_campaign_at(state: Arc<Mutex<AppState>>, idx: usize, end_at: jiff::Timestamp) {
+    clock::sleep_until(end_at).await;
+    let (name, remainder) = {
+        let mut state = state.lock().unwrap();
+        let name = state.campaigns[idx].name.clone();
+        let remainder = state.end_campaign(idx);
+        warn!("活动 {} 已到截止时间 {}，停止下发，{} 个未下发的号码转为软删除并导出", name, end_at, remainder.len());
+        let actor = Actor { operator: Some(format!("campaign:{}", name)), reason: Some("end_at".to_string()) };
+        state.log_change("expire", &remainder, actor);
+        state.notify("campaign_ended", serde_json::json!({ "campaign": name, "end_at": end_at, "remaining": remainder.len() }));
+        (name, remainder)
+    };
+    spawn_job(&state, "expire", |state, job| async move {
+        let (mut file, path) = create_export_file(&state, &job, "csv").await?;
+        let mut buf = String::from("number,campaign\n");
+        for number in &remainder {
+            buf.push_str(&format!("{},{}\n", number, name));
+        }
+        file.write_all(buf.as_bytes()).await.map_err(|e| e.to_string())?;
+        Ok(serde_json::json!({ "campaign": name, "file": path, "rows": remainder.len() }))
+    });
+}
+
+// 定期把进度保存到存储后端：持锁时只复制进度和新导入的号码，序列化和保存在锁外的阻塞线程池中进行；
+// 先追加新导入的号码再保存进度，进度中的游标不会指向未保存的号码
+async fn persist_state(
+    state: Arc<Mutex<AppState>>,
+    store: Box<dyn store::StateStore>,
+    secs: u64,
+    mut stopped: tokio::sync::watch::Receiver<bool>,
+) {
+    let store = Arc::new(Mutex::new(store));
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(secs.max(1)));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // 上次保存的进度的哈希，及已保存的导入号码（generation 和个数）
+    let mut saved: Option<u64> = None;
+    let mut imported_saved: Option<(u64, usize)> = None;
+    loop {
+        // 服务停止后再保存一次并结束
+        let last = tokio::select! {
+            _ = interval.tick() => false,
+            _ = stopped.wait_for(|stopped| *stopped) => true,
+        };
+        let (progress, (imported, replace), imported_mark) = {
+            let state = state.lock().unwrap();
+            let mark = (state.imported_generation, state.numbers.len() - state.loaded);
+            (state.progress(), state.imported_since(imported_saved), mark)
+        };
+        let store = store.clone();
+        let result = clock::spawn_blocking(move || {
+            let mut store = store.lock().unwrap();
+            if replace || !imported.is_empty() {
+                let records: Vec<String> = imported.iter().map(store::ImportedRecord::to_json).collect();
+                if let Err(e) = store.persist_imported(&records, replace) {
+                    return (false, Err(format!("{}: {}", store.name(), e)));
+                }
+            }
+            (true, save_progress(store.as_mut(), &progress, saved))
+        })
+        .await;
+        match result {
+            Ok((imported, progress)) => {
+                if imported {
+                    imported_saved = Some(imported_mark);
+                }
+                match progress {
+                    Ok(hash) => {
+                        if last {
+                            info!("已保存最后的进度");
+                        }
+                        saved = Some(hash);
+                    }
+                    Err(e) => warn!("保存进度失败 {}", e),
+                }
+            }
+            Err(e) => warn!("保存进度失败: {}", e),
+        }
+        if last {
+            return;
+        }
+    }
+}
+
+// 序列化并保存进度，返回其哈希；与上次保存的哈希相同时不再写入
+fn save_progress(store: &mut dyn store::StateStore, progress: &store::Progress, saved: Option<u64>) -> Result<u64, String> {
+    let body = progress.to_json()?;
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let hash = hasher.finish();
+    if saved != Some(hash) {
+        let data = store::encode(clock::now(), &body);
+        store.persist(&data).map_err(|e| format!("{}: {}", store.name(), e))?;
+    }
+    Ok(hash)
+}
+
+// 定期为游标之后和重发队列中即将下发的号码渲染消息，/fetch 取到这些号码时直接使用
+async fn prerender_upcoming(state: Arc<Mutex<AppState>>, prerender: Arc<prerender::Prerender>) {
+    let interval = std::time::Duration::from_millis(prerender.config.interval_ms);
+    loop {
+        let (upcoming, now) = {
+            let state = state.lock().unwrap();
+            let now = state.campaigns[0].renderer.now();
+            let limit = prerender.config.batches * state.default_fetch_count;
+            let upcoming = if state.prerender_wanted(now.timestamp()) { state.upcoming(limit, now.timestamp()) } else { Vec::new() };
+            (upcoming, now)
+        };
+        let pending = prerender.pending(upcoming, &now);
+        if !pending.is_empty() {
+            debug!("预渲染 {} 个号码的消息", pending.len());
+        }
+        let concurrency = pending.first().map_or(1, |(_, renderer, _)| shorten_concurrency(std::slice::from_ref(renderer)));
+        stream::iter(pending)
+            .map(|(number, renderer, meta)| {
+                let now = &now;
+                async move {
+                    let rendered = renderer.prerender(&number, meta.as_ref(), now).await;
+                    (number, renderer, meta, rendered)
+                }
+            })
+            .buffered(concurrency)
+            .for_each(|(number, renderer, meta, rendered)| {
+                if let Some(rendered) = rendered {
+                    prerender.insert(number, renderer, meta.as_ref(), &now, rendered);
+                }
+                std::future::ready(())
+            })
+            .await;
+        tokio::time::sleep(interval).await;
+    }
+}
+
+// 同时渲染的号码数：启用短链服务时按其 concurrency，否则渲染不涉及网络请求，逐个进行
+fn shorten_concurrency(renderers: &[Arc<message::Renderer>]) -> usize {
+    renderers.iter().find_map(|r| r.shortener.as_ref()).map_or(1, |s| s.concurrency())
+}
+
+// 只读副本：定期从存储后端加载主实例保存的进度
+async fn follow_state(state: Arc<Mutex<AppState>>, store: Box<dyn store::StateStore>, secs: u64) {
+    let store = Arc::new(Mutex::new(store));
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(secs.max(1)));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let store = store.clone();
+        let result = clock::spawn_blocking(move || {
+            let mut store = store.lock().unwrap();
+            store.load().map_err(|e| format!("{}: {}", store.name(), e))
+        })
+        .await;
+        match result {
+            Ok(Ok(Some(snapshot))) => {
+                debug!("加载 {} 保存的进度", snapshot.saved_at);
+                state.lock().unwrap().restore(snapshot);
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => warn!("加载进度失败 {}", e),
+            Err(e) => warn!("加载进度失败: {}", e),
+        }
+    }
+}
+
+// 替换活动（或其语言版本、备选消息）在内存中的消息模板，内容为空或超出短信条数预算时保留原消息
+fn reload_message(
+    state: &Arc<Mutex<AppState>>,
+    idx: usize,
+    slot: &campaign::Slot,
+    content: &str,
+    emoji: &HashMap<String, String>,
+) {
+    let mut state = state.lock().unwrap();
+    let campaign = &mut state.campaigns[idx];
+    let max_segments = campaign.max_segments;
+    let name = campaign.name.clone();
+    let Some((file, slot)) = campaign.slot_mut(slot) else { return };
+    let Some(line) = content.lines().next().map(str::trim_end).filter(|l| !l.trim().is_empty()) else {
+        warn!("{} 已修改但内容为空，保留原消息", file);
+        return;
+    };
+    let message = emoji::expand(line, emoji);
+    if message == slot.template {
+        return;
+    }
+    let renderer = slot.with_template(message);
+    let segments = segments::count(&renderer.render_static(&renderer.now()));
+    if let Some(max) = max_segments.filter(|&max| segments > max) {
+        warn!("{} 已修改但新消息为 {} 条短信，超出预算 {} 条，保留原消息", file, segments, max);
+        return;
+    }
+    info!(
+        "活动 {}（{}）消息内容已更新（约 {} 条短信）: {} => {}",
+        name, file, segments, slot.template, renderer.template
+    );
+    *slot = Arc::new(renderer);
+}
+
+// 请求头中的 X-Request-Id
+fn request_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+}
+
+// 处理 /fetch 请求，同一设备重复的 X-Request-Id 返回缓存的批次，不再重新取号
+async fn fetch_handler(
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<ResponseData>, StatusCode> {
+    let device = params.get("device").cloned().unwrap_or_default();
+
+    // 只有带正确 X-Override-Token 的请求可以指定消息
+    if params.contains_key("message_id") {
+        let token = headers.get("x-override-token").and_then(|v| v.to_str().ok());
+        let trusted = state.lock().unwrap().message_override_token.as_deref().is_some_and(|t| Some(t) == token);
+        if !trusted {
+            warn!("设备 {} 指定 message_id 但未通过校验，已拒绝", device);
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let tenant = state.lock().unwrap().tenant(&headers)?;
+    // 不带 device 参数的请求无法区分设备，不缓存
+    let request_id = request_id(&headers).filter(|_| params.contains_key("device"));
+    let Some(request_id) = request_id.filter(|_| state.lock().unwrap().fetch_cache.enabled()) else {
+        return fetch_batch(params, tenant, state).await;
+    };
+    let tx = loop {
+        let lookup = state.lock().unwrap().fetch_cache.lookup_or_begin(&device, &request_id);
+        match lookup {
+            dedup::Lookup::Cached(cached) => {
+                info!("设备 {} 重复请求 {}，返回缓存的批次 {:?}", device, request_id, cached.batch_id);
+                return Ok(Json(cached));
+            }
+            // 首个请求处理完后返回其结果，失败或被取消时重新查找
+            dedup::Lookup::Wait(mut rx) => {
+                let response = rx.wait_for(Option::is_some).await.ok().and_then(|response| response.clone());
+                if let Some(response) = response {
+                    info!("设备 {} 重复请求 {}，等待并返回首个请求的批次 {:?}", device, request_id, response.batch_id);
+                    return Ok(Json(response));
+                }
+            }
+            dedup::Lookup::Begin(tx) => break tx,
+        }
+    };
+
+    let pending = PendingRequest { state: state.0.clone(), device, request_id: Some(request_id) };
+    let response = fetch_batch(params, tenant, state).await?;
+    pending.finish(tx, &response.0);
+    Ok(response)
+}
+
+// /fetch 为取到号码的活动预留的未确认批次名额，登记批次或未登记（没有号码、出错或连接断开）时归还
+struct Reservation {
+    state: Arc<Mutex<AppState>>,
+    campaigns: Vec<usize>,
+}
+
+impl Reservation {
+    // 为本批次新取到号码的活动预留名额，须与取号在同一次加锁中调用
+    fn reserve(&mut self, state: &mut AppState, numbers: &[String]) {
+        let Some(leases) = &mut state.leases else { return };
+        for number in numbers {
+            let campaign = state.campaign_of.get(number).copied().unwrap_or(0);
+            if !self.campaigns.contains(&campaign) {
+                leases.reserve(campaign);
+                self.campaigns.push(campaign);
+            }
+        }
+    }
+
+    fn release(&mut self, state: &mut AppState) {
+        if let Some(leases) = &mut state.leases {
+            for campaign in self.campaigns.drain(..) {
+                leases.release(campaign);
+            }
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.campaigns.is_empty() {
+            return;
+        }
+        let shared = self.state.clone();
+        if let Ok(mut state) = shared.lock() {
+            self.release(&mut state);
+        }
+    }
+}
+
+// 处理中的 /fetch 请求，未完成（出错或连接断开）时移除登记，等待的重试重新处理
+struct PendingRequest {
+    state: Arc<Mutex<AppState>>,
+    device: String,
+    request_id: Option<String>,
+}
+
+impl PendingRequest {
+    fn finish(mut self, tx: tokio::sync::watch::Sender<Option<ResponseData>>, response: &ResponseData) {
+        let request_id = self.request_id.take().unwrap();
+        self.state.lock().unwrap().fetch_cache.finish(&self.device, &request_id, tx, response.clone());
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        // 处理中 panic 时锁可能已中毒，此时跳过
+        if let (Some(request_id), Ok(mut state)) = (&self.request_id, self.state.lock()) {
+            state.fetch_cache.abandon(&self.device, request_id);
+        }
+    }
+}
+
+async fn fetch_batch(
+    params: HashMap<String, String>,
+    tenant: Option<String>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<ResponseData>, StatusCode> {
+    let (n, requested, reason, renderers, test_number, budgets) = {
+        let mut state = state.lock().unwrap();
+
+        // 严格模式或启用发送抽查时只给已注册的设备下发，不带 device 参数的请求不能绕过抽查
+        let registration_required = state.devices.strict() || state.verifier.is_some();
+        if registration_required && !params.get("device").is_some_and(|d| state.devices.is_registered(d)) {
+            warn!("未注册的设备 {:?} 请求取号，已拒绝", params.get("device"));
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        state.last_fetch = Some(clock::now());
+        if let Some(device) = params.get("device") {
+            state.devices.record_fetch(device, 0, clock::now());
+            if let Some(locale) = params.get("locale") {
+                state.devices.set_locale(device, locale, clock::now());
+            }
+        }
+
+        // 上一批抽查的号码还未上传凭证时不下发
+        let pending = params.get("device").zip(state.verifier.as_ref()).map(|(d, v)| v.pending(d));
+        if let Some(pending) = pending.filter(|p| !p.is_empty()) {
+            return Ok(Json(ResponseData {
+                message: state.strings.verification_pending.clone(),
+                code: Some(Code::VerificationPending),
+                verify: pending,
+                ..Default::default()
+            }));
+        }
+
+        if state.draining {
+            return Ok(Json(ResponseData {
+                message: state.strings.draining.clone(),
+                code: Some(Code::Draining),
+                ..Default::default()
+            }));
+        }
+
+        if state.campaigns.iter().all(|c| c.ended) {
+            return Ok(Json(ResponseData {
+                message: state.strings.campaign_ended.clone(),
+                code: Some(Code::CampaignEnded),
+                ..Default::default()
+            }));
+        }
+
+        // 回收过期租约，所有未结束的活动的未确认批次都达到上限时不再下发
+        state.reclaim_expired(clock::now());
+        if let Some(leases) = &state.leases
+            && state.campaigns.iter().enumerate().filter(|(_, c)| !c.ended).all(|(idx, _)| leases.is_full(idx))
+        {
+            return Ok(Json(ResponseData {
+                message: state.strings.too_many_outstanding.clone(),
+                code: Some(Code::TooManyOutstanding),
+                ..Default::default()
+            }));
+        }
+
+        // 获取 n，如果没有提供（或为 0）则使用配置中的默认值
+        let n = params
+            .get("n")
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(state.default_fetch_count);
+
+        // 不超过设备当天剩余的配额
+        let quota = params.get("device").and_then(|d| state.devices.quota_remaining(d, clock::now()));
+        if quota == Some(0) {
+            return Ok(Json(ResponseData {
+                message: state.strings.daily_quota_reached.clone(),
+                code: Some(Code::DailyQuotaReached),
+                ..Default::default()
+            }));
+        }
+        let requested = n;
+        // 按配额、预热和失败率缩小批次时记录原因，取最终起限制作用的一项
+        let mut reason = None;
+        let mut shrink = |n: usize, size: usize, why: &'static str| {
+            if size < n {
+                reason = Some(why);
+            }
+            n.min(size)
+        };
+        let n = quota.map_or(n, |quota| shrink(n, quota, "daily_quota"));
+
+        // 新设备预热期间不超过当前小时的上限
+        let ramp = params.get("device").and_then(|d| state.devices.ramp_remaining(d, clock::now()));
+        if let Some((0, secs)) = ramp {
+            return Ok(Json(ResponseData {
+                message: state.strings.warm_up_limit.clone(),
+                code: Some(Code::WarmUpLimit),
+                retry_after: Some(secs),
+                ..Default::default()
+            }));
+        }
+        let n = ramp.map_or(n, |(remaining, _)| shrink(n, remaining, "warm_up"));
+
+        // 设备最近失败率偏高时缩小批次
+        let failure_rate = params
+            .get("device")
+            .zip(state.backpressure.as_ref())
+            .and_then(|(d, bp)| state.devices.recent_failure_rate(d, bp.min_acked));
+        let n = match (&state.backpressure, failure_rate) {
+            (Some(bp), Some(rate)) => shrink(n, bp.shrink(n, rate), "failure_rate"),
+            _ => n,
+        };
+
+        let test_number = state.features.test_number.then(|| state.test_number.clone());
+        // 按请求或设备登记的语言选择各活动的消息
+        let locale = params
+            .get("locale")
+            .map(String::as_str)
+            .or_else(|| params.get("device").and_then(|d| state.devices.locale(d)));
+        let mut renderers: Vec<Arc<message::Renderer>> =
+            state.campaigns.iter().map(|c| c.renderer_for(locale).clone()).collect();
+        // 指定 message_id 时，有该备选消息的活动改用该消息，其余活动不变
+        if let Some(id) = params.get("message_id") {
+            if !state.campaigns.iter().any(|c| c.messages.contains_key(id)) {
+                warn!("设备 {:?} 指定了不存在的 message_id {}", params.get("device"), id);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            for (renderer, c) in renderers.iter_mut().zip(&state.campaigns) {
+                if let Some(message) = c.messages.get(id) {
+                    *renderer = message.renderer.clone();
+                }
+            }
+            info!("设备 {:?} 指定消息 {}", params.get("device"), id);
+        }
+        // 各活动的短信条数预算
+        let budgets: Vec<(String, Option<usize>)> = state.campaigns.iter().map(|c| (c.name.clone(), c.max_segments)).collect();
+        (n, requested, reason, renderers, test_number, budgets)
+    };
+    // 在取号的同一次加锁中按活动预留名额，同时到达的请求不会在渲染期间一起超出上限
+    let mut reservation = Reservation { state: state.0.clone(), campaigns: Vec::new() };
+
+    // 在锁外渲染消息（短链需要请求外部服务，按 concurrency 并发），超出条数预算的号码暂扣，不下发
+    let now = renderers[0].now();
+    let concurrency = shorten_concurrency(&renderers);
+    let mut items = Vec::with_capacity(n + 1);
+    let mut held = Vec::new();
+    // 下发号码的短链及其所属活动，下发后写入短链记录
+    let mut links = Vec::new();
+    // 测试号使用批次中第一个号码所属活动的消息
+    let mut test_campaign = None;
+    // 启用轮流分配时本批次依次尝试的活动，取到号码后整批只取该活动
+    let priority = params.get("priority").is_some_and(|p| p == "high");
+    let mut order: Option<VecDeque<usize>> = {
+        let mut state = state.lock().unwrap();
+        let device = params.get("device").map_or("", String::as_str);
+        state.scheduler.as_mut().map(|s| s.order(device).into())
+    };
+    // 限速令牌不足以取满本批次
+    let mut paced = false;
+    while items.len() < n {
+        let picked: Vec<_> = {
+            let mut state = state.lock().unwrap();
+            let numbers = loop {
+                let campaign = match &order {
+                    Some(order) => match order.front() {
+                        Some(&c) => Some(c),
+                        None => break Vec::new(),
+                    },
+                    None => None,
+                };
+                let shard = params.get("device").and_then(|d| state.devices.shard(d));
+                let (numbers, short) =
+                    state.take_paced(n - items.len(), now.timestamp(), campaign, shard, priority, &reservation.campaigns);
+                paced |= short;
+                reservation.reserve(&mut state, &numbers);
+                match order.as_mut() {
+                    Some(order) if numbers.is_empty() && items.is_empty() => {
+                        order.pop_front();
+                    }
+                    Some(order) => {
+                        order.truncate(1);
+                        break numbers;
+                    }
+                    None => break numbers,
+                }
+            };
+            numbers
+                .into_iter()
+                .map(|number| {
+                    let meta = state.meta.get(&number).filter(|_| state.features.personalization).cloned();
+                    let campaign = state.campaign_index(&number);
+                    let prerendered =
+                        state.prerender.as_ref().and_then(|p| p.take(&renderers[campaign], &number, meta.as_ref(), &now));
+                    (number, campaign, meta, prerendered)
+                })
+                .collect()
+        };
+        if picked.is_empty() {
+            break;
+        }
+        let rendered: Vec<_> = stream::iter(picked)
+            .map(|(number, campaign, meta, prerendered)| {
+                let (renderer, now) = (&renderers[campaign], &now);
+                async move {
+                    let rendered = match prerendered {
+                        Some(rendered) => rendered,
+                        None => renderer.render(&number, meta.as_ref(), now).await,
+                    };
+                    (number, campaign, rendered)
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+        for (number, campaign, message::Rendered { message, link }) in rendered {
+            let segments = segments::count(&message);
+            let (name, max_segments) = &budgets[campaign];
+            if let Some(max) = max_segments.filter(|&max| segments > max) {
+                warn!("号码 {} 的消息为 {} 条短信，超出活动 {} 的预算 {} 条，已暂扣", number, segments, name, max);
+                let reason = "over segment budget".to_string();
+                held.push(HeldNumber { number, campaign: name.clone(), segments, max_segments: max, reason });
+                continue;
+            }
+            test_campaign.get_or_insert(campaign);
+            links.extend(link.map(|link| (campaign, link)));
+            items.push(Item { number, message });
+        }
+    }
+
+    let (remaining, retry_after, priority_only, blocked) = {
+        let mut state = state.lock().unwrap();
+        // 暂扣的号码未下发，归还所属活动的令牌
+        for h in &held {
+            let idx = campaign::find(&state.campaigns, &h.campaign).unwrap_or(0);
+            if let Some(bucket) = &mut state.pacing[idx] {
+                bucket.refund(1);
+            }
+        }
+        let rendered = (items.len() + held.len()) as u64;
+        state.record_usage(tenant.as_deref(), |c| c.rendered += rendered);
+        state.held.extend(held);
+        // 受限速影响时按最早有令牌的活动提示
+        let retry_after = if paced {
+            state.pacing.iter_mut().flatten().map(|b| b.retry_after_secs()).filter(|&secs| secs > 0).min()
+        } else {
+            None
+        };
+        // 有活动因未确认批次达到上限而未取号
+        let blocked = state.leases.as_ref().is_some_and(|l| {
+            (0..state.campaigns.len()).any(|idx| !reservation.campaigns.contains(&idx) && l.is_full(idx))
+        });
+        (state.remaining(), retry_after, priority && !state.priority_fallback, blocked)
+    };
+
+    if items.is_empty() {
+        // 还有号码因限速、发送时段或节假日暂不可发时提示稍后再取
+        let strings = {
+            let mut state = state.lock().unwrap();
+            // 号码发完时只推送一次，之后再补充号码并继续下发时重新计算
+            if remaining == 0 && !priority_only && !state.exhausted {
+                state.exhausted = true;
+                let data = serde_json::json!({ "issued": state.issued, "batches": state.batches });
+                state.notify("numbers_exhausted", data);
+            }
+            state.strings.clone()
+        };
+        let (message, code, retry_after) = match retry_after {
+            Some(secs) if remaining > 0 => (&strings.rate_limited, Code::RateLimited, Some(secs)),
+            _ if remaining > 0 && blocked => (&strings.too_many_outstanding, Code::TooManyOutstanding, None),
+            _ if priority_only => (&strings.no_priority_numbers, Code::NoPriorityNumbers, None),
+            _ if remaining > 0 => (&strings.no_numbers_available, Code::NoNumbersAvailable, None),
+            _ => (&strings.no_more_numbers, Code::NoMoreNumbers, None),
+        };
+        // return Err(StatusCode::NOT_FOUND);
+        return Ok(Json(ResponseData {
+            message: message.clone(),
+            code: Some(code),
+            retry_after,
+            ..Default::default()
+        }));
+    }
+
+    // 少于请求的数量时说明原因，便于设备区分限流和号码不足
+    let reason = (items.len() < requested).then(|| reason.unwrap_or(if paced { "pacing" } else { "available" }));
+    if let Some(reason) = reason {
+        debug!("设备 {:?} 请求 {} 个，下发 {} 个（{}）", params.get("device"), requested, items.len(), reason);
+    }
+
+    let (lease, verify) = {
+        let mut state = state.lock().unwrap();
+        state.issued += items.len() as u64;
+        state.batches += 1;
+        state.exhausted = false;
+        let issued = items.len() as u64;
+        let rendered = test_number.is_some() as u64;
+        state.record_usage(tenant.as_deref(), |c| {
+            c.issued += issued;
+            c.rendered += rendered;
+        });
+        state.first_issued_at.get_or_insert(now.timestamp());
+        let numbers: Vec<String> = items.iter().map(|item| item.number.clone()).collect();
+        let device = params.get("device").cloned();
+        if let Some(device) = &device {
+            state.devices.record_fetch(device, items.len(), now.timestamp());
+        }
+        reservation.release(&mut state);
+        let campaigns = state.campaigns_of(&numbers);
+        let lease = state
+            .leases
+            .as_mut()
+            .map(|l| l.issue(numbers.clone(), campaigns, device.clone(), trace::current(), now.timestamp()));
+        let verify = match (&mut state.verifier, &device) {
+            (Some(verifier), Some(device)) => {
+                verifier.sample(device, &numbers, lease.as_ref().map(|(id, _)| id.as_str()), now.timestamp())
+            }
+            _ => Vec::new(),
+        };
+        (lease, verify)
+    };
+
+    if let Some(test_number) = test_number {
+        let test_campaign = test_campaign.unwrap_or(0);
+        let rendered = renderers[test_campaign].render(&test_number, None, &now).await;
+        links.extend(rendered.link.map(|link| (test_campaign, link)));
+        items.insert(0, Item { number: test_number, message: rendered.message });
+    }
+
+    // 号码已下发，写入短链记录；暂扣的号码和请求中途失败时生成的短链不记录
+    let mut issued: Vec<(&Arc<shortener::Shortener>, Vec<&shortener::ShortLink>)> = Vec::new();
+    for (campaign, link) in &links {
+        let Some(shortener) = renderers[*campaign].shortener.as_ref() else {
+            continue;
+        };
+        match issued.iter_mut().find(|(s, _)| Arc::ptr_eq(s, shortener)) {
+            Some((_, links)) => links.push(link),
+            None => issued.push((shortener, vec![link])),
+        }
+    }
+    for (shortener, links) in issued {
+        shortener.record_issued(&links).await;
+    }
+
+    let personalized = items.iter().any(|item| item.message != items[0].message);
+    let response = ResponseData {
+        numbers: items.iter().map(|item| item.number.as_str()).collect::<Vec<_>>().join(","),
+        message: items[0].message.clone(),
+        count: items.len(),
+        items: if personalized { items } else { Vec::new() },
+        requested: reason.map(|_| requested),
+        reason,
+        retry_after: None,
+        batch_id: lease.as_ref().map(|(id, _)| id.clone()),
+        expires_at: lease.map(|(_, expires_at)| expires_at),
+        verify,
+        code: None,
+    };
+
+    // 进度按号码去重后的累计数计算，与各次请求的 n 无关
+    let (served, total, batches) = {
+        let state = state.lock().unwrap();
+        (state.served(), state.positions.len(), state.batches)
+    };
+    info!(
+        "数据请求: 当前进度：{} / {} 条， 累计第 {} 批，剩余 {} 条.",
+        served, total, batches, remaining
+    );
+
+    // 调试日志，显示具体返回的数据
+    debug!("Response data: {:?}", response);
+
+    Ok(Json(response))
+}
+
+#[derive(Debug, Deserialize)]
+struct AckRequest {
+    batch_id: String,
+    // 确认的设备，严格模式下必须是已注册的设备且为该批次的领取者
+    device: Option<String>,
+    // 发送失败的号码，放入重发队列
+    #[serde(default)]
+    failed: Vec<FailedEntry>,
+}
+
+// 失败号码，可直接写号码，也可附带失败原因
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FailedEntry {
+    Number(String),
+    Detailed { number: String, reason: Option<String> },
+}
+
+impl FailedEntry {
+    fn number(&self) -> &str {
+        match self {
+            FailedEntry::Number(number) | FailedEntry::Detailed { number, .. } => number,
+        }
+    }
+
+    fn reason(&self) -> Option<&str> {
+        match self {
+            FailedEntry::Number(_) => None,
+            FailedEntry::Detailed { reason, .. } => reason.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct AckData {
+    batch_id: String,
+    count: usize,
+    failed: usize,
+    quarantined: usize,
+}
+
+// 处理 /ack 请求，确认批次已处理完毕，释放租约
+// 重复的 X-Request-Id 返回缓存的确认结果，避免重试时批次已被确认而返回 404
+// 严格模式下未注册的设备或确认其他设备的批次返回 403
+async fn ack_handler(
+    headers: HeaderMap,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<AckRequest>,
+) -> Result<Json<AckData>, StatusCode> {
+    let mut state = state.lock().unwrap();
+    if state.devices.strict() && !req.device.as_deref().is_some_and(|d| state.devices.is_registered(d)) {
+        warn!("严格模式下拒绝未注册设备 {:?} 确认批次 {}", req.device, req.batch_id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let request_id = request_id(&headers);
+    if let Some(cached) = request_id.as_deref().and_then(|id| state.ack_cache.get(&req.batch_id, id)) {
+        info!("批次 {} 重复确认，返回缓存的结果", req.batch_id);
+        return Ok(Json(cached));
+    }
+    state.reclaim_expired(clock::now());
+    let strict = state.devices.strict();
+    let leases = state.leases.as_mut().ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(lease) = leases.get(&req.batch_id).filter(|lease| strict && lease.device != req.device) {
+        warn!("严格模式下设备 {:?} 不能确认设备 {:?} 的批次 {}", req.device, lease.device, req.batch_id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    match leases.ack(&req.batch_id) {
+        Some(lease) => {
+            // 只接受属于该批次的失败号码
+            let (failed, unknown): (Vec<FailedEntry>, Vec<FailedEntry>) =
+                req.failed.into_iter().partition(|entry| lease.numbers.iter().any(|n| n == entry.number()));
+            if !unknown.is_empty() {
+                warn!("批次 {} 的失败列表中有 {} 个号码不属于该批次，已忽略", req.batch_id, unknown.len());
+            }
+
+            // 记录失败，在足够多的不同设备上失败的号码隔离，其余重发
+            let now = clock::now();
+            let device = lease.device.as_deref().unwrap_or("unknown");
+            let mut quarantined = 0;
+            for entry in &failed {
+                if state.quarantine.record_failure(entry.number(), device, entry.reason(), now) {
+                    warn!("号码 {} 已在多台设备上发送失败，隔离不再重发", entry.number());
+                    let actor = Actor {
+                        operator: Some(format!("device:{}", device)),
+                        reason: entry.reason().map(String::from),
+                    };
+                    state.log_change("quarantine", &[entry.number().to_string()], actor);
+                    state.notify("number_quarantined", serde_json::json!({ "number": entry.number(), "device": device }));
+                    quarantined += 1;
+                } else {
+                    state.retry.push_back(entry.number().to_string());
+                }
+            }
+
+            if let Some(device) = &lease.device {
+                state.devices.record_ack(device, lease.numbers.len(), failed.len(), now);
+                let failed: Vec<String> = failed.iter().map(|entry| entry.number().to_string()).collect();
+                if let Some(verifier) = &mut state.verifier {
+                    verifier.cancel(device, &failed);
+                }
+            }
+            info!(
+                "批次 {} 已确认，共 {} 个号码，失败 {} 个（隔离 {} 个），设备 {:?}",
+                req.batch_id, lease.numbers.len(), failed.len(), quarantined, lease.device
+            );
+            state.notify(
+                "batch_acked",
+                serde_json::json!({
+                    "batch_id": req.batch_id,
+                    "device": lease.device,
+                    "count": lease.numbers.len(),
+                    "failed": failed.len(),
+                }),
+            );
+            if state.draining && state.drain_data().safe_to_stop {
+                info!("排空完成：已下发的批次全部确认，可以安全停止服务");
+                state.notify("drain_complete", serde_json::json!(state.drain_data()));
+            }
+            let data = AckData {
+                batch_id: req.batch_id,
+                count: lease.numbers.len(),
+                failed: failed.len(),
+                quarantined,
+            };
+            if let Some(id) = &request_id {
+                state.ack_cache.insert(&data.batch_id, id, data.clone());
+            }
+            Ok(Json(data))
+        }
+        None => {
+            warn!("确认未知或已过期的批次 {}", req.batch_id);
+            Err(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NackRequest {
+    batch_id: String,
+    // 退回的设备，严格模式下必须是已注册的设备且为该批次的领取者
+    device: Option<String>,
+    // 退回原因，如 "rebooting"
+    reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NackData {
+    batch_id: String,
+    // 放回重发队列队首的号码数
+    returned: usize,
+}
+
+// 处理 /nack 请求，设备退回整个未开始发送的批次（即将重启、人工介入等），
+// 号码立即放回重发队列队首，不必等租约过期；严格模式下与 /ack 一样校验设备，不符时返回 403
+async fn nack_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<NackRequest>,
+) -> Result<Json<NackData>, StatusCode> {
+    let mut state = state.lock().unwrap();
+    let strict = state.devices.strict();
+    if strict && !req.device.as_deref().is_some_and(|d| state.devices.is_registered(d)) {
+        warn!("严格模式下拒绝未注册设备 {:?} 退回批次 {}", req.device, req.batch_id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let leases = state.leases.as_mut().ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(lease) = leases.get(&req.batch_id).filter(|lease| strict && lease.device != req.device) {
+        warn!("严格模式下设备 {:?} 不能退回设备 {:?} 的批次 {}", req.device, lease.device, req.batch_id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let Some(lease) = leases.ack(&req.batch_id) else {
+        warn!("退回未知或已过期的批次 {}", req.batch_id);
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let returned = lease.numbers.len();
+    if let (Some(verifier), Some(device)) = (&mut state.verifier, &lease.device) {
+        verifier.cancel(device, &lease.numbers);
+    }
+    for number in lease.numbers.into_iter().rev() {
+        state.retry.push_front(number);
+    }
+    info!(
+        "批次 {} 被设备 {:?} 退回，{} 个号码放回队首，原因: {}",
+        req.batch_id, lease.device, returned, req.reason.as_deref().unwrap_or("-")
+    );
+    state.notify(
+        "batch_nacked",
+        serde_json::json!({ "batch_id": req.batch_id, "device": lease.device, "count": returned, "reason": req.reason }),
+    );
+    if state.draining && state.drain_data().safe_to_stop {
+        info!("排空完成：已下发的批次全部确认，可以安全停止服务");
+        state.notify("drain_complete", serde_json::json!(state.drain_data()));
+    }
+    Ok(Json(NackData { batch_id: req.batch_id, returned }))
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyRequest {
+    device: String,
+    proofs: Vec<verify::Proof>,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifyData {
+    #[serde(flatten)]
+    outcome: verify::Outcome,
+    // 仍待上传凭证的号码
+    pending: Vec<String>,
+}
+
+// 处理 POST /verify 请求，设备为抽查的号码上传截图哈希或送达确认，确认未送达的号码放回重发队列；未注册的设备返回 403
+async fn verify_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<VerifyRequest>,
+) -> Result<Json<VerifyData>, StatusCode> {
+    let mut state = state.lock().unwrap();
+    if !state.devices.is_registered(&req.device) {
+        warn!("未注册的设备 {} 上传抽查凭证，已拒绝", req.device);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let verifier = state.verifier.as_mut().ok_or(StatusCode::NOT_FOUND)?;
+    let outcome = verifier.submit(&req.device, req.proofs);
+    let pending = verifier.pending(&req.device);
+    if !outcome.undelivered.is_empty() {
+        warn!("设备 {} 的抽查号码 {:?} 实际未送达，放回重发队列", req.device, outcome.undelivered);
+        state.retry.extend(outcome.undelivered.iter().cloned());
+    }
+    info!(
+        "设备 {} 上传抽查凭证：通过 {} 个，未送达 {} 个，无效 {} 个，仍待上传 {} 个",
+        req.device, outcome.verified, outcome.undelivered.len(), outcome.rejected.len(), pending.len()
+    );
+    Ok(Json(VerifyData { outcome, pending }))
+}
+
+#[derive(Debug, Serialize)]
+struct PendingCheckEntry {
+    device: String,
+    #[serde(flatten)]
+    check: verify::PendingCheck,
+}
+
+// 处理 /verify/pending 请求，分页列出各设备待上传凭证的抽查号码
+async fn verify_list_handler(
+    Query(page): Query<PageParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<PageData<PendingCheckEntry>>, StatusCode> {
+    let state = state.lock().unwrap();
+    let verifier = state.verifier.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let entries = verifier
+        .list()
+        .into_iter()
+        .map(|(device, check)| PendingCheckEntry { device: device.to_string(), check: check.clone() });
+    Ok(Json(page.paginate(entries)))
+}
+
+#[derive(Debug, Deserialize)]
+struct HeartbeatRequest {
+    device: String,
+    // 设备状态，如 {"battery": 80}
+    #[serde(default)]
+    meta: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct HeartbeatData {
+    device: String,
+    // 服务端排空中，设备处理完手上的批次后不必再取号
+    draining: bool,
+}
+
+// 处理 /heartbeat 请求，记录设备在线及上报的状态；严格模式下未注册的设备返回 403
+async fn heartbeat_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<HeartbeatRequest>,
+) -> Result<Json<HeartbeatData>, StatusCode> {
+    let mut state = state.lock().unwrap();
+    if state.devices.strict() && !state.devices.is_registered(&req.device) {
+        warn!("严格模式下拒绝未注册设备 {} 的心跳", req.device);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    debug!("设备 {} 心跳: {:?}", req.device, req.meta);
+    state.devices.record_heartbeat(&req.device, req.meta, clock::now());
+    Ok(Json(HeartbeatData { device: req.device, draining: state.draining }))
+}
+
+#[derive(Debug, Deserialize)]
+struct RepliesRequest {
+    device: Option<String>,
+    replies: Vec<ReplyItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplyItem {
+    number: String,
+    text: String,
+    // 设备收到回复的时间，默认为上传时间
+    at: Option<jiff::Timestamp>,
+}
+
+#[derive(Debug, Serialize)]
+struct RepliesData {
+    received: usize,
+    // 命中规则的回复数
+    matched: usize,
+    opted_out: Vec<String>,
+}
+
+// 处理 /replies 上传，按关键词规则打标签，命中退订规则的号码加入黑名单并停止下发，需要时推送 reply_alert
+async fn replies_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<RepliesRequest>,
+) -> Json<RepliesData> {
+    let mut state = state.lock().unwrap();
+    let now = clock::now();
+    let mut data = RepliesData { received: req.replies.len(), matched: 0, opted_out: Vec::new() };
+    for item in req.replies {
+        let number = import::normalize(&item.number).unwrap_or(item.number);
+        let reply = state.replies.ingest(&number, &item.text, req.device.clone(), item.at.unwrap_or(now));
+        if reply.rules.is_empty() {
+            continue;
+        }
+        data.matched += 1;
+        info!("号码 {} 的回复命中规则 {:?}，标签 {:?}", number, reply.rules, reply.tags);
+        if reply.opt_out && state.opt_out(&number) {
+            let actor = Actor { operator: Some(format!("reply:{}", reply.rules.join(","))), reason: Some(reply.text.clone()) };
+            state.log_change("opt_out", std::slice::from_ref(&number), actor);
+            data.opted_out.push(number.clone());
+        }
+        if reply.alert {
+            state.notify("reply_alert", serde_json::json!(reply));
+        }
+    }
+    if !data.opted_out.is_empty() {
+        info!("回复退订 {} 个号码，当前黑名单 {} 个", data.opted_out.len(), state.blacklist.len());
+    }
+    Json(data)
+}
+
+#[derive(Debug, Deserialize)]
+struct ConversationParams {
+    tag: Option<String>,
+    number: Option<String>,
+}
+
+// 处理 GET /replies 请求，分页列出有回复的会话（最近回复在前），可按标签或号码过滤
+async fn conversations_handler(
+    Query(page): Query<PageParams>,
+    Query(params): Query<ConversationParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Json<PageData<replies::Conversation>> {
+    let state = state.lock().unwrap();
+    let entries = state
+        .replies
+        .list()
+        .into_iter()
+        .filter(|c| params.tag.as_ref().is_none_or(|t| c.tags.contains(t)))
+        .filter(|c| params.number.as_ref().is_none_or(|n| &c.number == n))
+        .cloned();
+    Json(page.paginate(entries))
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterRequest {
+    udid: String,
+    ios_version: Option<String>,
+    // SIM 卡运营商
+    carrier: Option<String>,
+    // 设备声明的每天最多发送数
+    max_daily: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct RegisterData {
+    // 之后 /fetch、/heartbeat 中使用的设备号
+    device_id: String,
+    // 设备所在的分组，/fetch 只下发该分组的号码；按 UDID 计算，重复注册和重启后不变
+    shard: usize,
+    // 每天最多下发的号码数，不限制时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily_quota: Option<usize>,
+}
+
+// 处理 /devices/register 请求，设备声明 UDID、系统版本、运营商和每日容量，分配设备号、配额和分组
+// 严格模式下 UDID 不在允许列表中时返回 403
+async fn register_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<RegisterRequest>,
+) -> Result<Json<RegisterData>, StatusCode> {
+    let mut state = state.lock().unwrap();
+    let registered = state.devices.register(&req.udid, req.ios_version, req.carrier, req.max_daily, clock::now());
+    let Ok((device_id, registration)) = registered else {
+        warn!("设备 {} 不在允许列表中，拒绝注册", req.udid);
+        return Err(StatusCode::FORBIDDEN);
+    };
+    let daily_quota = state.devices.daily_quota(&device_id);
+    info!(
+        "设备注册 {} => {}，iOS {:?}，运营商 {:?}，分组 {}，每日配额 {:?}",
+        req.udid, device_id, registration.ios_version, registration.carrier, registration.shard, daily_quota
+    );
+    Ok(Json(RegisterData { device_id, shard: registration.shard, daily_quota }))
+}
+
+#[derive(Debug, Serialize)]
+struct StatusData {
+    total: usize,
+    served: usize,
+    remaining: usize,
+    held: usize,
+    deferred: usize,
+    retry: usize,
+    // 按活动轮流分配时排队等待所属活动的号码数
+    queued: usize,
+    // 优先池中尚未下发的号码数
+    priority: usize,
+    // 未确认的批次数
+    outstanding: usize,
+    // 多台设备上反复失败而被隔离的号码数
+    quarantined: usize,
+    // 软删除的号码数
+    deleted: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draining: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    maintenance: bool,
+    // 内置短链的点击统计
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clicks: Option<shortener::ClickStats>,
+    // 管理版本号，每次成功的管理修改后加一
+    version: u64,
+}
+
+// 处理 /status 请求，返回活动进度报告，ETag 为当前的管理版本号
+async fn status_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Response {
+    let status = state.lock().unwrap().status_data();
+    ([(header::ETAG, etag(status.version))], Json(status)).into_response()
+}
+
+fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+#[derive(Debug, Serialize)]
+struct ConflictError {
+    error: &'static str,
+    message: String,
+    // 当前的管理版本号
+    version: u64,
+}
+
+// 管理修改的乐观并发控制：请求须带 If-Match（GET /status 的 ETag，"*" 表示不检查），不带时返回 428；
+// 版本号已被其他操作员的修改改变时返回 409，而不是覆盖对方的修改；修改成功后版本号加一并在 ETag 中返回
+// 后台执行的修改（返回 202 的 /import、/archive）在任务完成写入时再加一，见 run_import_job、archive_handler
+async fn version_guard(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // 检查版本号到修改完成期间不允许其他修改，避免两个请求都通过检查
+    let lock = state.lock().unwrap().admin_lock.clone();
+    let _guard = lock.lock().await;
+    let current = state.lock().unwrap().version;
+    let expected = request
+        .headers()
+        .get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().trim_start_matches("W/").trim_matches('"').to_string());
+    let Some(expected) = expected else {
+        warn!("{} {} 未带 If-Match，已拒绝", request.method(), request.uri().path());
+        let body = ConflictError {
+            error: "precondition_required",
+            message: "If-Match header with the ETag from GET /status is required".to_string(),
+            version: current,
+        };
+        return (StatusCode::PRECONDITION_REQUIRED, [(header::ETAG, etag(current))], Json(body)).into_response();
+    };
+    if expected != "*" && expected != current.to_string() {
+        warn!("{} {} 的版本号 {} 已过期（当前 {}），已拒绝", request.method(), request.uri().path(), expected, current);
+        let body = ConflictError {
+            error: "conflict",
+            message: "State was modified by another request, reload and retry".to_string(),
+            version: current,
+        };
+        return (StatusCode::CONFLICT, [(header::ETAG, etag(current))], Json(body)).into_response();
+    }
+
+    let mut response = next.run(request).await;
+    let version = {
+        let mut state = state.lock().unwrap();
+        if response.status().is_success() && response.status() != StatusCode::ACCEPTED {
+            state.version += 1;
+        }
+        state.version
+    };
+    if let Ok(value) = header::HeaderValue::from_str(&etag(version)) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    response
+}
+
+// 处理 /status/config 请求，返回启动摘要：加载的文件、去重结果、启用的功能、配额和计划
+async fn status_config_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<summary::Summary>, StatusCode> {
+    state.lock().unwrap().summary.clone().map(Json).ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+#[derive(Debug, Deserialize)]
+struct UsageParams {
+    // 如 2026-10，默认为当月
+    month: Option<String>,
+    // json（默认）或 csv
+    format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct UsageData {
+    month: String,
+    tenants: Vec<usage::Record>,
+}
+
+// 处理 /usage 请求，导出指定月份的用量，用于按租户计费；租户密钥只能查看自己的用量，admin_key 查看所有租户
+// 未配置 [tenants] 时返回 404，没有 X-Api-Key 或密钥未配置时返回 401
+async fn usage_handler(
+    headers: HeaderMap,
+    Query(params): Query<UsageParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Response {
+    let month = params.month.unwrap_or_else(|| usage::month(clock::now()));
+    let tenants = {
+        let state = state.lock().unwrap();
+        let Some(config) = &state.tenants else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+        let mut tenants = state.usage.month(&month);
+        if !config.is_admin(key) {
+            let Some(tenant) = config.tenant(key) else {
+                warn!("查看用量时缺少或使用了未知的 X-Api-Key，已拒绝");
+                return StatusCode::UNAUTHORIZED.into_response();
+            };
+            tenants.retain(|r| r.tenant == tenant);
+        }
+        tenants
+    };
+    if params.format.as_deref() != Some("csv") {
+        return Json(UsageData { month, tenants }).into_response();
+    }
+    let mut csv = String::from("month,tenant,issued,rendered,exports\n");
+    for r in &tenants {
+        csv.push_str(&format!("{},{},{},{},{}\n", r.month, r.tenant, r.counts.issued, r.counts.rendered, r.counts.exports));
+    }
+    let disposition = format!("attachment; filename=\"usage-{}.csv\"", month);
+    ([(header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()), (header::CONTENT_DISPOSITION, disposition)], csv)
+        .into_response()
+}
+
+#[derive(Debug, Serialize)]
+struct MaintenanceError {
+    error: &'static str,
+    message: String,
+}
+
+// 维护模式下拦截设备接口
+async fn maintenance_guard(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let maintenance = {
+        let state = state.lock().unwrap();
+        state.maintenance.then(|| state.strings.maintenance.clone())
+    };
+    if let Some(message) = maintenance {
+        let body = MaintenanceError { error: "maintenance", message };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    }
+    next.run(request).await
+}
+
+#[derive(Debug, Serialize)]
+struct OverloadError {
+    error: &'static str,
+    message: String,
+    // 当前处理中的设备请求数
+    queue_depth: usize,
+    retry_after: u64,
+}
+
+// 过载保护中间件的状态，提示文字随之传入，拒绝时不再等待状态锁
+type ShedState = (Arc<Mutex<AppState>>, Arc<shed::LoadShedder>, Arc<strings::Strings>);
+
+// 过载保护：处理中的请求超出上限或等待状态锁过久时返回 503 和 Retry-After
+async fn shed_guard(
+    axum::extract::State((state, shedder, strings)): axum::extract::State<ShedState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let uri = request.uri().clone();
+    let overloaded = |queue_depth: usize| {
+        debug!("过载，拒绝请求 {}，处理中 {} 个", uri, queue_depth);
+        let body = OverloadError {
+            error: "overloaded",
+            message: strings.overloaded.clone(),
+            queue_depth,
+            retry_after: shedder.retry_after_secs,
+        };
+        let retry_after = [(header::RETRY_AFTER, shedder.retry_after_secs.to_string())];
+        (StatusCode::SERVICE_UNAVAILABLE, retry_after, Json(body)).into_response()
+    };
+
+    let admitted = match shedder.admit() {
+        Ok(admitted) => admitted,
+        Err(depth) => return overloaded(depth),
+    };
+    // 与处理函数一样排队获取一次状态锁并计时，得到的是实际的等待时长；超过上限时拒绝，不再执行处理函数
+    let start = std::time::Instant::now();
+    drop(state.lock().unwrap());
+    if !shedder.check_wait(start.elapsed()) {
+        return overloaded(shedder.in_flight());
+    }
+    let response = next.run(request).await;
+    drop(admitted);
+    response
+}
+
+// 测试模式下按 /test/faults 的设置模拟故障：延迟、熔断、号码取完、响应损坏
+async fn fault_guard(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (faults, strings) = {
+        let state = state.lock().unwrap();
+        (state.faults.clone(), state.strings.clone())
+    };
+    if faults.latency_ms > 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(faults.latency_ms)).await;
+    }
+    if let Some(secs) = faults.breaker_remaining(clock::now()) {
+        let body = MaintenanceError { error: "circuit_open", message: strings.circuit_open.clone() };
+        let retry_after = [(header::RETRY_AFTER, secs.to_string())];
+        return (StatusCode::SERVICE_UNAVAILABLE, retry_after, Json(body)).into_response();
+    }
+    if request.uri().path() == "/fetch" {
+        if faults.malformed {
+            let body = r#"{"numbers":"13800000000,1390000"#;
+            return ([(header::CONTENT_TYPE, "application/json")], body).into_response();
+        }
+        if faults.exhausted {
+            let data = ResponseData {
+                message: strings.no_more_numbers.clone(),
+                code: Some(Code::NoMoreNumbers),
+                ..Default::default()
+            };
+            return Json(data).into_response();
+        }
+    }
+    next.run(request).await
+}
+
+// 处理 GET /test/faults 请求，查看当前注入的故障
+async fn faults_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Json<faults::Faults> {
+    Json(state.lock().unwrap().faults.clone())
+}
+
+// 处理 POST /test/faults 请求，如 {"latency_ms": 3000, "breaker_secs": 60}，未提供的字段保持不变
+async fn faults_update_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(update): Json<faults::FaultsUpdate>,
+) -> Json<faults::Faults> {
+    let mut state = state.lock().unwrap();
+    state.faults.apply(update, clock::now());
+    warn!("注入故障: {:?}", state.faults);
+    Json(state.faults.clone())
+}
+
+// 处理 DELETE /test/faults 请求，清除所有故障
+async fn faults_reset_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Json<faults::Faults> {
+    let mut state = state.lock().unwrap();
+    state.faults = faults::Faults { clock_offset_secs: clock::offset(), ..Default::default() };
+    info!("已清除注入的故障");
+    Json(state.faults.clone())
+}
+
+#[derive(Debug, Deserialize)]
+struct MaintenanceRequest {
+    enabled: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct MaintenanceData {
+    maintenance: bool,
+}
+
+// 处理 POST /maintenance 请求，开启或关闭维护模式
+async fn maintenance_handler(
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<MaintenanceRequest>,
+) -> Json<MaintenanceData> {
+    let mut state = state.lock().unwrap();
+    if state.maintenance != req.enabled {
+        state.maintenance = req.enabled;
+        if req.enabled {
+            warn!("进入维护模式，设备接口暂停服务");
+        } else {
+            info!("退出维护模式，设备接口恢复服务");
+        }
+    }
+    Json(MaintenanceData { maintenance: state.maintenance })
+}
+
+// 处理 GET /maintenance 请求，查询是否处于维护模式
+async fn maintenance_status_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Json<MaintenanceData> {
+    Json(MaintenanceData { maintenance: state.lock().unwrap().maintenance })
+}
+
+#[derive(Debug, Serialize)]
+struct DrainData {
+    draining: bool,
+    // 未确认的批次数
+    outstanding: usize,
+    // 运行中的后台任务数
+    running_jobs: usize,
+    // 无未确认批次和运行中任务，可以停止服务
+    safe_to_stop: bool,
+}
+
+// 处理 POST /drain 请求，停止下发新批次，仍接受已下发批次的 /ack
+async fn drain_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Json<DrainData> {
+    let mut state = state.lock().unwrap();
+    if !state.draining {
+        state.draining = true;
+        let drain = state.drain_data();
+        info!("开始排空：不再下发新批次，未确认批次 {} 个，运行中任务 {} 个", drain.outstanding, drain.running_jobs);
+    }
+    Json(state.drain_data())
+}
+
+// 处理 GET /drain 请求，查询排空进度，safe_to_stop 为 true 时可以停止服务
+async fn drain_status_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Json<DrainData> {
+    Json(state.lock().unwrap().drain_data())
+}
+
+// 处理 DELETE /drain 请求，取消排空，恢复下发
+async fn resume_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Json<DrainData> {
+    let mut state = state.lock().unwrap();
+    if state.draining {
+        state.draining = false;
+        info!("取消排空，恢复下发");
+    }
+    Json(state.drain_data())
+}
+
+#[derive(Debug, Serialize)]
+struct CampaignEntry {
+    name: String,
+    message_file: String,
+    message: String,
+    // 可按设备语言选择的消息
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<String>,
+    // 可在 /fetch 中用 message_id 指定的备选消息
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    messages: Vec<String>,
+    numbers: usize,
+    // 轮流分配的权重，未启用时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weight: Option<i64>,
+    // 排队等待该活动的号码数
+    queued: usize,
+    // 含该活动号码的未确认批次数
+    outstanding: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_at: Option<jiff::Timestamp>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    ended: bool,
+}
+
+// 处理 /campaigns 请求，列出各活动的消息和号码数
+async fn campaigns_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> Json<Vec<CampaignEntry>> {
+    let state = state.lock().unwrap();
+    let mut counts = vec![0; state.campaigns.len()];
+    for &idx in state.campaign_of.values() {
+        counts[idx] += 1;
+    }
+    counts[0] = state.positions.len() - state.campaign_of.len();
+    let entries = state
+        .campaigns
+        .iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(idx, (c, numbers))| CampaignEntry {
+            name: c.name.clone(),
+            message_file: c.message_file.clone(),
+            message: c.renderer.template.clone(),
+            variants: c.variants.keys().cloned().collect(),
+            messages: c.messages.keys().cloned().collect(),
+            numbers,
+            weight: state.scheduler.as_ref().map(|s| s.weights()[idx]),
+            queued: state.backlog[idx].len(),
+            outstanding: state.leases.as_ref().map_or(0, |l| l.outstanding_in(idx)),
+            end_at: c.end_at,
+            ended: c.ended,
+        })
+        .collect();
+    Json(entries)
+}
+
+// 处理 /metrics 请求，以 Prometheus 文本格式输出进度指标
+async fn metrics_handler(state: axum::extract::State<Arc<Mutex<AppState>>>) -> impl IntoResponse {
+    let state = state.lock().unwrap();
+    let status = state.status_data();
+    let now = clock::now();
+    let active_devices = state.devices.iter().filter(|d| d.status(now) == "active").count();
+    let mut series = vec![
+        ("sms_numbers_pool", "Numbers in the pool", status.total as u64),
+        ("sms_numbers_served", "Numbers sent", status.served as u64),
+        ("sms_numbers_remaining", "Numbers not yet issued", status.remaining as u64),
+        ("sms_numbers_held", "Numbers held over the segment budget", status.held as u64),
+        ("sms_numbers_deferred", "Numbers deferred outside the send window", status.deferred as u64),
+        ("sms_numbers_retry", "Numbers waiting for retry", status.retry as u64),
+        ("sms_numbers_queued", "Numbers queued for their campaign's turn", status.queued as u64),
+        ("sms_numbers_priority", "Priority numbers not yet issued", status.priority as u64),
+        ("sms_numbers_quarantined", "Quarantined numbers", status.quarantined as u64),
+        ("sms_numbers_deleted", "Soft-deleted numbers", status.deleted as u64),
+        ("sms_numbers_issued_total", "Numbers issued since start", state.issued),
+        ("sms_batches_outstanding", "Unacknowledged batches", status.outstanding as u64),
+        ("sms_devices_active", "Devices seen in the last 10 minutes", active_devices as u64),
+        ("sms_jobs_running", "Running background jobs", state.jobs.running() as u64),
+        ("sms_draining", "1 while draining", status.draining as u64),
+        ("sms_maintenance", "1 while in maintenance mode", status.maintenance as u64),
+    ];
+    if let Some(verifier) = &state.verifier {
+        series.push(("sms_verifications_passed_total", "Sampled sends verified by devices", verifier.verified));
+        series.push(("sms_verifications_undelivered_total", "Sampled sends devices confirmed undelivered", verifier.undelivered));
+        series.push(("sms_verifications_pending", "Sampled sends awaiting proof", verifier.pending_total() as u64));
+    }
+    if let Some(shedder) = &state.shedder {
+        series.push(("sms_requests_in_flight", "Device requests being processed", shedder.in_flight() as u64));
+        series.push(("sms_requests_shed_total", "Device requests rejected as overloaded", shedder.shed_count()));
+    }
+    if let Some(prerender) = &state.prerender {
+        let (hits, misses) = prerender.stats();
+        series.push(("sms_prerender_ready", "Numbers with a prerendered message", prerender.len() as u64));
+        series.push(("sms_prerender_hits_total", "Fetched numbers served from the prerender cache", hits));
+        series.push(("sms_prerender_misses_total", "Fetched numbers rendered on demand", misses));
+    }
+    if let Some(clicks) = &status.clicks {
+        series.push(("sms_link_clicks_total", "Builtin short link clicks", clicks.clicks));
+    }
+    // 以 _total 结尾的为累计值（counter），其余为当前值（gauge）
+    let body: String = series
+        .iter()
+        .map(|(name, help, value)| {
+            let kind = if name.ends_with("_total") { "counter" } else { "gauge" };
+            format!("# HELP {} {}\n# TYPE {} {}\n{} {}\n", name, help, name, kind, name, value)
+        })
+        .collect();
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+// 处理 /dashboard 请求，返回定时刷新 /status 和 /devices 的简易页面
+async fn dashboard_handler() -> Html<&'static str> {
+    Html(include_str!("dashboard.html"))
+}
+
+// 列表接口的分页和状态过滤参数
+#[derive(Debug, Deserialize)]
+struct PageParams {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    status: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct PageData<T> {
+    total: usize,
+    offset: usize,
+    limit: usize,
+    items: Vec<T>,
+}
+
+impl PageParams {
+    fn matches(&self, status: &str) -> bool {
+        self.status.as_deref().is_none_or(|s| s == status)
+    }
+
+    // 对已按状态过滤的结果分页，total 为过滤后的总数
+    fn paginate<T>(&self, items: impl Iterator<Item = T>) -> PageData<T> {
+        let mut page = self.start();
+        page.extend(items);
+        page
+    }
+
+    // 空的分页结果，之后分块 extend
+    fn start<T>(&self) -> PageData<T> {
+        PageData { total: 0, offset: self.offset, limit: self.limit.unwrap_or(100).min(1000), items: Vec::new() }
+    }
+}
+
+impl<T> PageData<T> {
+    fn extend(&mut self, items: impl Iterator<Item = T>) {
+        for item in items {
+            if self.total >= self.offset && self.items.len() < self.limit {
+                self.items.push(item);
+            }
+            self.total += 1;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum QuarantineEntry {
+    Quarantined(quarantine::QuarantinedNumber),
+    Held(HeldNumber),
+}
+
+// 处理 /quarantine 请求，列出被隔离（status=quarantined）和被暂扣（status=held）的号码及原因
+async fn quarantine_handler(
+    Query(page): Query<PageParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Json<PageData<QuarantineEntry>> {
+    let state = state.lock().unwrap();
+    let quarantined = state.quarantine.list().into_iter().map(QuarantineEntry::Quarantined);
+    let held = state.held.iter().cloned().map(QuarantineEntry::Held);
+    let entries = quarantined.chain(held).filter(|entry| {
+        page.matches(match entry {
+            QuarantineEntry::Quarantined(_) => "quarantined",
+            QuarantineEntry::Held(_) => "held",
+        })
+    });
+    Json(page.paginate(entries))
+}
+
+#[derive(Debug, Serialize)]
+struct JobAccepted {
+    job_id: String,
+}
+
+// 后台任务及遍历号码池的查询每次持锁处理的号码数
+const JOB_CHUNK: usize = 10_000;
+
+// 按活动取号时一次最多越过的其他活动号码数
+const BACKLOG_SCAN: usize = 10_000;
+
+// 超过这段时间没有设备取号时暂停预渲染
+const PRERENDER_IDLE: jiff::SignedDuration = jiff::SignedDuration::from_mins(5);
+
+// 处理 /import 请求，导入 JSON 数组或 NDJSON 格式的号码（可带附加信息），追加到号码池末尾
+// 带 async=true 时转为后台任务，立即返回任务号，进度见 /jobs/{id}
+async fn import_handler(
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    body: String,
+) -> Response {
+    let actor = Actor::from_headers(&headers);
+    if params.get("async").is_some_and(|v| v == "true" || v == "1") {
+        info!("后台导入请求体 {} 字节", body.len());
+        return spawn_job(&state, "import", |state, job| run_import_job(state, job, body, actor, None));
+    }
+
+    let runtime = state.lock().unwrap().runtime.clone();
+    let (records, mut summary) = runtime
+        .run_io(move || {
+            let mut summary = import::Summary::default();
+            let records = import::parse(&body, &mut summary);
+            (records, summary)
+        })
+        .await;
+    {
+        let mut state = state.lock().unwrap();
+        let accepted = state.import(records, &mut summary);
+        state.log_change("import", &accepted, actor);
+    }
+    log_import_summary(&summary);
+    Json(summary).into_response()
+}
+
+// 启动后台任务并返回 202 和任务号，任务结束后结果写入任务记录
+fn spawn_job<F, Fut>(state: &Arc<Mutex<AppState>>, kind: &str, run: F) -> Response
+where
+    F: FnOnce(Arc<Mutex<AppState>>, jobs::JobHandle) -> Fut,
+    Fut: Future<Output = Result<serde_json::Value, String>> + Send + 'static,
+{
+    let trace_id = trace::current();
+    let job = state.lock().unwrap().jobs.create(kind, trace_id.clone());
+    info!("创建后台任务 {}", job.id);
+    let job_id = job.id.clone();
+    // 任务单独运行，panic 时记为失败，不会一直显示为 running
+    let task = clock::spawn(trace::scope(trace_id.clone(), run(state.clone(), job.clone())));
+    let state = state.clone();
+    clock::spawn(trace::scope(trace_id, async move {
+        let result = task.await.unwrap_or_else(|e| Err(format!("job aborted: {}", e)));
+        let mut state = state.lock().unwrap();
+        match result {
+            Ok(summary) if job.is_cancelled() => {
+                info!("后台任务 {} 已取消", job.id);
+                state.jobs.cancelled(&job.id, Some(summary));
+            }
+            Ok(summary) => {
+                info!("后台任务 {} 完成", job.id);
+                state.jobs.complete(&job.id, summary);
+            }
+            Err(e) => {
+                warn!("后台任务 {} 失败: {}", job.id, e);
+                state.jobs.fail(&job.id, e);
+            }
+        }
+        if let Some(record) = state.jobs.get(&job.id) {
+            state.notify("job_finished", serde_json::json!(record));
+        }
+    }));
+    (StatusCode::ACCEPTED, Json(JobAccepted { job_id })).into_response()
+}
+
+// 后台导入：解析放到阻塞线程池，写入号码池时分批持锁并更新进度
+async fn run_import_job(
+    state: Arc<Mutex<AppState>>,
+    job: jobs::JobHandle,
+    body: String,
+    actor: Actor,
+    campaign: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let (records, mut summary) = clock::spawn_blocking(move || {
+        let mut summary = import::Summary::default();
+        let mut records = import::parse(&body, &mut summary);
+        // 未指定活动的号码归入 campaign
+        if let Some(campaign) = campaign {
+            for record in &mut records {
+                record.meta.entry("campaign".to_string()).or_insert_with(|| campaign.clone());
+            }
+        }
+        (records, summary)
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let total = records.len();
+    let mut processed = 0;
+    let mut accepted = Vec::new();
+    let mut records = records.into_iter();
+    while !job.is_cancelled() {
+        let chunk: Vec<import::Record> = records.by_ref().take(JOB_CHUNK).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        processed += chunk.len();
+        {
+            let mut state = state.lock().unwrap();
+            accepted.extend(state.import(chunk, &mut summary));
+            state.jobs.progress(&job.id, processed, total);
+        }
+        tokio::task::yield_now().await;
+    }
+
+    {
+        // 整个任务只加一次版本号，没有接受任何号码时号码池未变化，不加
+        let mut state = state.lock().unwrap();
+        if !accepted.is_empty() {
+            state.version += 1;
+        }
+        state.log_change("import", &accepted, actor);
+    }
+    log_import_summary(&summary);
+    Ok(serde_json::json!(summary))
+}
+
+fn log_import_summary(summary: &import::Summary) {
+    info!(
+        "导入号码：收到 {} 条，接受 {} 条，重复 {} 条，无效 {} 条，黑名单 {} 条",
+        summary.received, summary.accepted, summary.duplicates, summary.invalid, summary.blacklisted
+    );
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportParams {
+    status: Option<String>,
+}
+
+// 处理 /export 请求，后台把号码池及各号码状态导出为 CSV，可按 status 过滤
+async fn export_handler(
+    Query(params): Query<ExportParams>,
+    headers: HeaderMap,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Response {
+    let tenant = match state.lock().unwrap().tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(status) => return status.into_response(),
+    };
+    spawn_job(&state, "export", |state, job| async move {
+        let (file, rows) = write_numbers_csv(&state, &job, params.status.as_deref(), |_| {}).await?;
+        state.lock().unwrap().record_usage(tenant.as_deref(), |c| c.exports += 1);
+        Ok(serde_json::json!({ "file": file, "rows": rows }))
+    })
+}
+
+// 处理 /archive 请求，后台把已发送的号码归档为 CSV，并释放它们在内存中的附加信息
+async fn archive_handler(headers: HeaderMap, state: axum::extract::State<Arc<Mutex<AppState>>>) -> Response {
+    let tenant = match state.lock().unwrap().tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(status) => return status.into_response(),
+    };
+    spawn_job(&state, "archive", |state, job| async move {
+        let mut archived = Vec::new();
+        let (file, rows) = write_numbers_csv(&state, &job, Some("sent"), |entry| archived.push(entry.number.clone())).await?;
+        let mut state = state.lock().unwrap();
+        state.record_usage(tenant.as_deref(), |c| c.exports += 1);
+        for number in &archived {
+            state.meta.remove(number);
+        }
+        state.imported_generation += 1;
+        state.version += 1;
+        Ok(serde_json::json!({ "file": file, "rows": rows }))
+    })
+}
+
+// 分批持锁读取号码状态，把符合条件的号码写入导出目录下以任务号命名的 CSV，返回文件路径和行数
+async fn write_numbers_csv(
+    state: &Arc<Mutex<AppState>>,
+    job: &jobs::JobHandle,
+    status: Option<&str>,
+    mut on_row: impl FnMut(&NumberEntry),
+) -> Result<(String, usize), String> {
+    let (mut file, path) = create_export_file(state, job, "csv").await?;
+    file.write_all(b"number,position,status,batch_id\n").await.map_err(|e| e.to_string())?;
+
+    let mut rows = 0;
+    let mut from = 0;
+    while !job.is_cancelled() {
+        let (entries, total) = {
+            let mut state = state.lock().unwrap();
+            let total = state.numbers.len();
+            let entries = state.number_entries(from, from + JOB_CHUNK);
+            state.jobs.progress(&job.id, (from + JOB_CHUNK).min(total), total);
+            (entries, total)
+        };
+        let mut buf = String::new();
+        for entry in entries.iter().filter(|e| status.is_none_or(|s| s == e.status)) {
+            buf.push_str(&format!(
+                "{},{},{},{}\n",
+                entry.number,
+                entry.position,
+                entry.status,
+                entry.batch_id.as_deref().unwrap_or("")
+            ));
+            on_row(entry);
+            rows += 1;
+        }
+        file.write_all(buf.as_bytes()).await.map_err(|e| e.to_string())?;
+        from += JOB_CHUNK;
+        if from >= total {
+            break;
+        }
+    }
+    file.flush().await.map_err(|e| e.to_string())?;
+    Ok((path, rows))
+}
+
+async fn create_export_file(
+    state: &Arc<Mutex<AppState>>,
+    job: &jobs::JobHandle,
+    extension: &str,
+) -> Result<(tokio::fs::File, String), String> {
+    let dir = state.lock().unwrap().export_dir.clone();
+    let path = format!("{}/{}.{}", dir, job.id, extension);
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| format!("{}: {}", dir, e))?;
+    let file = tokio::fs::File::create(&path).await.map_err(|e| format!("{}: {}", path, e))?;
+    Ok((file, path))
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    generated_at: jiff::Timestamp,
+    status: StatusData,
+    // 各状态的号码数
+    numbers: std::collections::BTreeMap<&'static str, usize>,
+    devices: Vec<device::DeviceInfo>,
+}
+
+// 处理 /report 请求，后台统计各状态号码数并连同进度和设备统计生成 JSON 报告
+async fn report_handler(headers: HeaderMap, state: axum::extract::State<Arc<Mutex<AppState>>>) -> Response {
+    let tenant = match state.lock().unwrap().tenant(&headers) {
+        Ok(tenant) => tenant,
+        Err(status) => return status.into_response(),
+    };
+    spawn_job(&state, "report", |state, job| async move {
+        let mut counts = std::collections::BTreeMap::new();
+        let mut from = 0;
+        while !job.is_cancelled() {
+            let total = {
+                let mut state = state.lock().unwrap();
+                let total = state.numbers.len();
+                for entry in state.number_entries(from, from + JOB_CHUNK) {
+                    *counts.entry(entry.status).or_insert(0) += 1;
+                }
+                state.jobs.progress(&job.id, (from + JOB_CHUNK).min(total), total);
+                total
+            };
+            tokio::task::yield_now().await;
+            from += JOB_CHUNK;
+            if from >= total {
+                break;
+            }
+        }
+
+        let report = {
+            let state = state.lock().unwrap();
+            Report {
+                generated_at: clock::now(),
+                status: state.status_data(),
+                numbers: counts,
+                devices: state.devices.iter().cloned().collect(),
+            }
+        };
+        let (mut file, path) = create_export_file(&state, &job, "json").await?;
+        let content = serde_json::to_vec_pretty(&report).map_err(|e| e.to_string())?;
+        file.write_all(&content).await.map_err(|e| e.to_string())?;
+        file.flush().await.map_err(|e| e.to_string())?;
+        state.lock().unwrap().record_usage(tenant.as_deref(), |c| c.exports += 1);
+        Ok(serde_json::json!({ "file": path, "numbers": report.numbers }))
+    })
+}
+
+// 处理 /jobs 请求，分页列出后台任务，可按 status 过滤
+async fn jobs_handler(
+    Query(page): Query<PageParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Json<PageData<jobs::Job>> {
+    let state = state.lock().unwrap();
+    let entries = state.jobs.list().into_iter().filter(|job| page.matches(&job.status)).cloned();
+    Json(page.paginate(entries))
+}
+
+// 处理 /jobs/{id} 请求，查询后台任务的进度和结果
+async fn job_handler(
+    Path(id): Path<String>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<jobs::Job>, StatusCode> {
+    state.lock().unwrap().jobs.get(&id).cloned().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkParams {
+    // 链接有效期（秒），默认使用 [downloads] ttl_secs
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct LinkData {
+    url: String,
+    expires_at: jiff::Timestamp,
+}
+
+// 处理 /jobs/{id}/link 请求，为已完成任务的导出文件生成限时下载链接，可直接分享给客户
+// 未配置 [downloads] 时返回 404，任务不存在、未完成或没有导出文件时返回 404 / 409
+async fn job_link_handler(
+    Path(id): Path<String>,
+    Query(params): Query<LinkParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<LinkData>, StatusCode> {
+    let state = state.lock().unwrap();
+    let downloads = state.downloads.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let job = state.jobs.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let file = job
+        .summary
+        .as_ref()
+        .and_then(|summary| summary["file"].as_str())
+        .filter(|_| job.status == "completed")
+        .ok_or(StatusCode::CONFLICT)?;
+    let name = std::path::Path::new(file).file_name().and_then(|n| n.to_str()).ok_or(StatusCode::CONFLICT)?;
+    let ttl = params.ttl_secs.unwrap_or(downloads.ttl_secs);
+    let Some(expires_at) = downloads.expires_at(ttl, clock::now()) else {
+        warn!("任务 {} 的下载链接有效期 {} 秒超出上限 {} 秒", id, ttl, downloads.max_ttl_secs);
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    info!("生成任务 {} 的下载链接 {}，有效期 {} 秒", id, name, ttl);
+    Ok(Json(LinkData { url: downloads.link(name, expires_at), expires_at }))
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadParams {
+    expires: i64,
+    sig: String,
+}
+
+// 处理 /download/{name} 请求，校验签名和有效期后按块返回导出目录中的文件
+// 签名错误返回 403，链接过期返回 410
+async fn download_handler(
+    Path(name): Path<String>,
+    Query(params): Query<DownloadParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Response, StatusCode> {
+    let (downloads, dir) = {
+        let state = state.lock().unwrap();
+        (state.downloads.clone().ok_or(StatusCode::NOT_FOUND)?, state.export_dir.clone())
+    };
+    if !download::valid_name(&name) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    match downloads.verify(&name, params.expires, &params.sig, clock::now()) {
+        Ok(()) => {}
+        Err(download::Invalid::Signature) => {
+            warn!("下载链接签名错误: {}", name);
+            return Err(StatusCode::FORBIDDEN);
+        }
+        Err(download::Invalid::Expired) => return Err(StatusCode::GONE),
+    }
+    let path = format!("{}/{}", dir, name);
+    let file = tokio::fs::File::open(&path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    info!("下载导出文件 {}", path);
+    let content_type = if name.ends_with(".json") { "application/json" } else { "text/csv; charset=utf-8" };
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_string()),
+        (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name)),
+    ];
+    Ok((headers, axum::body::Body::from_stream(read_chunks(file))).into_response())
+}
+
+// 按 64KB 分块读取文件，大文件下载不占用整块内存
+fn read_chunks(
+    file: tokio::fs::File,
+) -> impl futures_util::Stream<Item = std::io::Result<Vec<u8>>> + Send + 'static {
+    stream::unfold(file, |mut file| async move {
+        let mut buf = vec![0; 64 * 1024];
+        match tokio::io::AsyncReadExt::read(&mut file, &mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), file))
+            }
+            Err(e) => Some((Err(e), file)),
+        }
+    })
+}
+
+// 处理 /jobs/{id}/cancel 请求，运行中的任务在处理下一批前停止，已结束的任务返回 409
+async fn cancel_job_handler(
+    Path(id): Path<String>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<jobs::Job>, StatusCode> {
+    match state.lock().unwrap().jobs.cancel(&id) {
+        jobs::Cancel::Requested(job) => {
+            info!("请求取消后台任务 {}", id);
+            Ok(Json(*job))
+        }
+        jobs::Cancel::Finished => Err(StatusCode::CONFLICT),
+        jobs::Cancel::NotFound => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// 管理操作的操作者和原因，取自 X-Operator、X-Reason 请求头
+struct Actor {
+    operator: Option<String>,
+    reason: Option<String>,
+}
+
+impl Actor {
+    fn from_headers(headers: &HeaderMap) -> Actor {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .map(|v| String::from_utf8_lossy(v.as_bytes()).trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        Actor { operator: header("x-operator"), reason: header("x-reason") }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangelogParams {
+    action: Option<String>,
+}
+
+// 处理 /changelog 请求，分页列出号码池的运行时变更（最新在前），可按 action 过滤
+async fn changelog_handler(
+    Query(page): Query<PageParams>,
+    Query(params): Query<ChangelogParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Json<PageData<changelog::Change>> {
+    let state = state.lock().unwrap();
+    let entries = state
+        .changelog
+        .iter()
+        .filter(|c| params.action.as_deref().is_none_or(|a| a == c.action))
+        .cloned();
+    Json(page.paginate(entries))
+}
+
+#[derive(Debug, Serialize)]
+struct NumberEntry {
+    number: String,
+    position: usize,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_id: Option<String>,
+}
+
+// 处理 /numbers 请求，分页列出号码池及各号码状态；按块遍历号码池，块之间释放锁，不阻塞取号
+async fn numbers_handler(
+    Query(page): Query<PageParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Json<PageData<NumberEntry>> {
+    let mut data = page.start();
+    let mut from = 0;
+    loop {
+        let total = {
+            let state = state.lock().unwrap();
+            data.extend(state.number_entries(from, from + JOB_CHUNK).into_iter().filter(|e| page.matches(e.status)));
+            state.numbers.len()
+        };
+        from += JOB_CHUNK;
+        if from >= total {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    Json(data)
+}
+
+#[derive(Debug, Serialize)]
+struct BatchEntry {
+    batch_id: String,
+    // outstanding 未确认 / overdue 已过期待回收
+    status: &'static str,
+    device: Option<String>,
+    count: usize,
+    issued_at: jiff::Timestamp,
+    expires_at: jiff::Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trace_id: Option<String>,
+}
+
+// 处理 /batches 请求，分页列出未确认的批次
+async fn batches_handler(
+    Query(page): Query<PageParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Json<PageData<BatchEntry>> {
+    let state = state.lock().unwrap();
+    let now = clock::now();
+    let entries = state
+        .leases
+        .iter()
+        .flat_map(|l| l.iter())
+        .map(|(id, lease)| BatchEntry {
+            batch_id: id.to_string(),
+            status: if lease.expires_at <= now { "overdue" } else { "outstanding" },
+            device: lease.device.clone(),
+            count: lease.numbers.len(),
+            issued_at: lease.issued_at,
+            expires_at: lease.expires_at,
+            trace_id: lease.trace_id.clone(),
+        })
+        .filter(|entry| page.matches(entry.status));
+    Json(page.paginate(entries))
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceEntry {
+    // active 最近 10 分钟内有请求 / idle
+    status: &'static str,
+    #[serde(flatten)]
+    info: device::DeviceInfo,
+    // 未确认的批次
+    leases: Vec<String>,
+    // 每日配额，不限制时不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily_quota: Option<usize>,
+    // 预热期间当前小时的上限，预热结束后不返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ramp_limit: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure_rate: Option<f64>,
+    // 最近一次心跳上报的电量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    battery: Option<serde_json::Value>,
+    // 设备在心跳中上报 SIM 卡被运营商封禁
+    banned: bool,
+    // 待上传凭证的抽查号码数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pending_verification: Option<usize>,
+}
+
+// 处理 /devices 请求，分页列出设备概况：心跳、当前批次、当日配额用量、失败率、电量、封禁状态
+async fn devices_handler(
+    Query(page): Query<PageParams>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Json<PageData<DeviceEntry>> {
+    let state = state.lock().unwrap();
+    let now = clock::now();
+    let mut leases: HashMap<&str, Vec<String>> = HashMap::new();
+    for (id, lease) in state.leases.iter().flat_map(|l| l.iter()) {
+        if let Some(device) = &lease.device {
+            leases.entry(device.as_str()).or_default().push(id.to_string());
+        }
+    }
+    let entries = state
+        .devices
+        .iter()
+        .map(|info| {
+            let mut info = info.clone();
+            info.issued_today = state.devices.issued_today(&info.device, now);
+            info
+        })
+        .map(|info| DeviceEntry {
+            status: info.status(now),
+            leases: leases.remove(info.device.as_str()).unwrap_or_default(),
+            daily_quota: state.devices.daily_quota(&info.device),
+            ramp_limit: state.devices.ramp_limit(&info.device, now),
+            failure_rate: info.failure_rate(),
+            battery: info.reported("battery").cloned(),
+            banned: info.reported("banned").and_then(serde_json::Value::as_bool).unwrap_or(false),
+            pending_verification: state.verifier.as_ref().map(|v| v.pending(&info.device).len()),
+            info,
+        })
+        .filter(|entry| page.matches(entry.status));
+    Json(page.paginate(entries))
+}
+
+#[derive(Debug, Deserialize)]
+struct NumbersRequest {
+    numbers: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReleaseData {
+    released: usize,
+    not_found: Vec<String>,
+}
+
+// 处理 /quarantine/release 请求，将排查过的隔离或暂扣号码放回重发队列
+async fn release_handler(
+    headers: HeaderMap,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<NumbersRequest>,
+) -> Json<ReleaseData> {
+    let mut state = state.lock().unwrap();
+    let mut released = Vec::new();
+    let mut not_found = Vec::new();
+    for number in req.numbers {
+        let was_quarantined = state.quarantine.release(&number);
+        let held_before = state.held.len();
+        state.held.retain(|h| h.number != number);
+        if was_quarantined || state.held.len() < held_before {
+            state.retry.push_back(number.clone());
+            released.push(number);
+        } else {
+            not_found.push(number);
+        }
+    }
+    info!("释放 {} 个隔离/暂扣号码回重发队列，未找到 {} 个", released.len(), not_found.len());
+    state.log_change("release", &released, Actor::from_headers(&headers));
+    Json(ReleaseData { released: released.len(), not_found })
+}
+
+#[derive(Debug, Serialize)]
+struct NumbersData {
+    updated: usize,
+    not_found: Vec<String>,
+    // 已退订（在黑名单中）而不能恢复的号码
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    blacklisted: Vec<String>,
+    // 所属活动已结束而不能恢复的号码
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    expired: Vec<String>,
+}
+
+// 处理 /numbers/delete 请求，软删除号码：不再下发，但保留在号码池中，可恢复
+async fn delete_handler(
+    headers: HeaderMap,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<NumbersRequest>,
+) -> Json<NumbersData> {
+    let mut state = state.lock().unwrap();
+    let (found, not_found): (Vec<String>, Vec<String>) =
+        req.numbers.into_iter().partition(|n| state.positions.contains_key(n));
+    let updated: Vec<String> = found.into_iter().filter(|n| state.deleted.insert(n.clone())).collect();
+    info!("软删除 {} 个号码，当前共删除 {} 个，未找到 {} 个", updated.len(), state.deleted.len(), not_found.len());
+    state.log_change("delete", &updated, Actor::from_headers(&headers));
+    Json(NumbersData { updated: updated.len(), not_found, blacklisted: Vec::new(), expired: Vec::new() })
+}
+
+// 处理 /numbers/restore 请求，恢复软删除的号码，已被跳过的号码放回重发队列；已退订或所属活动已结束的号码不能恢复
+async fn restore_handler(
+    headers: HeaderMap,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+    Json(req): Json<NumbersRequest>,
+) -> Json<NumbersData> {
+    let mut state = state.lock().unwrap();
+    let mut updated = Vec::new();
+    let mut not_found = Vec::new();
+    let mut blacklisted = Vec::new();
+    let mut expired = Vec::new();
+    for number in req.numbers {
+        if state.blacklist.contains(&number) {
+            blacklisted.push(number);
+            continue;
+        }
+        if state.deleted.contains(&number) && state.campaigns[state.campaign_index(&number)].ended {
+            expired.push(number);
+            continue;
+        }
+        if !state.deleted.remove(&number) {
+            not_found.push(number);
+            continue;
+        }
+        if state.deleted_skipped.remove(&number) {
+            state.retry.push_back(number.clone());
+        }
+        updated.push(number);
+    }
+    if !blacklisted.is_empty() {
+        warn!("{} 个号码已退订，不能恢复: {:?}", blacklisted.len(), blacklisted);
+    }
+    if !expired.is_empty() {
+        warn!("{} 个号码所属的活动已结束，不能恢复: {:?}", expired.len(), expired);
+    }
+    info!("恢复 {} 个软删除号码，当前共删除 {} 个", updated.len(), state.deleted.len());
+    state.log_change("restore", &updated, Actor::from_headers(&headers));
+    Json(NumbersData { updated: updated.len(), not_found, blacklisted, expired })
+}
+
+#[derive(Debug, Serialize)]
+struct SearchData {
+    query: String,
+    total_matches: usize,
+    matches: Vec<SearchMatch>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchMatch {
+    number: String,
+    position: usize,
+    status: &'static str,
+    // 所在的未确认批次
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_id: Option<String>,
+    // 前面还有多少个号码待下发
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ahead: Option<usize>,
+    // 按当前下发速度估算的下发时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    eta: Option<jiff::Timestamp>,
+}
+
+// 处理 /search?q= 请求，按号码片段查找号码的位置和状态
+async fn search_handler(
+    Query(params): Query<HashMap<String, String>>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<Json<SearchData>, StatusCode> {
+    let query = params.get("q").map(|q| q.trim().to_string()).unwrap_or_default();
+    if query.len() < 3 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = params.get("limit").and_then(|v| v.parse().ok()).unwrap_or(50);
+
+    // 按块遍历号码池，块之间释放锁，不阻塞取号
+    let mut total_matches = 0;
+    let mut matches = Vec::new();
+    let mut from = 0;
+    loop {
+        let total = {
+            let state = state.lock().unwrap();
+            let now = clock::now();
+            let rate = state.issue_rate(now);
+            // 本块有需要返回的号码时才建立状态索引
+            let mut index = None;
+            let len = state.numbers.len();
+            for (number, position) in state.numbers.range(from.min(len)..(from + JOB_CHUNK).min(len)).zip(from..) {
+                if !number.contains(&query) || state.positions.get(number) != Some(&position) {
+                    continue;
+                }
+                total_matches += 1;
+                if matches.len() >= limit {
+                    continue;
+                }
+
+                let index = index.get_or_insert_with(|| state.status_index());
+                let (status, batch_id) = state.number_status(index, number, position);
+                let ahead = match status {
+                    "pending" => Some(position - state.start_index + state.retry.len()),
+                    "retry" => state.retry.iter().position(|n| n == number),
+                    _ => None,
+                };
+                let eta = ahead.zip(rate).map(|(ahead, rate)| {
+                    now.checked_add(jiff::SignedDuration::from_secs_f64(ahead as f64 / rate)).unwrap_or(now)
+                });
+                matches.push(SearchMatch { number: number.clone(), position, status, batch_id, ahead, eta });
+            }
+            len
+        };
+        from += JOB_CHUNK;
+        if from >= total {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+
+    Ok(Json(SearchData { query, total_matches, matches }))
+}
+
+// 处理 /s/{code} 内置短链跳转
+async fn redirect_handler(
+    Path(code): Path<String>,
+    state: axum::extract::State<Arc<Mutex<AppState>>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let shorteners = state.lock().unwrap().builtin_shorteners();
+    for shortener in shorteners {
+        if let Some(target) = shortener.resolve(&code).await {
+            return Ok((StatusCode::FOUND, [(header::LOCATION, target)]));
+        }
+    }
+    Err(StatusCode::NOT_FOUND)
+}
+
+// 一次取号的范围：campaign、shard 为 Some 时只取该活动、该分组的号码；grants 为各活动剩余的令牌，用完的活动不再取号
+struct Draw {
+    campaign: Option<usize>,
+    shard: Option<usize>,
+    grants: Vec<usize>,
+}
+
+impl AppState {
+    // 按各活动的令牌桶限速取号，每个号码消耗所属活动的令牌，未用完的令牌归还；campaign 为 Some 时只取该活动的号码，
+    // shard 为 Some 时只取该分组的号码；reserved 为本批次已预留未确认批次名额的活动；同时返回令牌是否不足 n 个
+    fn take_paced(
+        &mut self,
+        n: usize,
+        now: jiff::Timestamp,
+        campaign: Option<usize>,
+        shard: Option<usize>,
+        priority: bool,
+        reserved: &[usize],
+    ) -> (Vec<String>, bool) {
+        // 未确认批次已达上限的活动（本批次已预留名额的除外）不取号
+        let full: Vec<bool> = (0..self.campaigns.len())
+            .map(|idx| !reserved.contains(&idx) && self.leases.as_ref().is_some_and(|l| l.is_full(idx)))
+            .collect();
+        let grants: Vec<usize> = self
+            .pacing
+            .iter_mut()
+            .enumerate()
+            .map(|(idx, bucket)| match bucket {
+                _ if full[idx] => 0,
+                Some(bucket) if campaign.is_none_or(|c| c == idx) => bucket.take(n),
+                _ => n,
+            })
+            .collect();
+        let short = grants.iter().enumerate().any(|(idx, &g)| g < n && !full[idx] && campaign.is_none_or(|c| c == idx));
+        // 可取的活动令牌都已用完时不再推进游标，避免把号码整体转入排队
+        if grants.iter().enumerate().all(|(idx, &g)| g == 0 || campaign.is_some_and(|c| c != idx)) {
+            return (Vec::new(), short);
+        }
+        let mut draw = Draw { campaign, shard, grants };
+        let mut numbers = if priority { self.take_priority(n, now, &mut draw) } else { Vec::new() };
+        if !priority || (numbers.is_empty() && self.priority_fallback) {
+            numbers = self.take_numbers(n, now, &mut draw);
+        }
+        for (idx, bucket) in self.pacing.iter_mut().enumerate() {
+            if let Some(bucket) = bucket.as_mut().filter(|_| !full[idx] && campaign.is_none_or(|c| c == idx)) {
+                bucket.refund(draw.grants[idx]);
+            }
+        }
+        (numbers, short)
+    }
+
+    // 取出最多 n 个可发送的号码：先取排队和已到时段的延后号码，再推进游标，
+    // 游标处不在时段内（或节假日限流用尽）的号码转入所属地区的延后队列
+    fn take_numbers(&mut self, n: usize, now: jiff::Timestamp, draw: &mut Draw) -> Vec<String> {
+        let mut numbers = Vec::with_capacity(n);
+        if !self.regions.any_open(now) {
+            return numbers;
+        }
+        let mut limits = self.region_limits(n, now);
+
+        // 先取按活动排队的号码（只取某个活动时只取该活动的），其他分组的号码转入所属分组的排队
+        let queued: Vec<usize> = match draw.campaign {
+            Some(c) => vec![c],
+            None => (0..self.backlog.len()).collect(),
+        };
+        for c in queued {
+            while numbers.len() < n && draw.grants[c] > 0 {
+                let Some(number) = self.backlog[c].pop_front() else { break };
+                if self.suppressed(&number) {
+                    self.deleted_skipped.insert(number);
+                    continue;
+                }
+                let Some(number) = self.keep_for(number, draw) else { continue };
+                self.accept(number, &mut limits, &mut numbers, draw);
+            }
+        }
+        // 再取按分组排队的号码（只取某个分组时只取该分组的）
+        let queued: Vec<usize> = match draw.shard {
+            Some(s) => vec![s],
+            None => (0..self.shard_backlog.len()).collect(),
+        };
+        for s in queued {
+            let mut left = self.shard_backlog[s].len();
+            while numbers.len() < n && left > 0 {
+                let Some(number) = self.shard_backlog[s].pop_front() else { break };
+                left -= 1;
+                if self.suppressed(&number) {
+                    self.deleted_skipped.insert(number);
+                    continue;
+                }
+                let Some(number) = self.keep_for(number, draw) else { continue };
+                self.accept(number, &mut limits, &mut numbers, draw);
+            }
+        }
+
+        for idx in 0..limits.len() {
+            while numbers.len() < n && limits[idx] > 0 {
+                let Some(number) = self.deferred[idx].pop_front() else { break };
+                if self.suppressed(&number) {
+                    self.deleted_skipped.insert(number);
+                    continue;
+                }
+                let Some(number) = self.keep_for(number, draw) else { continue };
+                self.accept(number, &mut limits, &mut numbers, draw);
+            }
+        }
+
+        // 按重发策略在重发队列和新号码之间分配空位，一方取完后由另一方补足
+        let retry_quota = numbers.len() + self.retry_policy.retry_quota(n - numbers.len());
+        self.take_retry(retry_quota, &mut limits, &mut numbers, draw);
+        let newly_deferred = self.take_fresh(n, &mut limits, &mut numbers, draw);
+        self.take_retry(n, &mut limits, &mut numbers, draw);
+
+        if newly_deferred > 0 {
+            info!("{} 个号码不在本地发送时段或节假日限流，已延后，当前共延后 {} 个", newly_deferred, self.deferred_count());
+        }
+        numbers
+    }
+
+    // 号码所在地区本批还有空位时下发并消耗所属活动的令牌，否则转入延后队列；返回是否下发
+    fn accept(&mut self, number: String, limits: &mut [usize], numbers: &mut Vec<String>, draw: &mut Draw) -> bool {
+        let idx = self.regions.classify(&number);
+        if limits[idx] == 0 {
+            debug!("号码 {} 所在地区 {} 当前不可发送，延后", number, self.regions.get(idx).name);
+            self.deferred[idx].push_back(number);
+            return false;
+        }
+        limits[idx] -= 1;
+        self.throttle_usage[idx].1 += 1;
+        draw.grants[self.campaign_index(&number)] -= 1;
+        numbers.push(number);
+        true
+    }
+
+    // 本批各地区最多可取的数量
+    fn region_limits(&mut self, n: usize, now: jiff::Timestamp) -> Vec<usize> {
+        let hour = now.as_second() / 3600;
+        (0..self.regions.len())
+            .map(|idx| match self.regions.availability(idx, now) {
+                Availability::Open => n,
+                Availability::Closed => 0,
+                Availability::Throttled(per_hour) => {
+                    let (bucket, used) = &mut self.throttle_usage[idx];
+                    if *bucket != hour {
+                        *bucket = hour;
+                        *used = 0;
+                    }
+                    per_hour.saturating_sub(*used)
+                }
+            })
+            .collect()
+    }
+
+    // 从优先池取出最多 n 个号码，不在发送时段、所属活动令牌已用完或属于其他分组的号码留在优先池中
+    fn take_priority(&mut self, n: usize, now: jiff::Timestamp, draw: &mut Draw) -> Vec<String> {
+        let mut numbers = Vec::new();
+        if !self.regions.any_open(now) {
+            return numbers;
+        }
+        let mut limits = self.region_limits(n, now);
+        let mut waiting = Vec::new();
+        while numbers.len() < n {
+            let Some(number) = self.priority.pop_front() else { break };
+            // 已由游标按普通顺序下发，或已软删除、退订（游标到达时处理）
+            if !self.priority_pending.contains(&number) || self.suppressed(&number) {
+                continue;
+            }
+            let idx = self.regions.classify(&number);
+            let own = self.campaign_index(&number);
+            let other_shard = draw.shard.is_some_and(|s| self.shard_of(&number) != s);
+            if limits[idx] == 0 || draw.grants[own] == 0 || other_shard {
+                waiting.push(number);
+                continue;
+            }
+            limits[idx] -= 1;
+            self.throttle_usage[idx].1 += 1;
+            draw.grants[own] -= 1;
+            self.priority_pending.remove(&number);
+            self.priority_taken.insert(number.clone());
+            numbers.push(number);
+        }
+        for number in waiting.into_iter().rev() {
+            self.priority.push_front(number);
+        }
+        numbers
+    }
+
+    // 从重发队列取号，直到 numbers 达到 until 个
+    fn take_retry(&mut self, until: usize, limits: &mut [usize], numbers: &mut Vec<String>, draw: &mut Draw) {
+        while numbers.len() < until {
+            let Some(number) = self.retry.pop_front() else { break };
+            if self.suppressed(&number) {
+                self.deleted_skipped.insert(number);
+                continue;
+            }
+            let Some(number) = self.keep_for(number, draw) else { continue };
+            self.accept(number, limits, numbers, draw);
+        }
+    }
+
+    // 推进游标取新号码，直到 numbers 达到 until 个，返回新延后的数量
+    // 所有地区本批都已取满时不再推进游标，避免把剩余号码整体转入延后队列
+    // 只取某个活动或分组时，最多越过 BACKLOG_SCAN 个其他活动或分组的号码，避免一次请求把整个号码池转入排队
+    fn take_fresh(&mut self, until: usize, limits: &mut [usize], numbers: &mut Vec<String>, draw: &mut Draw) -> usize {
+        let mut newly_deferred = 0;
+        let mut skipped = 0;
+        while numbers.len() < until
+            && self.start_index < self.numbers.len()
+            && limits.iter().any(|&l| l > 0)
+            && skipped < BACKLOG_SCAN
+        {
+            let position = self.start_index;
+            let number = self.numbers[position].clone();
+            self.start_index += 1;
+            // 同一号码只在首次出现的位置下发
+            if self.positions.get(&number) != Some(&position) {
+                self.duplicates_skipped += 1;
+                continue;
+            }
+            self.priority_pending.remove(&number);
+            if self.priority_taken.remove(&number) {
+                continue;
+            }
+            if self.suppressed(&number) {
+                self.deleted_skipped.insert(number);
+                continue;
+            }
+            let Some(number) = self.keep_for(number, draw) else {
+                skipped += 1;
+                continue;
+            };
+            if !self.accept(number, limits, numbers, draw) {
+                newly_deferred += 1;
+            }
+        }
+        newly_deferred
+    }
+
+    // 追加导入的号码，跳过黑名单和已存在的号码，返回接受的号码
+    fn import(&mut self, records: Vec<import::Record>, summary: &mut import::Summary) -> Vec<String> {
+        let mut accepted = Vec::new();
+        for mut record in records {
+            let campaign = match record.meta.remove("campaign") {
+                Some(name) => match campaign::find(&self.campaigns, &name) {
+                    Some(idx) if self.campaigns[idx].ended => {
+                        summary.invalid += 1;
+                        summary.error(format!("{}: campaign {:?} has ended", record.number, name));
+                        continue;
+                    }
+                    Some(idx) => idx,
+                    None => {
+                        summary.invalid += 1;
+                        summary.error(format!("{}: unknown campaign {:?}", record.number, name));
+                        continue;
+                    }
+                },
+                None => 0,
+            };
+            let priority = match record.meta.remove("priority").as_deref() {
+                Some("high") => true,
+                None | Some("normal") => false,
+                Some(other) => {
+                    summary.invalid += 1;
+                    summary.error(format!("{}: unknown priority {:?}", record.number, other));
+                    continue;
+                }
+            };
+            if self.blacklist.contains(&record.number) {
+                summary.blacklisted += 1;
+                continue;
+            }
+            if self.positions.contains_key(&record.number) {
+                summary.duplicates += 1;
+                continue;
+            }
+            self.positions.insert(record.number.clone(), self.numbers.len());
+            if campaign > 0 {
+                self.campaign_of.insert(record.number.clone(), campaign);
+            }
+            if priority {
+                self.priority.push_back(record.number.clone());
+                self.priority_pending.insert(record.number.clone());
+            }
+            if !record.meta.is_empty() {
+                self.meta.insert(record.number.clone(), record.meta);
+            }
+            accepted.push(record.number.clone());
+            self.numbers.push_back(record.number);
+            summary.accepted += 1;
+        }
+        accepted
+    }
+
+    // 活动到期：尚未下发的号码（游标之后、重发、延后、排队中）软删除不再下发，返回这些号码
+    fn end_campaign(&mut self, idx: usize) -> Vec<String> {
+        self.campaigns[idx].ended = true;
+        let candidates: Vec<String> = self
+            .numbers
+            .range(self.start_index..)
+            .chain(&self.retry)
+            .chain(self.deferred.iter().flatten())
+            .chain(self.queued())
+            .filter(|number| self.campaign_index(number) == idx && !self.priority_taken.contains(*number))
+            .cloned()
+            .collect();
+        candidates.into_iter().filter(|number| self.deleted.insert(number.clone())).collect()
+    }
+
+    // 退订号码：加入黑名单，在号码池中的软删除不再下发，返回是否新退订
+    // 软删除或已退订（在黑名单中）的号码不再下发；退订的号码同时软删除，取号时再检查黑名单以防被恢复
+    fn suppressed(&self, number: &str) -> bool {
+        self.deleted.contains(number) || self.blacklist.contains(number)
+    }
+
+    fn opt_out(&mut self, number: &str) -> bool {
+        if !self.blacklist.insert(number.to_string()) {
+            return false;
+        }
+        if self.positions.contains_key(number) {
+            self.deleted.insert(number.to_string());
+        }
+        true
+    }
+
+    // 记录号码池变更，附带当前请求的追踪号
+    fn log_change(&mut self, action: &str, numbers: &[String], actor: Actor) {
+        self.changelog.record(action, numbers, actor.operator, actor.reason, trace::current());
+    }
+
+    // 是否需要预渲染：最近有设备取号，且未排空、未维护、有地区在发送时段内、还有未结束的活动；
+    // 否则不提前生成短链，避免为不会很快下发的号码消耗外部短链服务的配额
+    fn prerender_wanted(&self, now: jiff::Timestamp) -> bool {
+        let active = self.last_fetch.is_some_and(|at| now.duration_since(at) <= PRERENDER_IDLE);
+        active
+            && !self.draining
+            && !self.maintenance
+            && self.regions.any_open(now)
+            && self.campaigns.iter().any(|c| !c.is_over(now))
+    }
+
+    // 接下来可能下发的号码及其默认语言的消息：先重发队列，再游标之后的号码
+    fn upcoming(&self, limit: usize, now: jiff::Timestamp) -> Vec<prerender::Upcoming> {
+        let fresh = self
+            .numbers
+            .iter()
+            .enumerate()
+            .skip(self.start_index)
+            .filter(|(position, number)| self.positions.get(*number) == Some(position))
+            .map(|(_, number)| number);
+        self.retry
+            .iter()
+            .chain(fresh)
+            .filter(|number| !self.suppressed(number) && !self.priority_taken.contains(*number))
+            .filter(|number| !self.campaigns[self.campaign_index(number)].is_over(now))
+            .take(limit)
+            .map(|number| {
+                let renderer = self.campaigns[self.campaign_index(number)].renderer.clone();
+                let meta = self.meta.get(number).filter(|_| self.features.personalization).cloned();
+                (number.clone(), renderer, meta)
+            })
+            .collect()
+    }
+
+    fn campaign_index(&self, number: &str) -> usize {
+        self.campaign_of.get(number).copied().unwrap_or(0)
+    }
+
+    // 号码所属的活动，去重后按序号排列
+    fn campaigns_of(&self, numbers: &[String]) -> Vec<usize> {
+        let mut campaigns: Vec<usize> = numbers.iter().map(|n| self.campaign_index(n)).collect();
+        campaigns.sort_unstable();
+        campaigns.dedup();
+        campaigns
+    }
+
+    // 只取某个活动时其他活动的号码，以及所属活动令牌已用完的号码，转入所属活动的排队；
+    // 只取某个分组时其他分组的号码转入所属分组的排队；返回 None
+    fn keep_for(&mut self, number: String, draw: &Draw) -> Option<String> {
+        let own = self.campaign_index(&number);
+        if draw.campaign.is_some_and(|c| c != own) || draw.grants[own] == 0 {
+            self.backlog[own].push_back(number);
+            return None;
+        }
+        let shard = self.shard_of(&number);
+        if draw.shard.is_some_and(|s| s != shard) {
+            self.shard_backlog[shard].push_back(number);
+            return None;
+        }
+        Some(number)
+    }
+
+    // 号码所属的分组，未分组时为 0
+    fn shard_of(&self, number: &str) -> usize {
+        device::shard_of(number, self.shard_backlog.len())
+    }
+
+    // 按活动和按分组排队的号码
+    fn queued(&self) -> impl Iterator<Item = &String> {
+        self.backlog.iter().flatten().chain(self.shard_backlog.iter().flatten())
+    }
+
+    fn backlog_count(&self) -> usize {
+        self.backlog.iter().chain(&self.shard_backlog).map(VecDeque::len).sum()
+    }
+
+    // 各活动使用的内置短链服务，多个活动共用同一个时只返回一次
+    fn builtin_shorteners(&self) -> Vec<Arc<shortener::Shortener>> {
+        let mut shorteners: Vec<Arc<shortener::Shortener>> = Vec::new();
+        for shortener in self.campaigns.iter().filter_map(|c| c.renderer.shortener.as_ref()) {
+            if shortener.is_builtin() && !shorteners.iter().any(|s| Arc::ptr_eq(s, shortener)) {
+                shorteners.push(shortener.clone());
+            }
+        }
+        shorteners
+    }
+
+    fn status_data(&self) -> StatusData {
+        let shorteners = self.builtin_shorteners();
+        let deferred = self.deferred_count();
+        StatusData {
+            total: self.positions.len(),
+            served: self.served(),
+            remaining: self.remaining(),
+            priority: self.priority_pending.len(),
+            held: self.held.len(),
+            deferred,
+            retry: self.retry.len(),
+            queued: self.backlog_count(),
+            outstanding: self.leases.as_ref().map_or(0, |l| l.outstanding()),
+            quarantined: self.quarantine.len(),
+            deleted: self.deleted.len(),
+            draining: self.draining,
+            maintenance: self.maintenance,
+            clicks: (!shorteners.is_empty()).then(|| shortener::ClickStats::sum(shorteners.iter().map(|s| s.click_stats()))),
+            version: self.version,
+        }
+    }
+
+    // 请求所属的租户：未配置 [tenants] 时为 None，没有 X-Api-Key 或密钥未配置时返回 401
+    fn tenant(&self, headers: &HeaderMap) -> Result<Option<String>, StatusCode> {
+        let Some(tenants) = &self.tenants else { return Ok(None) };
+        let key = headers.get("x-api-key").and_then(|v| v.to_str().ok());
+        match tenants.tenant(key) {
+            Some(tenant) => Ok(Some(tenant.to_string())),
+            None => {
+                warn!("缺少或未知的 X-Api-Key，已拒绝");
+                Err(StatusCode::UNAUTHORIZED)
+            }
+        }
+    }
+
+    fn record_usage(&mut self, tenant: Option<&str>, update: impl FnOnce(&mut usage::Counts)) {
+        if let Some(tenant) = tenant {
+            self.usage.record(tenant, clock::now(), update);
+        }
+    }
+
+    fn notify(&self, event: &str, data: serde_json::Value) {
+        if let Some(notifier) = &self.notifier {
+            notifier.dispatch(event, data);
+        }
+    }
+
+    fn drain_data(&self) -> DrainData {
+        let outstanding = self.leases.as_ref().map_or(0, |l| l.outstanding());
+        let running_jobs = self.jobs.running();
+        DrainData {
+            draining: self.draining,
+            outstanding,
+            running_jobs,
+            safe_to_stop: self.draining && outstanding == 0 && running_jobs == 0,
+        }
+    }
+
+    // 号码池中 [from, to) 位置的号码及状态，重复出现的号码只取首次位置
+    fn number_entries(&self, from: usize, to: usize) -> Vec<NumberEntry> {
+        let index = self.status_index();
+        let len = self.numbers.len();
+        self.numbers
+            .range(from.min(len)..to.min(len))
+            .zip(from..)
+            .filter(|(number, position)| self.positions.get(*number) == Some(position))
+            .map(|(number, position)| {
+                let (status, batch_id) = self.number_status(&index, number, position);
+                NumberEntry { number: number.clone(), position, status, batch_id }
+            })
+            .collect()
+    }
+
+    fn status_index(&self) -> StatusIndex<'_> {
+        let mut leased = HashMap::new();
+        for (id, lease) in self.leases.iter().flat_map(|l| l.iter()) {
+            for number in &lease.numbers {
+                leased.insert(number.as_str(), id);
+            }
+        }
+        StatusIndex {
+            held: self.held.iter().map(|h| h.number.as_str()).collect(),
+            leased,
+            retry: self.retry.iter().map(String::as_str).collect(),
+            deferred: self.deferred.iter().flatten().map(String::as_str).collect(),
+            backlog: self.queued().map(String::as_str).collect(),
+        }
+    }
+
+    // 号码当前状态，以及所在的未确认批次
+    fn number_status(&self, index: &StatusIndex, number: &str, position: usize) -> (&'static str, Option<String>) {
+        if self.deleted.contains(number) {
+            return ("deleted", None);
+        }
+        if self.quarantine.is_quarantined(number) {
+            return ("quarantined", None);
+        }
+        if index.held.contains(number) {
+            return ("held", None);
+        }
+        if let Some(id) = index.leased.get(number) {
+            return ("leased", Some(id.to_string()));
+        }
+        if index.retry.contains(number) {
+            return ("retry", None);
+        }
+        if index.deferred.contains(number) {
+            return ("deferred", None);
+        }
+        if index.backlog.contains(number) {
+            return ("queued", None);
+        }
+        if position >= self.start_index && !self.priority_taken.contains(number) {
+            ("pending", None)
+        } else {
+            ("sent", None)
+        }
+    }
+
+    // 自首次下发以来的平均下发速度（个/秒）
+    fn issue_rate(&self, now: jiff::Timestamp) -> Option<f64> {
+        let elapsed = now.duration_since(self.first_issued_at?).as_secs_f64();
+        (elapsed >= 1.0 && self.issued > 0).then(|| self.issued as f64 / elapsed)
+    }
+
+    // 游标越过的不重复号码数：越过的位置中除去重复号码的后续位置
+    fn passed(&self) -> usize {
+        self.start_index - self.duplicates_skipped
+    }
+
+    // 已下发的号码：游标越过的不重复号码，扣除仍在各队列中等待、被暂扣和因软删除被跳过的，加上经优先池提前下发的；
+    // 隔离的号码已下发过，仍计入。队列中的号码都来自游标或优先池且互不重复，结果不会小于 0
+    fn served(&self) -> usize {
+        self.passed() + self.priority_taken.len()
+            - self.deferred_count()
+            - self.held.len()
+            - self.retry.len()
+            - self.backlog_count()
+            - self.deleted_skipped.len()
+    }
+
+    // 尚未下发的号码：游标之后未经优先池提前下发的不重复号码加上延后、待重发和排队中的号码，均不含软删除（含退订）的，
+    // 这些号码取号时会被跳过
+    fn remaining(&self) -> usize {
+        let ahead = self.positions.len() - self.passed() - self.priority_taken.len();
+        let deleted_ahead = self
+            .deleted
+            .iter()
+            .filter(|number| {
+                self.positions.get(*number).is_some_and(|&position| position >= self.start_index)
+                    && !self.priority_taken.contains(*number)
+            })
+            .count();
+        let queued = self
+            .retry
+            .iter()
+            .chain(self.deferred.iter().flatten())
+            .chain(self.queued())
+            .filter(|number| !self.suppressed(number))
+            .count();
+        ahead - deleted_ahead + queued
+    }
+
+    // 回收过期租约中的号码到重发队列
+    fn reclaim_expired(&mut self, now: jiff::Timestamp) {
+        let Some(leases) = &mut self.leases else { return };
+        for (id, lease) in leases.reclaim_expired(now) {
+            warn!(
+                "批次 {} 租约过期未确认（设备 {:?}，追踪号 {:?}），回收 {} 个号码重发",
+                id, lease.device, lease.trace_id, lease.numbers.len()
+            );
+            if let (Some(verifier), Some(device)) = (&mut self.verifier, &lease.device) {
+                verifier.cancel(device, &lease.numbers);
+            }
+            self.retry.extend(lease.numbers);
+        }
+    }
+
+    fn deferred_count(&self) -> usize {
+        self.deferred.iter().map(VecDeque::len).sum()
+    }
+
+    // 需要持久化的进度，暂扣和排队中的号码并入重发队列，恢复后重新判断
+    // 保存进度所需的状态，直接引用各队列，序列化时不复制
+    fn progress(&self) -> store::Progress {
+        let retry = self.retry.iter().chain(self.queued()).chain(self.held.iter().map(|h| &h.number));
+        store::Progress {
+            total: self.numbers.len(),
+            base: self.loaded,
+            cursor: self.start_index,
+            retry: retry.cloned().collect(),
+            deferred: self.deferred.iter().flatten().cloned().collect(),
+            leases: self
+                .leases
+                .iter()
+                .flat_map(|l| l.iter())
+                .map(|(id, lease)| store::LeaseRecord {
+                    batch_id: id.to_string(),
+                    numbers: lease.numbers.clone(),
+                    device: lease.device.clone(),
+                    issued_at: lease.issued_at,
+                    expires_at: lease.expires_at,
+                })
+                .collect(),
+            deleted: self.deleted.iter().cloned().collect(),
+            deleted_skipped: self.deleted_skipped.iter().cloned().collect(),
+            issued: self.issued,
+            batches: self.batches,
+            first_issued_at: self.first_issued_at,
+            usage: self.usage.records(),
+            version: self.version,
+            priority: self.priority.iter().cloned().collect(),
+            priority_pending: self.priority_pending.iter().cloned().collect(),
+            priority_taken: self.priority_taken.iter().cloned().collect(),
+            failures: self.quarantine.failures().clone(),
+            quarantined: self.quarantine.quarantined().clone(),
+            registrations: self
+                .devices
+                .registrations()
+                .map(|(device, registration)| store::RegistrationRecord {
+                    device: device.to_string(),
+                    registration: registration.clone(),
+                })
+                .collect(),
+            ended: self.campaigns.iter().filter(|c| c.ended).map(|c| c.name.clone()).collect(),
+            sources: self.source_since.clone(),
+            first_seen: self.devices.first_seen().map(|(device, at)| (device.to_string(), at)).collect(),
+        }
+    }
+
+    // 上次保存（generation 和已保存的导入号码数）之后运行中导入的号码；期间有归档或恢复时返回全部，需替换已保存的记录
+    fn imported_since(&self, saved: Option<(u64, usize)>) -> (Vec<store::ImportedRecord>, bool) {
+        let (from, replace) = match saved {
+            Some((generation, count)) if generation == self.imported_generation => (count, false),
+            _ => (0, true),
+        };
+        let records = self
+            .numbers
+            .range(self.loaded + from..)
+            .map(|number| store::ImportedRecord {
+                number: number.clone(),
+                campaign: self.campaign_of.get(number).map(|&idx| self.campaigns[idx].name.clone()),
+                meta: self.meta.get(number).cloned().unwrap_or_default(),
+            })
+            .collect();
+        (records, replace)
+    }
+
+    // 启动时恢复保存的进度并记录日志
+    fn restore_logged(&mut self, snapshot: store::Snapshot) {
+        // 旧版本保存的进度不含 base，号码池只有号码文件中的号码
+        let base = snapshot.base.unwrap_or(snapshot.total);
+        if base != self.loaded {
+            warn!(
+                "保存进度时号码文件中有 {} 个号码，当前加载了 {} 个，号码文件可能已修改，游标位置可能不准确",
+                base, self.loaded
+            );
+        }
+        let saved_at = snapshot.saved_at;
+        let batches = snapshot.leases.len();
+        let imported = snapshot.imported.len();
+        self.restore(snapshot);
+        info!(
+            "恢复 {} 保存的进度 => 游标 {} / {}（运行中导入 {} 个），待重发 {} 个，延后 {} 个，未确认批次 {} 个",
+            saved_at,
+            self.start_index,
+            self.numbers.len(),
+            imported,
+            self.retry.len(),
+            self.deferred_count(),
+            batches
+        );
+    }
+
+    // 用保存的进度替换当前的游标和各队列；未启用租约时，保存的未确认批次放回重发队列
+    fn restore(&mut self, snapshot: store::Snapshot) {
+        self.restore_imported(snapshot.imported);
+        self.start_index = snapshot.cursor.min(self.numbers.len());
+        self.duplicates_skipped = (0..self.start_index)
+            .filter(|&position| self.positions.get(&self.numbers[position]) != Some(&position))
+            .count();
+        self.batches = snapshot.batches;
+        self.retry = snapshot.retry.into();
+        self.held.clear();
+        self.backlog.iter_mut().chain(&mut self.shard_backlog).for_each(VecDeque::clear);
+        self.deferred.iter_mut().for_each(VecDeque::clear);
+        for number in snapshot.deferred {
+            let idx = self.regions.classify(&number);
+            self.deferred[idx].push_back(number);
+        }
+        self.deleted = snapshot.deleted.into_iter().collect();
+        self.deleted_skipped = snapshot.deleted_skipped.into_iter().collect();
+        self.issued = snapshot.issued;
+        self.first_issued_at = snapshot.first_issued_at;
+        self.usage.restore(snapshot.usage);
+        self.version = snapshot.version;
+        // 号码文件中号码所属的活动来自启动时加载，运行中导入的号码的附加信息和所属活动来自保存的记录
+        let (positions, loaded) = (&self.positions, self.loaded);
+        let from_file = |number: &String| positions.get(number).is_some_and(|&position| position < loaded);
+        self.meta.retain(|number, _| from_file(number));
+        self.campaign_of.retain(|number, _| from_file(number));
+        self.meta.extend(snapshot.meta);
+        for (number, name) in snapshot.campaign_of {
+            match campaign::find(&self.campaigns, &name) {
+                Some(idx) => {
+                    self.campaign_of.insert(number, idx);
+                }
+                None => warn!("号码 {} 所属的活动 {} 已不在配置中，归入默认活动", number, name),
+            }
+        }
+        self.imported_generation += 1;
+        // 批次所属的活动按恢复后的 campaign_of 计算
+        if let Some(leases) = &mut self.leases {
+            leases.clear();
+        }
+        for record in snapshot.leases {
+            let campaigns = self.campaigns_of(&record.numbers);
+            match &mut self.leases {
+                Some(leases) => {
+                    let lease = lease::Lease {
+                        numbers: record.numbers,
+                        device: record.device,
+                        issued_at: record.issued_at,
+                        expires_at: record.expires_at,
+                        trace_id: None,
+                        campaigns,
+                    };
+                    leases.restore(record.batch_id, lease);
+                }
+                None => self.retry.extend(record.numbers),
+            }
+        }
+        self.priority = snapshot.priority.into();
+        self.priority_pending = snapshot.priority_pending.into_iter().collect();
+        self.priority_taken = snapshot.priority_taken.into_iter().collect();
+        self.quarantine.restore(snapshot.failures, snapshot.quarantined);
+        self.devices
+            .restore_registrations(snapshot.registrations.into_iter().map(|r| (r.device, r.registration)).collect());
+        for campaign in &mut self.campaigns {
+            campaign.ended = snapshot.ended.contains(&campaign.name);
+        }
+        self.source_since = snapshot.sources;
+        self.devices.restore_first_seen(snapshot.first_seen);
+    }
+
+    // 号码池恢复为号码文件中的号码加上保存的运行中导入的号码
+    fn restore_imported(&mut self, imported: Vec<String>) {
+        for number in self.numbers.drain(self.loaded..) {
+            if self.positions.get(&number).is_some_and(|&position| position >= self.loaded) {
+                self.positions.remove(&number);
+            }
+        }
+        for number in imported {
+            if self.blacklist.contains(&number) || self.positions.contains_key(&number) {
+                continue;
+            }
+            self.positions.insert(number.clone(), self.numbers.len());
+            self.numbers.push_back(number);
+        }
+    }
+}
+
+// 加载配置文件
+fn load_config(path: &str) -> Config {
+    let config_content = fs::read_to_string(path)
+        .expect("Failed to read config.toml");
+    let config: Config = toml::from_str(&config_content)
+        .expect("Failed to parse config.toml");
+    config
+}
+
+// 加载数据，同时返回启动摘要所需的加载统计
+fn load_state(config: &Config, inputs: Inputs) -> (AppState, summary::Loaded) {
+    let mut loaded = summary::Loaded::default();
+    let (lines, message) = match inputs {
+        Inputs::Files => {
+            let lines = load_numbers("numbers.txt");
+            loaded.files.push(summary::File {
+                kind: "numbers",
+                path: "numbers.txt".to_string(),
+                lines: fs::metadata("numbers.txt").is_ok().then_some(lines.len()),
+            });
+            loaded.files.push(summary::File::read("message", "msg.txt"));
+            (lines, load_message("msg.txt"))
+        }
+        Inputs::Given { numbers, message } => (numbers.into(), message),
+    };
+    // 与 /import 一样规范化号码（去掉空格和连字符），格式不正确的行跳过
+    let mut numbers = VecDeque::with_capacity(lines.len());
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        match import::normalize(line) {
+            Some(number) => numbers.push_back(number),
+            None => loaded.numbers.invalid += 1,
+        }
+    }
+    if loaded.numbers.invalid > 0 {
+        warn!("numbers.txt 中有 {} 行不是有效的号码，已跳过", loaded.numbers.invalid);
+    }
+    loaded.numbers.loaded = numbers.len();
+    let mut blacklist: HashSet<String> = config
+        .blacklist_file
+        .as_deref()
+        .map(|path| load_numbers(path).into_iter().filter_map(|n| import::normalize(&n)).collect())
+        .unwrap_or_default();
+    if let Some(path) = &config.blacklist_file {
+        loaded.files.push(summary::File::read("blacklist", path));
+    }
+    // 回复命中规则而退订的号码同样排除
+    let replies = replies::Replies::load(&config.replies);
+    let before = blacklist.len();
+    blacklist.extend(replies.opted_out().map(String::from));
+    loaded.numbers.opted_out = blacklist.len() - before;
+    loaded.numbers.blacklist = blacklist.len();
+    if config.replies.file.is_none() && config.replies.rules.iter().any(|r| r.opt_out) {
+        warn!("未配置 replies.file，回复退订的号码重启后会重新下发");
+    }
+    if !blacklist.is_empty() {
+        let before = numbers.len();
+        numbers.retain(|n| !blacklist.contains(n));
+        loaded.numbers.blacklisted = before - numbers.len();
+        info!("加载黑名单 {} 个号码，排除 {} 个", blacklist.len(), before - numbers.len());
+    }
+    for file in config.message_variants.values().chain(config.messages.values()) {
+        loaded.files.push(summary::File::read("message", file));
+    }
+    for c in &config.campaigns {
+        loaded.files.push(summary::File::read("message", &c.message_file));
+        for file in c.variants.values().chain(c.messages.values()) {
+            loaded.files.push(summary::File::read("message", file));
+        }
+    }
+    let message = emoji::expand(&message, &config.emoji);
+    info!("加载 {} 个号码， 消息内容: {}", numbers.len(), message);
+    if !config.vars.is_empty() {
+        info!("加载 {} 个模板变量: {:?}", config.vars.len(), config.vars);
+    }
+
+    let renderer = message::Renderer {
+        template: message,
+        vars: config.vars.clone(),
+        timezone: datetime::load_timezone(config.timezone.as_deref()),
+        locale: datetime::Locale::parse(&config.locale),
+        shortener: config.shortener.clone().map(|c| {
+            info!("启用短链服务 {} => {}", c.kind, c.endpoint);
+            Arc::new(shortener::Shortener::new(c))
+        }),
+    };
+
+    let regions = region::Regions::new(
+        &config.regions,
+        config.timezone.as_deref(),
+        config.send_window.as_deref(),
+        &config.holidays,
+    );
+    if !config.regions.is_empty() || config.send_window.is_some() {
+        info!("加载 {} 个地区，默认发送时段 {:?}", config.regions.len(), config.send_window);
+    }
+
+    let mut campaigns = vec![campaign::Campaign {
+        name: "default".to_string(),
+        message_file: "msg.txt".to_string(),
+        renderer: Arc::new(renderer),
+        variants: std::collections::BTreeMap::new(),
+        messages: std::collections::BTreeMap::new(),
+        end_at: config.end_at,
+        ended: false,
+        max_segments: config.max_segments,
+        pacing: config.pacing.clone(),
+        max_outstanding: config.leases.as_ref().map_or(0, |l| l.max_outstanding),
+    }];
+    for (locale, file) in &config.message_variants {
+        campaigns[0].add_variant(locale, file, emoji::expand(&read_message_file(file), &config.emoji));
+    }
+    for (id, file) in &config.messages {
+        campaigns[0].add_message(id, file, emoji::expand(&read_message_file(file), &config.emoji));
+    }
+    for c in &config.campaigns {
+        if campaign::find(&campaigns, &c.name).is_some() {
+            panic!("Duplicate campaign name '{}'", c.name);
+        }
+        let template = emoji::expand(&read_message_file(&c.message_file), &config.emoji);
+        let mut campaign = campaign::Campaign::new(c, &campaigns[0], template);
+        for (locale, file) in &c.variants {
+            campaign.add_variant(locale, file, emoji::expand(&read_message_file(file), &config.emoji));
+        }
+        for (id, file) in &c.messages {
+            campaign.add_message(id, file, emoji::expand(&read_message_file(file), &config.emoji));
+        }
+        info!("加载活动 {} => 消息内容: {}", c.name, campaign.renderer.template);
+        campaigns.push(campaign);
+    }
+
+    for campaign in &campaigns {
+        let variants = campaign.variants.iter().chain(&campaign.messages).map(|(key, v)| (Some(key.as_str()), &v.renderer));
+        for (key, renderer) in std::iter::once((None, &campaign.renderer)).chain(variants) {
+            let name = match key {
+                Some(key) => format!("{}（{}）", campaign.name, key),
+                None => campaign.name.clone(),
+            };
+            if key.is_some() {
+                info!("加载活动 {} 的消息: {}", name, renderer.template);
+            }
+            let segments = segments::count(&renderer.render_static(&renderer.now()));
+            match campaign.max_segments {
+                Some(max) if segments > max => warn!(
+                    "活动 {} 的消息模板为 {} 条短信，已超出预算 {} 条，渲染后超出的号码将被暂扣",
+                    name, segments, max
+                ),
+                _ => info!("活动 {} 的消息模板约 {} 条短信", name, segments),
+            }
+        }
+    }
+
+    let pacing = campaigns
+        .iter()
+        .map(|campaign| {
+            campaign.pacing.as_ref().map(|c| {
+                info!("活动 {} 启用限速 => 每分钟 {} 个，突发 {:?}", campaign.name, c.rate_per_minute, c.burst);
+                pacing::TokenBucket::new(c)
+            })
+        })
+        .collect();
+
+    let max_outstanding = campaigns.iter().map(|c| c.max_outstanding).collect();
+
+    let mut positions = HashMap::with_capacity(numbers.len());
+    for (idx, number) in numbers.iter().enumerate() {
+        positions.entry(number.clone()).or_insert(idx);
+    }
+    loaded.numbers.duplicates = numbers.len() - positions.len();
+
+    let mut state = AppState {
+        positions,
+        loaded: 0,
+        imported_generation: 0,
+        source_since: HashMap::new(),
+        deleted: HashSet::new(),
+        deleted_skipped: HashSet::new(),
+        issued: 0,
+        batches: 0,
+        duplicates_skipped: 0,
+        first_issued_at: None,
+        devices: device::Devices::new(&config.devices, datetime::load_timezone(config.timezone.as_deref())),
+        blacklist,
+        replies,
+        meta: HashMap::new(),
+        jobs: jobs::Jobs::load(config.jobs_file.as_deref()),
+        changelog: changelog::Changelog::load(config.changelog_file.as_deref()),
+        export_dir: config.export_dir.clone(),
+        downloads: config.downloads.clone().inspect(|d| d.validate()),
+        draining: false,
+        maintenance: config.maintenance,
+        faults: faults::Faults::default(),
+        features: config.features.clone(),
+        notifier: config
+            .features
+            .webhooks
+            .then(|| notify::Dispatcher::new(config.notify.clone()))
+            .filter(|d| !d.is_empty())
+            .map(Arc::new),
+        exhausted: false,
+        last_fetch: None,
+        tenants: config.tenants.clone(),
+        usage: usage::Usage::default(),
+        version: 0,
+        admin_lock: Arc::new(tokio::sync::Mutex::new(())),
+        numbers,
+        start_index: 0,
+        default_fetch_count: config.default_fetch_count,
+        test_number: config.test_number.clone(),
+        scheduler: config.scheduling.as_ref().map(|c| {
+            let scheduler = campaign::Scheduler::new(c, &campaigns);
+            info!("启用活动轮流分配 => 权重 {:?}", c.weights);
+            scheduler
+        }),
+        backlog: vec![VecDeque::new(); campaigns.len()],
+        shard_backlog: vec![VecDeque::new(); config.devices.shards.max(1)],
+        campaigns,
+        campaign_of: HashMap::new(),
+        priority: VecDeque::new(),
+        priority_pending: HashSet::new(),
+        priority_taken: HashSet::new(),
+        priority_fallback: config.priority_fallback,
+        message_override_token: config.message_override_token.clone(),
+        strings: Arc::new(config.strings.clone()),
+        summary: None,
+        held: Vec::new(),
+        deferred: vec![VecDeque::new(); regions.len()],
+        throttle_usage: vec![(0, 0); regions.len()],
+        regions,
+        pacing,
+        backpressure: config.backpressure.clone().inspect(|c| {
+            info!("启用按失败率缩小批次 => 失败率超过 {}，最小批次 {}", c.failure_rate, c.min_batch);
+        }),
+        prerender: config.prerender.clone().map(|c| Arc::new(prerender::Prerender::new(c))),
+        leases: config.leases.as_ref().map(|c| {
+            info!("启用批次租约 => 时长 {} 秒，每个活动的未确认批次上限 {}", c.ttl_secs, c.max_outstanding);
+            lease::Leases::new(c, max_outstanding)
+        }),
+        retry: VecDeque::new(),
+        retry_policy: lease::RetryPolicy::new(&config.retry),
+        fetch_cache: dedup::RequestCache::new(config.request_cache_secs),
+        ack_cache: dedup::RequestCache::new(config.request_cache_secs),
+        shedder: config.shedding.as_ref().map(|c| {
+            info!("启用过载保护 => 处理中请求上限 {}，等锁上限 {} 毫秒", c.max_in_flight, c.max_lock_wait_ms);
+            Arc::new(shed::LoadShedder::new(c))
+        }),
+        runtime: config.runtime.clone(),
+        quarantine: quarantine::Quarantine::new(config.quarantine.as_ref()),
+        verifier: config.verification.as_ref().map(|c| {
+            info!("启用发送抽查 => 每批抽查 {}%", c.sample_percent);
+            verify::Verifier::new(c)
+        }),
+    };
+
+    // 各活动的号码追加到号码池，与 /import 一样排除黑名单和重复号码
+    for c in config.campaigns.iter().filter(|c| c.numbers_file.is_some()) {
+        let path = c.numbers_file.as_deref().unwrap();
+        let mut summary = import::Summary::default();
+        let records = load_numbers(path)
+            .into_iter()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                summary.received += 1;
+                let number = import::normalize(&line);
+                if number.is_none() {
+                    summary.invalid += 1;
+                }
+                number
+            })
+            .map(|number| import::Record { number, meta: HashMap::from([("campaign".to_string(), c.name.clone())]) })
+            .collect();
+        state.import(records, &mut summary);
+        info!(
+            "活动 {} 从 {} 加载 {} 个号码（重复 {} 个，无效 {} 个，黑名单 {} 个）",
+            c.name, path, summary.accepted, summary.duplicates, summary.invalid, summary.blacklisted
+        );
+        loaded.files.push(summary::File { kind: "numbers", path: path.to_string(), lines: Some(summary.received) });
+        loaded.imported.insert(c.name.clone(), summary);
+    }
+    state.loaded = state.numbers.len();
+    (state, loaded)
+}
+
+// 由配置、加载统计和恢复后的状态生成启动摘要，并检查常见的配置错误
+fn startup_summary(
+    config: &Config,
+    state: &AppState,
+    mut loaded: summary::Loaded,
+    restored_from: Option<jiff::Timestamp>,
+) -> summary::Summary {
+    let now = clock::now();
+    let timezone = datetime::load_timezone(config.timezone.as_deref());
+    let mut warnings = Vec::new();
+
+    for file in loaded.files.iter().filter(|f| f.lines.is_none()) {
+        warnings.push(format!("{} 不存在或无法读取", file.path));
+    }
+    if state.positions.is_empty() && config.sources.is_empty() {
+        warnings.push("号码池为空，请检查 numbers.txt 或通过 /import 导入".to_string());
+    }
+
+    let campaigns: Vec<summary::Campaign> = state
+        .campaigns
+        .iter()
+        .enumerate()
+        .map(|(idx, c)| {
+            let renderers = std::iter::once(&c.renderer).chain(c.variants.values().chain(c.messages.values()).map(|v| &v.renderer));
+            let segments =
+                renderers.map(|r| segments::count(&r.render_static(&r.now()))).max().unwrap_or_default();
+            if let Some(max) = c.max_segments.filter(|&max| segments > max) {
+                warnings.push(format!("活动 {} 的消息为 {} 条短信，超出预算 {} 条", c.name, segments, max));
+            }
+            if c.end_at.is_some_and(|end_at| end_at <= now) {
+                warnings.push(format!("活动 {} 的截止时间已过，不会下发", c.name));
+            }
+            let numbers_file = config.campaigns.iter().find(|cc| cc.name == c.name).and_then(|cc| cc.numbers_file.clone());
+            summary::Campaign {
+                name: c.name.clone(),
+                message_file: c.message_file.clone(),
+                segments,
+                max_segments: c.max_segments,
+                rate_per_minute: c.pacing.as_ref().map(|p| p.rate_per_minute),
+                variants: c.variants.keys().cloned().collect(),
+                messages: c.messages.keys().cloned().collect(),
+                numbers_file,
+                imported: loaded.imported.remove(&c.name),
+                weight: state.scheduler.as_ref().map(|s| s.weights()[idx]),
+                end_at: c.end_at,
+            }
+        })
+        .collect();
+
+    if config.devices.strict && config.devices.allowed_udids.is_empty() {
+        warnings.push("设备严格模式已开启但 allowed_udids 为空，所有设备都无法注册".to_string());
+    }
+    if !config.notify.channels.is_empty() && !config.features.webhooks {
+        warnings.push("已配置 [notify] 但 features.webhooks 已关闭，不会推送事件".to_string());
+    }
+    if config.message_override_token.is_none() && state.campaigns.iter().any(|c| !c.messages.is_empty()) {
+        warnings.push("已配置备选消息但未配置 message_override_token，/fetch 无法指定 message_id".to_string());
+    }
+    if config.state.is_none() {
+        warnings.push("未配置 [state]，重启后进度丢失，将从头开始下发".to_string());
+        if config.tenants.is_some() {
+            warnings.push("已配置 [tenants] 但未配置 [state]，重启后各租户的用量清零".to_string());
+        }
+    }
+    if config.maintenance {
+        warnings.push("维护模式已开启，设备接口暂停服务".to_string());
+    }
+    if config.test_mode {
+        warnings.push("测试模式已开启，不要在正式活动中使用".to_string());
+    }
+
+    let enabled = [
+        ("leases", config.leases.is_some()),
+        ("pacing", config.pacing.is_some() || config.campaigns.iter().any(|c| c.pacing.is_some())),
+        ("backpressure", config.backpressure.is_some()),
+        ("prerender", config.prerender.is_some()),
+        ("downloads", config.downloads.is_some()),
+        ("tenants", config.tenants.is_some()),
+        ("shedding", config.shedding.is_some()),
+        ("quarantine", config.quarantine.is_some()),
+        ("verification", config.verification.is_some()),
+        ("scheduling", config.scheduling.is_some()),
+        ("shortener", config.shortener.is_some()),
+        ("notify", state.notifier.is_some()),
+        ("replies", !config.replies.rules.is_empty()),
+        ("state", config.state.is_some()),
+    ];
+
+    let mut numbers = loaded.numbers;
+    numbers.pool = state.positions.len();
+    numbers.test_number = config.features.test_number.then(|| config.test_number.clone());
+
+    summary::Summary {
+        started_at: now,
+        listen: config.listen.clone().unwrap_or_else(|| format!("0.0.0.0:{}", config.port)),
+        admin_listen: config.admin_listen.clone(),
+        files: loaded.files,
+        numbers,
+        campaigns,
+        features: config.features.clone(),
+        enabled: enabled.iter().filter(|(_, on)| *on).map(|(name, _)| *name).collect(),
+        limits: summary::Limits {
+            default_fetch_count: config.default_fetch_count,
+            max_segments: config.max_segments,
+            daily_quota: config.devices.daily_quota,
+            ramp: config.devices.ramp.clone(),
+            strict_devices: config.devices.strict,
+            allowed_udids: config.devices.allowed_udids.len(),
+            shards: config.devices.shards,
+            rate_per_minute: config.pacing.as_ref().map(|p| p.rate_per_minute),
+            burst: config.pacing.as_ref().and_then(|p| p.burst),
+            lease_ttl_secs: config.leases.as_ref().map(|l| l.ttl_secs),
+            max_outstanding: config.leases.as_ref().map(|l| l.max_outstanding),
+            max_in_flight: config.shedding.as_ref().map(|s| s.max_in_flight),
+            quarantine_devices: config.quarantine.as_ref().map(|q| q.distinct_devices),
+            verify_percent: config.verification.as_ref().map(|v| v.sample_percent),
+        },
+        schedule: summary::Schedule {
+            timezone: timezone.iana_name().unwrap_or("system").to_string(),
+            send_window: config.send_window.clone(),
+            regions: config.regions.len(),
+            holiday_mode: config.holidays.mode.clone(),
+            holidays: config.holidays.dates.len(),
+            // 只读副本不拉取号码源
+            sources: config
+                .sources
+                .iter()
+                .filter(|_| !config.state.as_ref().is_some_and(|s| s.replica))
+                .map(|source| summary::Source {
+                    name: source.name.clone(),
+                    schedule: source.schedule.clone(),
+                    next_run: cron::Cron::parse(&source.schedule).ok().and_then(|c| c.next_after(now, &timezone)),
+                })
+                .collect(),
+        },
+        state: config.state.as_ref().map(|s| summary::State {
+            backend: s.backend.clone(),
+            replica: s.replica,
+            restored_from,
+            cursor: state.start_index,
+        }),
+        warnings,
+    }
+}
+
+// 读取 numbers.txt
+fn load_numbers(path: &str) -> VecDeque<String> {
+    fs::read_to_string(path)
+        .map(|data| data.lines().map(String::from).collect())
+        .unwrap_or_else(|_| VecDeque::new())
+}
+
+// 读取配置中指定的消息文件，不存在时无法启动
+fn read_message_file(path: &str) -> String {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| data.lines().next().map(String::from))
+        .unwrap_or_else(|| panic!("Failed to read message file {}", path))
+}
+
+// 读取 msg.txt
+fn load_message(path: &str) -> String {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| data.lines().next().map(String::from))
+        .unwrap_or_else(|| "No message found".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{import, load_state, AppState, Config, Inputs};
+    use std::collections::HashMap;
+
+    const NUMBERS: [&str; 5] = ["13800000001", "13800000002", "13800000003", "13800000004", "13800000005"];
+
+    // 号码池只保留 numbers，其中 priority 中的号码进入优先池
+    fn state_with(numbers: &[&str], priority: &[&str]) -> AppState {
+        let config: Config = toml::from_str("port = 0\ndefault_fetch_count = 1\ntest_number = \"13888888888\"").unwrap();
+        let (mut state, _) = load_state(&config, Inputs::Given { numbers: Vec::new(), message: "hello".to_string() });
+        let records = numbers
+            .iter()
+            .map(|number| {
+                let mut meta = HashMap::new();
+                if priority.contains(number) {
+                    meta.insert("priority".to_string(), "high".to_string());
+                }
+                import::Record { number: number.to_string(), meta }
+            })
+            .collect();
+        state.import(records, &mut import::Summary::default());
+        state
+    }
+
+    fn take(state: &mut AppState, n: usize, priority: bool) -> Vec<String> {
+        state.take_paced(n, jiff::Timestamp::now(), None, None, priority, &[]).0
+    }
+
+    fn counters(state: &AppState) -> (usize, usize) {
+        (state.served(), state.remaining())
+    }
+
+    #[test]
+    fn counters_are_exact_when_n_varies() {
+        let mut state = state_with(&NUMBERS, &[]);
+        let mut issued = Vec::new();
+        for (n, expected) in [(3, (3, 2)), (1, (4, 1)), (5, (5, 0))] {
+            issued.extend(take(&mut state, n, false));
+            assert_eq!(counters(&state), expected);
+        }
+        assert_eq!(issued, NUMBERS);
+        // 最后一页之后再取不到号码，计数不变
+        assert!(take(&mut state, 5, false).is_empty());
+        assert_eq!(counters(&state), (5, 0));
+    }
+
+    #[test]
+    fn retried_numbers_are_not_served_until_reissued() {
+        let mut state = state_with(&NUMBERS, &[]);
+        let batch = take(&mut state, 3, false);
+        state.retry.extend(batch[1..].iter().cloned());
+        assert_eq!(counters(&state), (1, 4));
+        let batch = take(&mut state, 2, false);
+        assert_eq!(batch, ["13800000002", "13800000003"]);
+        assert_eq!(counters(&state), (3, 2));
+        take(&mut state, 5, false);
+        assert_eq!(counters(&state), (5, 0));
+    }
+
+    #[test]
+    fn deleted_numbers_are_neither_served_nor_remaining() {
+        let mut state = state_with(&NUMBERS, &[]);
+        take(&mut state, 1, false);
+        // 游标之后和重发队列中的软删除号码都不计入
+        state.deleted.insert("13800000003".to_string());
+        state.retry.push_back("13800000001".to_string());
+        state.deleted.insert("13800000001".to_string());
+        assert_eq!(counters(&state), (0, 3));
+        assert_eq!(take(&mut state, 5, false), ["13800000002", "13800000004", "13800000005"]);
+        assert_eq!(counters(&state), (3, 0));
+
+        // 恢复已被跳过的号码后放回重发队列，重新计入
+        for number in ["13800000001", "13800000003"] {
+            state.deleted.remove(number);
+            if state.deleted_skipped.remove(number) {
+                state.retry.push_back(number.to_string());
+            }
+        }
+        assert_eq!(counters(&state), (3, 2));
+        take(&mut state, 5, false);
+        assert_eq!(counters(&state), (5, 0));
+    }
+
+    #[test]
+    fn priority_numbers_are_counted_once() {
+        let mut state = state_with(&NUMBERS, &["13800000004"]);
+        assert_eq!(take(&mut state, 1, true), ["13800000004"]);
+        assert_eq!(counters(&state), (1, 4));
+        // 提前下发的号码在游标到达时跳过
+        assert_eq!(take(&mut state, 3, false), ["13800000001", "13800000002", "13800000003"]);
+        assert_eq!(counters(&state), (4, 1));
+        assert_eq!(take(&mut state, 5, false), ["13800000005"]);
+        assert_eq!(counters(&state), (5, 0));
+
+        // 提前下发后退回重发的号码不重复计数
+        let mut state = state_with(&NUMBERS, &["13800000002"]);
+        take(&mut state, 1, true);
+        state.retry.push_back("13800000002".to_string());
+        assert_eq!(counters(&state), (0, 5));
+        take(&mut state, 5, false);
+        assert_eq!(counters(&state), (5, 0));
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+// 过载保护：设备请求排队过多或等待状态锁过久时直接返回 503，避免延迟持续升高
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShedConfig {
+    // 同时处理中的设备请求上限
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    // 等待状态锁超过该时长（毫秒）的请求被拒绝
+    #[serde(default = "default_max_lock_wait_ms")]
+    pub max_lock_wait_ms: u64,
+    // 返回给设备的 Retry-After 秒数
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
+}
+
+fn default_max_in_flight() -> usize {
+    64
+}
+
+fn default_max_lock_wait_ms() -> u64 {
+    200
+}
+
+fn default_retry_after_secs() -> u64 {
+    5
+}
+
+pub struct LoadShedder {
+    max_in_flight: usize,
+    max_lock_wait: Duration,
+    pub retry_after_secs: u64,
+    in_flight: AtomicUsize,
+    shed: AtomicU64,
+}
+
+// 处理中的请求，离开作用域时计数减一
+pub struct Admitted<'a> {
+    shedder: &'a LoadShedder,
+}
+
+impl Drop for Admitted<'_> {
+    fn drop(&mut self) {
+        self.shedder.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl LoadShedder {
+    pub fn new(config: &ShedConfig) -> LoadShedder {
+        LoadShedder {
+            max_in_flight: config.max_in_flight.max(1),
+            max_lock_wait: Duration::from_millis(config.max_lock_wait_ms),
+            retry_after_secs: config.retry_after_secs,
+            in_flight: AtomicUsize::new(0),
+            shed: AtomicU64::new(0),
+        }
+    }
+
+    // 登记一个新请求，超出上限时返回当前排队数
+    pub fn admit(&self) -> Result<Admitted<'_>, usize> {
+        let depth = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        let admitted = Admitted { shedder: self };
+        if depth > self.max_in_flight {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return Err(depth);
+        }
+        Ok(admitted)
+    }
+
+    // 请求等待状态锁的时长超出阈值时计为一次拒绝
+    pub fn check_wait(&self, wait: Duration) -> bool {
+        if wait > self.max_lock_wait {
+            self.shed.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    // 累计拒绝的请求数
+    pub fn shed_count(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+}
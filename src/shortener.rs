@@ -0,0 +1,323 @@
+use crate::http_client::{self, encode_component};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use rand::{Rng, distr::Alphanumeric};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+// 短链服务配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShortenerConfig {
+    // yourls / bitly / builtin
+    pub kind: String,
+    // API 地址，如 https://yourls.example.com/yourls-api.php；bitly 默认 https://api-ssl.bitly.com
+    #[serde(default)]
+    pub endpoint: String,
+    // builtin 模式下本服务对外的访问地址，短链为 {base_url}/s/{code}
+    #[serde(default)]
+    pub base_url: String,
+    // YOURLS 的 signature token
+    #[serde(default)]
+    pub signature: String,
+    // Bitly 的 access token
+    #[serde(default)]
+    pub token: String,
+    // 长链接模板，可使用 {number} 等占位符，为每个号码生成独立链接
+    pub target: String,
+    // 短链记录文件
+    #[serde(default = "default_record_file")]
+    pub record_file: String,
+    // builtin 模式下的点击记录，每次点击追加一行短码，重启后据此恢复点击数
+    #[serde(default = "default_clicks_file")]
+    pub clicks_file: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    // 同时进行的短链请求数，/fetch 和预渲染按此并发生成一批号码的短链
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_record_file() -> String {
+    "links.csv".to_string()
+}
+
+fn default_clicks_file() -> String {
+    "link_clicks.log".to_string()
+}
+
+fn default_timeout_secs() -> u64 {
+    5
+}
+
+fn default_concurrency() -> usize {
+    8
+}
+
+const BITLY_ENDPOINT: &str = "https://api-ssl.bitly.com";
+
+pub struct Shortener {
+    config: ShortenerConfig,
+    links: Mutex<LinkTable>,
+}
+
+// 为号码生成的短链，随渲染结果一起传递，号码下发后才写入短链文件
+#[derive(Debug, Clone)]
+pub struct ShortLink {
+    pub number: String,
+    pub short: String,
+    pub long_url: String,
+}
+
+// 内置短链表
+#[derive(Default)]
+struct LinkTable {
+    by_code: HashMap<String, Link>,
+    by_number: HashMap<String, String>,
+}
+
+struct Link {
+    number: String,
+    target: String,
+    clicks: u64,
+}
+
+impl LinkTable {
+    // 从短链记录文件重建内置短链表，重启后已发出的短链仍可跳转；点击数从点击记录恢复
+    fn load(path: &str, clicks_path: &str) -> LinkTable {
+        let mut links = LinkTable::default();
+        for line in read_lines(path).lines() {
+            // 长链接中可能有逗号
+            let mut fields = line.splitn(3, ',');
+            let (Some(number), Some(short), Some(target)) = (fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            let Some((_, code)) = short.rsplit_once("/s/") else {
+                continue;
+            };
+            links.by_number.insert(number.to_string(), code.to_string());
+            links.by_code.insert(
+                code.to_string(),
+                Link { number: number.to_string(), target: target.to_string(), clicks: 0 },
+            );
+        }
+        for code in read_lines(clicks_path).lines() {
+            if let Some(link) = links.by_code.get_mut(code) {
+                link.clicks += 1;
+            }
+        }
+        if !links.by_code.is_empty() {
+            let clicks: u64 = links.by_code.values().map(|l| l.clicks).sum();
+            info!("从 {} 恢复 {} 个短链，累计点击 {} 次", path, links.by_code.len(), clicks);
+        }
+        links
+    }
+}
+
+// 读取记录文件，不存在时为空
+fn read_lines(path: &str) -> String {
+    match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            warn!("读取短链记录 {} 失败: {}", path, e);
+            String::new()
+        }
+    }
+}
+
+// 短链点击统计
+#[derive(Debug, Serialize)]
+pub struct ClickStats {
+    pub links: usize,
+    pub clicked: usize,
+    pub clicks: u64,
+    pub click_through_rate: f64,
+}
+
+impl ClickStats {
+    // 合并多个短链服务的点击统计
+    pub fn sum(stats: impl IntoIterator<Item = ClickStats>) -> ClickStats {
+        let (mut links, mut clicked, mut clicks) = (0, 0, 0);
+        for s in stats {
+            links += s.links;
+            clicked += s.clicked;
+            clicks += s.clicks;
+        }
+        let click_through_rate = if links == 0 { 0.0 } else { clicked as f64 / links as f64 };
+        ClickStats { links, clicked, clicks, click_through_rate }
+    }
+}
+
+#[derive(Deserialize)]
+struct YourlsResponse {
+    shorturl: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BitlyResponse {
+    link: Option<String>,
+    message: Option<String>,
+}
+
+impl Shortener {
+    pub fn new(mut config: ShortenerConfig) -> Shortener {
+        if config.kind == "bitly" && config.endpoint.is_empty() {
+            config.endpoint = BITLY_ENDPOINT.to_string();
+        }
+        if config.concurrency == 0 {
+            panic!("shortener.concurrency must be at least 1");
+        }
+        match config.kind.as_str() {
+            "yourls" | "bitly" => {
+                if let Err(e) = http_client::check_url(&config.endpoint) {
+                    panic!("Invalid shortener endpoint: {}", e);
+                }
+            }
+            "builtin" => {
+                if config.base_url.is_empty() {
+                    panic!("shortener.base_url is required for builtin shortener");
+                }
+            }
+            other => panic!("Unknown shortener kind '{}', expected yourls, bitly or builtin", other),
+        }
+        let links = if config.kind == "builtin" {
+            LinkTable::load(&config.record_file, &config.clicks_file)
+        } else {
+            LinkTable::default()
+        };
+        Shortener { config, links: Mutex::new(links) }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        self.config.kind == "builtin"
+    }
+
+    // 解析内置短链并记录一次点击，返回跳转目标
+    pub async fn resolve(&self, code: &str) -> Option<String> {
+        let target = {
+            let mut links = self.links.lock().unwrap();
+            let link = links.by_code.get_mut(code)?;
+            link.clicks += 1;
+            info!("短链点击: {} => 号码 {}，累计 {} 次", code, link.number, link.clicks);
+            link.target.clone()
+        };
+        append(&self.config.clicks_file, &format!("{}\n", code)).await;
+        Some(target)
+    }
+
+    pub fn click_stats(&self) -> ClickStats {
+        let links = self.links.lock().unwrap();
+        let clicked = links.by_code.values().filter(|l| l.clicks > 0).count();
+        let total = links.by_code.len();
+        ClickStats {
+            links: total,
+            clicked,
+            clicks: links.by_code.values().map(|l| l.clicks).sum(),
+            click_through_rate: if total == 0 { 0.0 } else { clicked as f64 / total as f64 },
+        }
+    }
+
+    // 同一号码复用已有短码
+    fn builtin_code(&self, number: &str, long_url: &str) -> String {
+        let mut links = self.links.lock().unwrap();
+        if let Some(code) = links.by_number.get(number) {
+            let code = code.clone();
+            if let Some(link) = links.by_code.get_mut(&code) {
+                link.target = long_url.to_string();
+            }
+            return code;
+        }
+        let code = loop {
+            let code = random_code();
+            if !links.by_code.contains_key(&code) {
+                break code;
+            }
+        };
+        links.by_number.insert(number.to_string(), code.clone());
+        links.by_code.insert(
+            code.clone(),
+            Link { number: number.to_string(), target: long_url.to_string(), clicks: 0 },
+        );
+        code
+    }
+
+    pub fn target(&self) -> &str {
+        &self.config.target
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.config.concurrency
+    }
+
+    // 为号码生成短链，不写入短链文件；号码下发后由调用方 record_issued
+    pub async fn shorten_for(&self, number: &str, long_url: &str) -> Result<ShortLink, String> {
+        let short = self.shorten(number, long_url).await?;
+        Ok(ShortLink { number: number.to_string(), short, long_url: long_url.to_string() })
+    }
+
+    // 号码已下发，把其短链追加到短链文件，用于后续归因；暂扣、预渲染后未下发的号码不记录
+    pub async fn record_issued(&self, links: &[&ShortLink]) {
+        let lines: String =
+            links.iter().map(|link| format!("{},{},{}\n", link.number, link.short, link.long_url)).collect();
+        if !lines.is_empty() {
+            self.record(&lines).await;
+        }
+    }
+
+    async fn shorten(&self, number: &str, long_url: &str) -> Result<String, String> {
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        match self.config.kind.as_str() {
+            "builtin" => {
+                let code = self.builtin_code(number, long_url);
+                Ok(format!("{}/s/{}", self.config.base_url.trim_end_matches('/'), code))
+            }
+            "yourls" => {
+                let body = format!(
+                    "signature={}&action=shorturl&format=json&url={}",
+                    encode_component(&self.config.signature),
+                    encode_component(long_url)
+                );
+                let headers = [("Content-Type", "application/x-www-form-urlencoded")];
+                let resp = http_client::post(&self.config.endpoint, &headers, &body, timeout).await?;
+                // 长链接已存在时 YOURLS 返回失败状态，但仍带有 shorturl
+                let parsed: YourlsResponse = serde_json::from_str(&resp.body)
+                    .map_err(|e| format!("invalid yourls response ({}): {}", resp.status, e))?;
+                parsed.shorturl.ok_or_else(|| parsed.message.unwrap_or_else(|| format!("http {}", resp.status)))
+            }
+            _ => {
+                let url = format!("{}/v4/shorten", self.config.endpoint.trim_end_matches('/'));
+                let auth = format!("Bearer {}", self.config.token);
+                let headers = [("Content-Type", "application/json"), ("Authorization", auth.as_str())];
+                let body = serde_json::json!({ "long_url": long_url }).to_string();
+                let resp = http_client::post(&url, &headers, &body, timeout).await?;
+                let parsed: BitlyResponse = serde_json::from_str(&resp.body)
+                    .map_err(|e| format!("invalid bitly response ({}): {}", resp.status, e))?;
+                match parsed.link {
+                    Some(link) if resp.is_success() => Ok(link),
+                    _ => Err(parsed.message.unwrap_or_else(|| format!("http {}", resp.status))),
+                }
+            }
+        }
+    }
+
+    // 追加记录 号码,短链,长链接
+    async fn record(&self, lines: &str) {
+        append(&self.config.record_file, lines).await;
+    }
+}
+
+async fn append(path: &str, lines: &str) {
+    let result = match OpenOptions::new().create(true).append(true).open(path).await {
+        Ok(mut f) => f.write_all(lines.as_bytes()).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("写入短链记录 {} 失败: {}", path, e);
+    }
+}
+
+// 生成 7 位 base62 短码
+fn random_code() -> String {
+    rand::rng().sample_iter(Alphanumeric).take(7).map(char::from).collect()
+}
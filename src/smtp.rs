@@ -0,0 +1,101 @@
+use crate::notify;
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{Mailbox, header::ContentType},
+    transport::smtp::authentication::Credentials,
+};
+use log::{debug, warn};
+use serde::Deserialize;
+use std::time::Duration;
+
+// 邮件通知：经 SMTP 服务器发送，支持 STARTTLS、TLS 和用户名密码认证
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailConfig {
+    // 服务器地址，如 "smtp.example.com:587"
+    pub smtp: String,
+    // starttls（默认）/ tls / none，none 仅用于本机或内网的中继
+    #[serde(default = "default_tls")]
+    pub tls: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default = "notify::default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_tls() -> String {
+    "starttls".to_string()
+}
+
+pub struct Email {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Email {
+    pub fn new(config: EmailConfig) -> Email {
+        if config.to.is_empty() {
+            panic!("Email channel needs at least one recipient in to");
+        }
+        let (host, port) = config
+            .smtp
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .unwrap_or_else(|| panic!("Invalid email smtp address {:?}, expected host:port", config.smtp));
+        let builder = match config.tls.as_str() {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            "none" => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)),
+            other => panic!("Unknown email tls mode '{}', expected starttls, tls or none", other),
+        };
+        let mut builder = builder
+            .unwrap_or_else(|e| panic!("Invalid email smtp host {:?}: {}", host, e))
+            .port(port)
+            .timeout(Some(Duration::from_secs(config.timeout_secs)));
+        match (config.username, config.password) {
+            (Some(username), Some(password)) => {
+                if config.tls == "none" {
+                    panic!("Email channel refuses to send credentials without TLS, set tls = \"starttls\" or \"tls\"");
+                }
+                builder = builder.credentials(Credentials::new(username, password));
+            }
+            (None, None) => {}
+            _ => panic!("Email channel needs both username and password"),
+        }
+        let parse = |addr: &str| -> Mailbox {
+            addr.parse().unwrap_or_else(|e| panic!("Invalid email address {:?}: {}", addr, e))
+        };
+        Email { transport: builder.build(), from: parse(&config.from), to: config.to.iter().map(|a| parse(a)).collect() }
+    }
+}
+
+impl notify::Notifier for Email {
+    fn send(&self, event: &notify::Event) {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(format!("[ios_sms_rpa] {}", event.name))
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let name = event.name.clone();
+        let message = match message.body(serde_json::to_string_pretty(event).unwrap_or_default()) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("邮件事件 {} 生成失败: {}", name, e);
+                return;
+            }
+        };
+        let transport = self.transport.clone();
+        tokio::spawn(async move {
+            match transport.send(message).await {
+                Ok(_) => debug!("邮件事件 {} 已发送", name),
+                Err(e) => warn!("邮件事件 {} 发送失败: {}", name, e),
+            }
+        });
+    }
+}
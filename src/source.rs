@@ -0,0 +1,36 @@
+use crate::http_client::{self, encode_component};
+use jiff::Timestamp;
+use serde::Deserialize;
+use std::time::Duration;
+
+// 远程号码源：按计划拉取号码列表并追加到号码池，用于持续补充号码的长期活动
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceConfig {
+    pub name: String,
+    // http:// 或 https://，返回格式同 /import（JSON 数组、NDJSON 或每行一个号码）
+    pub url: String,
+    // cron 表达式（分 时 日 月 周，按 timezone），如 "0 */6 * * *"
+    pub schedule: String,
+    // 拉取的号码归属的活动，号码对象中的 campaign 字段优先
+    pub campaign: Option<String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    60
+}
+
+// 拉取号码列表，since 为上次成功拉取的时间，作为查询参数传给数据源以便只返回新增号码
+pub async fn fetch(config: &SourceConfig, since: Option<Timestamp>) -> Result<String, String> {
+    let mut url = config.url.clone();
+    if let Some(since) = since {
+        let separator = if url.contains('?') { '&' } else { '?' };
+        url.push_str(&format!("{}since={}", separator, encode_component(&since.to_string())));
+    }
+    let resp = http_client::request("GET", &url, &[], None, Duration::from_secs(config.timeout_secs)).await?;
+    if !resp.is_success() {
+        return Err(format!("{} returned HTTP {}", config.url, resp.status));
+    }
+    Ok(resp.body)
+}
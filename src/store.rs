@@ -0,0 +1,405 @@
+use crate::{device::Registration, quarantine::FailureRecord};
+use jiff::Timestamp;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io::Write, time::Duration};
+
+// 状态存储配置，backend 为 file / sqlite / redis
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoreConfig {
+    pub backend: String,
+    // file、sqlite 使用的文件路径
+    #[serde(default = "default_path")]
+    pub path: String,
+    // redis 地址，如 redis://:password@127.0.0.1:6379/0，TLS 使用 rediss://
+    pub url: Option<String>,
+    // redis 中保存状态的键
+    #[serde(default = "default_key")]
+    pub key: String,
+    // 保存（只读副本为加载）间隔（秒）
+    #[serde(default = "default_persist_secs")]
+    pub persist_secs: u64,
+    // 只读副本：每隔 persist_secs 从存储后端加载主实例的进度，不保存，只提供状态、查询、导出和报告
+    #[serde(default)]
+    pub replica: bool,
+}
+
+fn default_path() -> String {
+    "state.json".to_string()
+}
+
+fn default_key() -> String {
+    "ios_sms_rpa:state".to_string()
+}
+
+fn default_persist_secs() -> u64 {
+    5
+}
+
+// 未确认批次的持久化记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeaseRecord {
+    pub batch_id: String,
+    pub numbers: Vec<String>,
+    pub device: Option<String>,
+    pub issued_at: Timestamp,
+    pub expires_at: Timestamp,
+}
+
+// 注册设备的持久化记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationRecord {
+    pub device: String,
+    #[serde(flatten)]
+    pub registration: Registration,
+}
+
+// 重启后恢复进度所需的状态
+#[derive(Debug, Clone, Deserialize)]
+pub struct Snapshot {
+    pub saved_at: Timestamp,
+    // 保存时号码池的大小，与启动时加载的号码数不同时游标可能已失效
+    pub total: usize,
+    // 保存时从号码文件加载的号码数，之后的号码为运行中导入（/import、号码源）的
+    #[serde(default)]
+    pub base: Option<usize>,
+    pub cursor: usize,
+    pub retry: Vec<String>,
+    pub deferred: Vec<String>,
+    pub leases: Vec<LeaseRecord>,
+    pub deleted: Vec<String>,
+    // 取号时因软删除被跳过的号码，恢复后需放回重发队列
+    #[serde(default)]
+    pub deleted_skipped: Vec<String>,
+    // 下发历史：累计下发的号码数、批次数及首次下发时间
+    pub issued: u64,
+    #[serde(default)]
+    pub batches: u64,
+    pub first_issued_at: Option<Timestamp>,
+    // 各租户每月的用量
+    #[serde(default)]
+    pub usage: Vec<crate::usage::Record>,
+    // 管理版本号，重启后旧的 ETag 仍然有效
+    #[serde(default)]
+    pub version: u64,
+    // 运行中导入的号码，按导入顺序；旧版本保存在进度中，现在由 ImportedRecord 单独追加保存，加载时合并
+    #[serde(default)]
+    pub imported: Vec<String>,
+    // 运行中导入的号码的附加信息
+    #[serde(default)]
+    pub meta: HashMap<String, HashMap<String, String>>,
+    // 运行中导入的不属于默认活动的号码 => 活动名
+    #[serde(default)]
+    pub campaign_of: HashMap<String, String>,
+    // 优先池及其中未下发、已提前下发的号码
+    #[serde(default)]
+    pub priority: Vec<String>,
+    #[serde(default)]
+    pub priority_pending: Vec<String>,
+    #[serde(default)]
+    pub priority_taken: Vec<String>,
+    // 各号码的失败记录及被隔离的号码
+    #[serde(default)]
+    pub failures: HashMap<String, FailureRecord>,
+    #[serde(default)]
+    pub quarantined: HashMap<String, Timestamp>,
+    #[serde(default)]
+    pub registrations: Vec<RegistrationRecord>,
+    // 已结束的活动名
+    #[serde(default)]
+    pub ended: Vec<String>,
+    // 各号码源上次成功拉取的开始时间
+    #[serde(default)]
+    pub sources: HashMap<String, Timestamp>,
+    // 各设备首次请求的时间，用于新设备预热
+    #[serde(default)]
+    pub first_seen: HashMap<String, Timestamp>,
+}
+
+// 每次保存的进度：保存时在锁内复制，释放锁后再序列化；字段须与 Snapshot 一致，saved_at 由 encode 加上，
+// 运行中导入的号码及其附加信息只增不改，不在其中，见 ImportedRecord
+#[derive(Debug, Serialize)]
+pub struct Progress {
+    pub total: usize,
+    pub base: usize,
+    pub cursor: usize,
+    pub retry: Vec<String>,
+    pub deferred: Vec<String>,
+    pub leases: Vec<LeaseRecord>,
+    pub deleted: Vec<String>,
+    pub deleted_skipped: Vec<String>,
+    pub issued: u64,
+    pub batches: u64,
+    pub first_issued_at: Option<Timestamp>,
+    pub usage: Vec<crate::usage::Record>,
+    pub version: u64,
+    pub priority: Vec<String>,
+    pub priority_pending: Vec<String>,
+    pub priority_taken: Vec<String>,
+    pub failures: HashMap<String, FailureRecord>,
+    pub quarantined: HashMap<String, Timestamp>,
+    pub registrations: Vec<RegistrationRecord>,
+    pub ended: Vec<String>,
+    pub sources: HashMap<String, Timestamp>,
+    pub first_seen: HashMap<String, Timestamp>,
+}
+
+impl Progress {
+    // 不含 saved_at 的 JSON，内容未变化时可跳过保存
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+}
+
+// 在 Progress::to_json 的结果前加上 saved_at，得到 Snapshot 的 JSON
+pub fn encode(saved_at: Timestamp, body: &str) -> String {
+    let saved_at = serde_json::to_string(&saved_at).unwrap_or_default();
+    match body.strip_prefix('{') {
+        Some("}") | None => format!("{{\"saved_at\":{}}}", saved_at),
+        Some(rest) => format!("{{\"saved_at\":{},{}", saved_at, rest),
+    }
+}
+
+// 运行中导入的一个号码，按导入顺序追加保存，不随每次进度重写
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportedRecord {
+    pub number: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub meta: HashMap<String, String>,
+}
+
+impl ImportedRecord {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("序列化导入号码记录失败")
+    }
+}
+
+// 状态存储后端
+pub trait StateStore: Send {
+    fn name(&self) -> String;
+    // 读取上次保存的进度并合并运行中导入的号码，没有保存过进度时返回 None
+    fn load(&mut self) -> Result<Option<Snapshot>, String>;
+    // 保存 encode 得到的 JSON
+    fn persist(&mut self, data: &str) -> Result<(), String>;
+    // 追加运行中导入的号码记录（ImportedRecord 的 JSON），replace 时先清空已保存的记录
+    fn persist_imported(&mut self, records: &[String], replace: bool) -> Result<(), String>;
+}
+
+pub fn open(config: &StoreConfig) -> Box<dyn StateStore> {
+    match config.backend.as_str() {
+        "file" => Box::new(FileStore { path: config.path.clone(), imported_path: format!("{}.imported", config.path) }),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Box::new(sqlite::SqliteStore::open(&config.path).unwrap_or_else(|e| panic!("{}", e))),
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => panic!("state.backend = \"sqlite\" requires building with --features sqlite"),
+        "redis" => {
+            let url = config.url.as_deref().expect("state.url is required for the redis backend");
+            Box::new(RedisStore::new(url, &config.key).unwrap_or_else(|e| panic!("Invalid state.url: {}", e)))
+        }
+        other => panic!("Unknown state backend '{}', expected file, sqlite or redis", other),
+    }
+}
+
+// 解析保存的进度，并按顺序合并运行中导入的号码记录
+fn decode<'a>(content: &str, imported: impl Iterator<Item = &'a str>) -> Result<Snapshot, String> {
+    let mut snapshot: Snapshot = serde_json::from_str(content).map_err(|e| format!("invalid state: {}", e))?;
+    for line in imported.filter(|l| !l.trim().is_empty()) {
+        // 写到一半退出时最后一行可能不完整
+        let record: ImportedRecord = match serde_json::from_str(line) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("忽略无法解析的导入号码记录: {}", e);
+                continue;
+            }
+        };
+        if let Some(campaign) = record.campaign {
+            snapshot.campaign_of.insert(record.number.clone(), campaign);
+        }
+        if !record.meta.is_empty() {
+            snapshot.meta.insert(record.number.clone(), record.meta);
+        }
+        snapshot.imported.push(record.number);
+    }
+    Ok(snapshot)
+}
+
+// 进度保存为一个 JSON 文件，先写临时文件再改名；运行中导入的号码逐行追加到 <path>.imported
+struct FileStore {
+    path: String,
+    imported_path: String,
+}
+
+impl StateStore for FileStore {
+    fn name(&self) -> String {
+        format!("file {}", self.path)
+    }
+
+    fn load(&mut self) -> Result<Option<Snapshot>, String> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {}", self.path, e)),
+        };
+        let imported = match fs::read_to_string(&self.imported_path) {
+            Ok(imported) => imported,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", self.imported_path, e)),
+        };
+        decode(&content, imported.lines()).map(Some)
+    }
+
+    fn persist(&mut self, data: &str) -> Result<(), String> {
+        let tmp = format!("{}.tmp", self.path);
+        fs::write(&tmp, data).and_then(|_| fs::rename(&tmp, &self.path)).map_err(|e| format!("{}: {}", self.path, e))
+    }
+
+    fn persist_imported(&mut self, records: &[String], replace: bool) -> Result<(), String> {
+        let mut content = String::new();
+        for record in records {
+            content.push_str(record);
+            content.push('\n');
+        }
+        let result = if replace {
+            let tmp = format!("{}.tmp", self.imported_path);
+            fs::write(&tmp, content).and_then(|_| fs::rename(&tmp, &self.imported_path))
+        } else {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.imported_path)
+                .and_then(|mut f| f.write_all(content.as_bytes()))
+        };
+        result.map_err(|e| format!("{}: {}", self.imported_path, e))
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{decode, Snapshot, StateStore};
+    use rusqlite::{Connection, OptionalExtension};
+
+    // 进度以 JSON 保存在 state 表的一行中，运行中导入的号码每个一行保存在 imported 表中
+    pub struct SqliteStore {
+        path: String,
+        conn: Connection,
+    }
+
+    impl SqliteStore {
+        pub fn open(path: &str) -> Result<SqliteStore, String> {
+            let conn = Connection::open(path).map_err(|e| format!("{}: {}", path, e))?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS state (id INTEGER PRIMARY KEY CHECK (id = 1), data TEXT NOT NULL);
+                 CREATE TABLE IF NOT EXISTS imported (seq INTEGER PRIMARY KEY, data TEXT NOT NULL);",
+            )
+            .map_err(|e| format!("{}: {}", path, e))?;
+            Ok(SqliteStore { path: path.to_string(), conn })
+        }
+    }
+
+    impl StateStore for SqliteStore {
+        fn name(&self) -> String {
+            format!("sqlite {}", self.path)
+        }
+
+        fn load(&mut self) -> Result<Option<Snapshot>, String> {
+            let data: Option<String> = self
+                .conn
+                .query_row("SELECT data FROM state WHERE id = 1", [], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())?;
+            let Some(data) = data else { return Ok(None) };
+            let mut stmt = self.conn.prepare("SELECT data FROM imported ORDER BY seq").map_err(|e| e.to_string())?;
+            let imported = stmt
+                .query_map([], |row| row.get::<_, String>(0))
+                .and_then(|rows| rows.collect::<Result<Vec<String>, _>>())
+                .map_err(|e| e.to_string())?;
+            decode(&data, imported.iter().map(String::as_str)).map(Some)
+        }
+
+        fn persist(&mut self, data: &str) -> Result<(), String> {
+            self.conn
+                .execute("INSERT INTO state (id, data) VALUES (1, ?1) ON CONFLICT(id) DO UPDATE SET data = ?1", [data])
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+
+        fn persist_imported(&mut self, records: &[String], replace: bool) -> Result<(), String> {
+            let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+            if replace {
+                tx.execute("DELETE FROM imported", []).map_err(|e| e.to_string())?;
+            }
+            {
+                let mut stmt = tx.prepare("INSERT INTO imported (data) VALUES (?1)").map_err(|e| e.to_string())?;
+                for record in records {
+                    stmt.execute([record]).map_err(|e| e.to_string())?;
+                }
+            }
+            tx.commit().map_err(|e| e.to_string())
+        }
+    }
+}
+
+// 进度以 JSON 保存在 Redis 的一个键中，运行中导入的号码追加到 <key>:imported 列表；连接断开后在下次读写时重连
+struct RedisStore {
+    client: redis::Client,
+    conn: Option<redis::Connection>,
+    key: String,
+    imported_key: String,
+}
+
+impl RedisStore {
+    // redis://[:password@]host[:port][/db]，TLS 使用 rediss://
+    fn new(url: &str, key: &str) -> Result<RedisStore, String> {
+        let client = redis::Client::open(url).map_err(|e| e.to_string())?;
+        Ok(RedisStore { client, conn: None, key: key.to_string(), imported_key: format!("{}:imported", key) })
+    }
+
+    fn query<T>(&mut self, run: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>) -> Result<T, String> {
+        let conn = match &mut self.conn {
+            Some(conn) => conn,
+            None => self.conn.insert(self.client.get_connection_with_timeout(TIMEOUT).map_err(|e| e.to_string())?),
+        };
+        conn.set_read_timeout(Some(TIMEOUT)).and_then(|_| conn.set_write_timeout(Some(TIMEOUT))).map_err(|e| e.to_string())?;
+        let result = run(conn);
+        if result.as_ref().is_err_and(|e| e.is_io_error() || e.is_connection_dropped() || e.is_timeout()) {
+            self.conn = None;
+        }
+        result.map_err(|e| e.to_string())
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+impl StateStore for RedisStore {
+    fn name(&self) -> String {
+        let info = self.client.get_connection_info();
+        format!("redis {} {}", info.addr(), self.key)
+    }
+
+    fn load(&mut self) -> Result<Option<Snapshot>, String> {
+        let mut pipe = redis::pipe();
+        pipe.get(&self.key).lrange(&self.imported_key, 0, -1);
+        let (data, imported): (Option<String>, Vec<String>) = self.query(|conn| pipe.query(conn))?;
+        data.map(|data| decode(&data, imported.iter().map(String::as_str))).transpose()
+    }
+
+    fn persist(&mut self, data: &str) -> Result<(), String> {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(&self.key).arg(data);
+        self.query(|conn| cmd.query(conn))
+    }
+
+    fn persist_imported(&mut self, records: &[String], replace: bool) -> Result<(), String> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if replace {
+            pipe.del(&self.imported_key).ignore();
+        }
+        if !records.is_empty() {
+            pipe.rpush(&self.imported_key, records).ignore();
+        }
+        self.query(|conn| pipe.query(conn))
+    }
+}
@@ -0,0 +1,47 @@
+use serde::Deserialize;
+
+// 返回给设备的提示文字（响应中的 message），默认为英文，可在 [strings] 中按部署改为中文等
+// 设备脚本应按 count、retry_after 和 code 字段（503 响应为 error 字段）判断，不要依赖这些文字
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Strings {
+    // 号码已全部下发
+    pub no_more_numbers: String,
+    // 还有号码，但因发送时段、节假日或地区限速暂不可发
+    pub no_numbers_available: String,
+    // 只取优先池且优先池已空
+    pub no_priority_numbers: String,
+    // 触发全局限速
+    pub rate_limited: String,
+    pub daily_quota_reached: String,
+    // 新设备预热期间达到当前小时的上限
+    pub warm_up_limit: String,
+    pub too_many_outstanding: String,
+    pub verification_pending: String,
+    pub draining: String,
+    pub campaign_ended: String,
+    pub maintenance: String,
+    pub overloaded: String,
+    // 测试模式下注入的熔断
+    pub circuit_open: String,
+}
+
+impl Default for Strings {
+    fn default() -> Strings {
+        Strings {
+            no_more_numbers: "No more numbers".to_string(),
+            no_numbers_available: "No numbers available now, retry later".to_string(),
+            no_priority_numbers: "No priority numbers, retry later".to_string(),
+            rate_limited: "Rate limited, retry later".to_string(),
+            daily_quota_reached: "Daily quota reached".to_string(),
+            warm_up_limit: "Warm-up limit reached, retry later".to_string(),
+            too_many_outstanding: "Too many outstanding batches, retry later".to_string(),
+            verification_pending: "Verification pending, upload proofs before fetching".to_string(),
+            draining: "Server is draining, no new batches".to_string(),
+            campaign_ended: "Campaign ended, no more batches".to_string(),
+            maintenance: "Server under maintenance, retry later".to_string(),
+            overloaded: "Server overloaded, retry later".to_string(),
+            circuit_open: "Circuit breaker open, retry later".to_string(),
+        }
+    }
+}
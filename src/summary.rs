@@ -0,0 +1,213 @@
+use crate::{features::FeaturesConfig, import};
+use jiff::Timestamp;
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// 启动摘要：加载的文件、号码去重结果、启用的功能、配额和计划，启动时打印并通过 GET /status/config 返回，
+// 便于在设备开始发送前确认活动配置无误；不包含令牌、密码等敏感配置
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub started_at: Timestamp,
+    pub listen: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_listen: Option<String>,
+    pub files: Vec<File>,
+    pub numbers: Numbers,
+    pub campaigns: Vec<Campaign>,
+    pub features: FeaturesConfig,
+    // 已启用的可选子系统，如 leases、pacing、notify
+    pub enabled: Vec<&'static str>,
+    pub limits: Limits,
+    pub schedule: Schedule,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<State>,
+    // 启动时发现的可能配置错误
+    pub warnings: Vec<String>,
+}
+
+// 启动时读取的文件，lines 为 None 表示文件不存在或无法读取
+#[derive(Debug, Clone, Serialize)]
+pub struct File {
+    pub kind: &'static str,
+    pub path: String,
+    pub lines: Option<usize>,
+}
+
+// numbers.txt 的加载和去重结果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Numbers {
+    pub loaded: usize,
+    // 格式不正确而跳过的行（空行不计）
+    pub invalid: usize,
+    // 黑名单（含回复退订）排除的号码
+    pub blacklisted: usize,
+    // 文件中重复出现的号码，只下发第一次
+    pub duplicates: usize,
+    pub blacklist: usize,
+    pub opted_out: usize,
+    // 号码池总数（含各活动 numbers_file 追加的号码）
+    pub pool: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub test_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Campaign {
+    pub name: String,
+    pub message_file: String,
+    // 消息模板的短信条数
+    pub segments: usize,
+    // 该活动的短信条数预算
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_segments: Option<usize>,
+    // 该活动每分钟的下发速率
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_per_minute: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub numbers_file: Option<String>,
+    // numbers_file 的导入结果
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imported: Option<import::Summary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_at: Option<Timestamp>,
+}
+
+// 下发数量相关的限制，None 或 0 表示不限制
+#[derive(Debug, Clone, Serialize)]
+pub struct Limits {
+    pub default_fetch_count: usize,
+    pub max_segments: Option<usize>,
+    pub daily_quota: usize,
+    pub ramp: Vec<usize>,
+    pub strict_devices: bool,
+    pub allowed_udids: usize,
+    pub shards: usize,
+    pub rate_per_minute: Option<f64>,
+    pub burst: Option<f64>,
+    pub lease_ttl_secs: Option<u64>,
+    pub max_outstanding: Option<usize>,
+    pub max_in_flight: Option<usize>,
+    pub quarantine_devices: Option<usize>,
+    pub verify_percent: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Schedule {
+    pub timezone: String,
+    pub send_window: Option<String>,
+    pub regions: usize,
+    pub holiday_mode: String,
+    pub holidays: usize,
+    pub sources: Vec<Source>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Source {
+    pub name: String,
+    pub schedule: String,
+    pub next_run: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct State {
+    pub backend: String,
+    pub replica: bool,
+    // 恢复的进度的保存时间，没有保存的进度时为 None
+    pub restored_from: Option<Timestamp>,
+    pub cursor: usize,
+}
+
+// 加载数据时的统计，启动后与配置一起生成摘要
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub files: Vec<File>,
+    pub numbers: Numbers,
+    // 活动名 => numbers_file 的导入结果
+    pub imported: HashMap<String, import::Summary>,
+}
+
+impl File {
+    pub fn read(kind: &'static str, path: &str) -> File {
+        let lines = std::fs::read_to_string(path).ok().map(|data| data.lines().count());
+        File { kind, path: path.to_string(), lines }
+    }
+}
+
+impl Summary {
+    // 启动时打印摘要，可能的配置错误以 warn 级别输出
+    pub fn log(&self) {
+        info!("========== 启动摘要 ==========");
+        info!("设备接口 {}，管理接口 {}", self.listen, self.admin_listen.as_deref().unwrap_or("同设备接口"));
+        for file in &self.files {
+            match file.lines {
+                Some(lines) => info!("文件 [{}] {} => {} 行", file.kind, file.path, lines),
+                None => info!("文件 [{}] {} => 不存在", file.kind, file.path),
+            }
+        }
+        let n = &self.numbers;
+        info!(
+            "号码 => numbers.txt {} 个，无效 {} 行，黑名单排除 {} 个，重复 {} 个，号码池共 {} 个（黑名单 {} 个，其中回复退订 {} 个）",
+            n.loaded, n.invalid, n.blacklisted, n.duplicates, n.pool, n.blacklist, n.opted_out
+        );
+        for c in &self.campaigns {
+            let imported = c.imported.as_ref().map_or(String::new(), |s| {
+                let file = c.numbers_file.as_deref().unwrap_or_default();
+                format!("，{} 追加 {} 个（重复 {}，无效 {}，黑名单 {}）", file, s.accepted, s.duplicates, s.invalid, s.blacklisted)
+            });
+            info!(
+                "活动 {} => {}（{} 条短信，预算 {:?}），每分钟限速 {:?}，语言 {:?}，备选消息 {:?}，权重 {:?}，截止 {:?}{}",
+                c.name,
+                c.message_file,
+                c.segments,
+                c.max_segments,
+                c.rate_per_minute,
+                c.variants,
+                c.messages,
+                c.weight,
+                c.end_at,
+                imported
+            );
+        }
+        info!("已启用 => {}", if self.enabled.is_empty() { "无".to_string() } else { self.enabled.join(", ") });
+        let l = &self.limits;
+        info!(
+            "限制 => 单次 {} 个，短信条数 {:?}，设备每日配额 {}，预热 {:?}，设备分组 {}，限速每分钟 {:?}，租约 {:?} 秒，未确认批次上限 {:?}",
+            l.default_fetch_count,
+            l.max_segments,
+            l.daily_quota,
+            l.ramp,
+            l.shards,
+            l.rate_per_minute,
+            l.lease_ttl_secs,
+            l.max_outstanding
+        );
+        let s = &self.schedule;
+        info!(
+            "计划 => 时区 {}，发送时段 {:?}，地区 {} 个，节假日 {} 天（{}）",
+            s.timezone, s.send_window, s.regions, s.holidays, s.holiday_mode
+        );
+        for source in &s.sources {
+            info!("号码源 {} => {}，下次拉取 {:?}", source.name, source.schedule, source.next_run);
+        }
+        if let Some(state) = &self.state {
+            info!(
+                "进度存储 {}{} => 恢复自 {:?}，游标 {}",
+                state.backend,
+                if state.replica { "（只读副本）" } else { "" },
+                state.restored_from,
+                state.cursor
+            );
+        }
+        for warning in &self.warnings {
+            warn!("配置检查: {}", warning);
+        }
+        info!("========== GET /status/config 查看完整摘要 ==========");
+    }
+}
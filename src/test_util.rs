@@ -0,0 +1,367 @@
+// 集成测试支持：按给定的号码、消息和配置启动服务，监听系统分配的本机端口（并行的测试不会争用端口），
+// 供设备端自动化针对真实协议编写集成测试，无需手工模拟 JSON
+//
+// let server = TestServer::builder().numbers(["13800000001", "13800000002"]).start().await?;
+// let batch = server.client("dev-1")?.fetch(Some(10)).await?;
+// server.advance(3600).await?;   // 服务时钟前进一小时
+//
+// 默认在测试进程中运行（server::build_app），不需要编译好的二进制，每个服务使用自己的时钟（可通过 Builder::clock 传入），
+// 日志输出到测试进程的 logger，配置中的相对路径相对于测试进程的工作目录。
+// 通过 Builder::bin 或环境变量 IOS_SMS_RPA_BIN 指定 ios_sms_rpa 二进制时，改为在临时目录中启动真实的服务进程，
+// 启动前检查其 --version 与本库的版本一致；本 crate 的集成测试中可用 env!("CARGO_BIN_EXE_ios_sms_rpa")
+use crate::{client::Client, http_client, server};
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::oneshot,
+};
+
+pub use crate::clock::Clock;
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Builder {
+    bin: Option<PathBuf>,
+    clock: Clock,
+    numbers: Vec<String>,
+    message: String,
+    default_fetch_count: usize,
+    test_number: String,
+    config: String,
+    sources: Vec<(String, String)>,
+    startup_timeout: Duration,
+}
+
+// 运行中的服务，drop 时停止服务；服务进程则结束进程并删除临时目录
+pub struct TestServer {
+    base_url: String,
+    mode: Mode,
+}
+
+enum Mode {
+    // 发送端 drop 后服务停止接受新连接
+    InProcess { _stop: oneshot::Sender<()> },
+    Process { dir: PathBuf, child: Child },
+}
+
+impl TestServer {
+    pub fn builder() -> Builder {
+        Builder {
+            bin: std::env::var_os("IOS_SMS_RPA_BIN").map(PathBuf::from),
+            clock: Clock::default(),
+            numbers: Vec::new(),
+            message: "Test message".to_string(),
+            default_fetch_count: 100,
+            test_number: "13888888888".to_string(),
+            config: String::new(),
+            sources: Vec::new(),
+            startup_timeout: Duration::from_secs(10),
+        }
+    }
+
+    // 如 http://127.0.0.1:41234
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // 服务进程的工作目录，其中有 config.toml、numbers.txt、msg.txt、server.log 和导出文件；进程内运行时为 None
+    pub fn dir(&self) -> Option<&Path> {
+        match &self.mode {
+            Mode::Process { dir, .. } => Some(dir),
+            Mode::InProcess { .. } => None,
+        }
+    }
+
+    // 服务进程的日志，断言失败时便于排查；进程内运行时日志直接输出到测试进程，这里为空
+    pub fn log(&self) -> String {
+        self.dir().and_then(|dir| fs::read_to_string(dir.join("server.log")).ok()).unwrap_or_default()
+    }
+
+    // 指定设备的协议客户端，测试中不重试
+    pub fn client(&self, device: &str) -> Result<Client, crate::client::Error> {
+        Ok(Client::new(&self.base_url, device)?.retries(0, Duration::ZERO))
+    }
+
+    pub async fn get(&self, path: &str) -> Result<http_client::Response, String> {
+        http_client::request("GET", &format!("{}{}", self.base_url, path), &[], None, Duration::from_secs(10)).await
+    }
+
+    pub async fn post(&self, path: &str, body: &str) -> Result<http_client::Response, String> {
+        let headers = [("Content-Type", "application/json")];
+        http_client::post(&format!("{}{}", self.base_url, path), &headers, body, Duration::from_secs(10)).await
+    }
+
+    // 管理修改：带上 /status 中当前版本号的 If-Match，见服务端的 version_guard
+    pub async fn admin(&self, method: &str, path: &str, body: &str) -> Result<http_client::Response, String> {
+        let version = self.status().await?["version"].to_string();
+        let headers = [("Content-Type", "application/json"), ("If-Match", version.as_str())];
+        let url = format!("{}{}", self.base_url, path);
+        http_client::request(method, &url, &headers, Some(body), Duration::from_secs(10)).await
+    }
+
+    // GET /status 的内容：total、served、remaining、outstanding、version 等
+    pub async fn status(&self) -> Result<serde_json::Value, String> {
+        let resp = self.get("/status").await?;
+        if !resp.is_success() {
+            return Err(format!("status: HTTP {}: {}", resp.status, resp.body));
+        }
+        serde_json::from_str(&resp.body).map_err(|e| format!("status: {}: {}", e, resp.body))
+    }
+
+    // 服务时钟前进 secs 秒：发送时段、配额、租约、活动截止和号码源计划都按调整后的时间判断
+    pub async fn advance(&self, secs: u64) -> Result<(), String> {
+        let resp = self.post("/test/faults", &format!("{{\"advance_secs\": {}}}", secs)).await?;
+        if !resp.is_success() {
+            return Err(format!("advance clock: HTTP {}: {}", resp.status, resp.body));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Mode::Process { dir, child } = &mut self.mode {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+impl Builder {
+    // 启动该 ios_sms_rpa 二进制的服务进程，而不是在测试进程中运行
+    pub fn bin(mut self, path: impl Into<PathBuf>) -> Builder {
+        self.bin = Some(path.into());
+        self
+    }
+
+    // 进程内运行的服务使用的时钟，测试可保留其副本直接调快；服务进程使用自己的时钟，只能通过 TestServer::advance 调整
+    pub fn clock(mut self, clock: Clock) -> Builder {
+        self.clock = clock;
+        self
+    }
+
+    // numbers.txt 的内容
+    pub fn numbers<I, S>(mut self, numbers: I) -> Builder
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.numbers = numbers.into_iter().map(Into::into).collect();
+        self
+    }
+
+    // msg.txt 的内容，默认为 "Test message"
+    pub fn message(mut self, message: &str) -> Builder {
+        self.message = message.to_string();
+        self
+    }
+
+    pub fn default_fetch_count(mut self, n: usize) -> Builder {
+        self.default_fetch_count = n;
+        self
+    }
+
+    // 每批开头的测试号，不需要时配置 "[features]\ntest_number = false"
+    pub fn test_number(mut self, number: &str) -> Builder {
+        self.test_number = number.to_string();
+        self
+    }
+
+    // 追加到 config.toml 的配置，如 "[leases]\nttl_secs = 60"；port、listen、test_mode、default_fetch_count
+    // 和 test_number 由 Builder 设置，不能再写；多次调用时顶层键须在第一次调用中给出
+    pub fn config(mut self, toml: &str) -> Builder {
+        self.config.push_str(toml);
+        self.config.push('\n');
+        self
+    }
+
+    // 每分钟从 source 拉取号码的 [[sources]]，配合 TestServer::advance 触发拉取
+    pub fn source(mut self, name: &str, source: &FakeSource) -> Builder {
+        self.sources.push((name.to_string(), source.url().to_string()));
+        self
+    }
+
+    // 等待服务进程开始响应的时间
+    pub fn startup_timeout(mut self, timeout: Duration) -> Builder {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    pub async fn start(self) -> Result<TestServer, String> {
+        // 端口为 0，服务进程绑定后在标准输出给出实际地址；进程内运行时不使用，监听地址由 start_in_process 绑定
+        let mut config = format!(
+            "port = 0\nlisten = \"127.0.0.1:0\"\ntest_mode = true\ndefault_fetch_count = {}\ntest_number = \"{}\"\n{}",
+            self.default_fetch_count, self.test_number, self.config
+        );
+        for (name, url) in &self.sources {
+            config.push_str(&format!("\n[[sources]]\nname = \"{}\"\nurl = \"{}\"\nschedule = \"* * * * *\"\n", name, url));
+        }
+        match self.bin.clone() {
+            Some(bin) => self.start_process(&bin, config).await,
+            None => self.start_in_process(config).await,
+        }
+    }
+
+    async fn start_in_process(self, config: String) -> Result<TestServer, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| e.to_string())?;
+        let base_url = format!("http://{}", listener.local_addr().map_err(|e| e.to_string())?);
+        // 配置无效时 build_app 与服务进程一样 panic，这里转为错误
+        let (numbers, message, clock) = (self.numbers, self.message, self.clock);
+        let app = tokio::spawn(async move { server::build_app(&config, numbers, message, clock).await })
+            .await
+            .map_err(|e| match e.try_into_panic() {
+                Ok(panic) => panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_else(|| "server panicked".to_string()),
+                Err(e) => e.to_string(),
+            })?;
+        let (stop, stopped) = oneshot::channel::<()>();
+        tokio::spawn(app.serve(listener, async {
+            let _ = stopped.await;
+        }));
+        Ok(TestServer { base_url, mode: Mode::InProcess { _stop: stop } })
+    }
+
+    async fn start_process(self, bin: &Path, config: String) -> Result<TestServer, String> {
+        check_version(bin)?;
+        let dir = std::env::temp_dir().join(format!(
+            "ios_sms_rpa-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let write = |name: &str, content: &str| {
+            fs::write(dir.join(name), content).map_err(|e| format!("{}: {}", name, e))
+        };
+        write("config.toml", &config)?;
+        write("numbers.txt", &self.numbers.join("\n"))?;
+        write("msg.txt", &self.message)?;
+        let log = fs::File::create(dir.join("server.log")).map_err(|e| e.to_string())?;
+
+        let mut child = Command::new(bin)
+            .current_dir(&dir)
+            .env("RUST_LOG", std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()))
+            .stdout(Stdio::piped())
+            .stderr(log)
+            .spawn()
+            .map_err(|e| format!("start {}: {}", bin.display(), e))?;
+        let mut bound = read_address(child.stdout.take().expect("stdout is piped"));
+        let mut server = TestServer { base_url: String::new(), mode: Mode::Process { dir, child } };
+
+        // 等待服务给出地址并开始响应
+        let deadline = tokio::time::Instant::now() + self.startup_timeout;
+        loop {
+            if server.base_url.is_empty() {
+                match bound.try_recv() {
+                    Ok(addr) => server.base_url = format!("http://{}", addr),
+                    Err(oneshot::error::TryRecvError::Empty) => {}
+                    Err(oneshot::error::TryRecvError::Closed) => {
+                        return Err(format!("server closed stdout without an address:\n{}", server.log()));
+                    }
+                }
+            } else if server.get("/status").await.is_ok_and(|resp| resp.is_success()) {
+                return Ok(server);
+            }
+            if let Mode::Process { child, .. } = &mut server.mode
+                && let Ok(Some(status)) = child.try_wait()
+            {
+                return Err(format!("server exited with {}:\n{}", status, server.log()));
+            }
+            if tokio::time::Instant::now() > deadline {
+                return Err(format!("server did not start within {:?}:\n{}", self.startup_timeout, server.log()));
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+// 服务进程须与本库同一版本，避免 PATH 中的旧版二进制按旧协议响应
+fn check_version(bin: &Path) -> Result<(), String> {
+    let output = Command::new(bin).arg("--version").output().map_err(|e| format!("run {} --version: {}", bin.display(), e))?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let expected = format!("ios_sms_rpa {}", env!("CARGO_PKG_VERSION"));
+    if version != expected {
+        return Err(format!("{} reports {:?}, expected {:?}", bin.display(), version, expected));
+    }
+    Ok(())
+}
+
+// 在线程中读取服务进程的标准输出，取 "listening on <地址>" 一行；之后继续读取到进程退出，避免服务端写入失败
+fn read_address(stdout: std::process::ChildStdout) -> oneshot::Receiver<String> {
+    let (tx, rx) = oneshot::channel();
+    std::thread::spawn(move || {
+        let mut tx = Some(tx);
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(addr) = line.strip_prefix("listening on ")
+                && let Some(tx) = tx.take()
+            {
+                let _ = tx.send(addr.trim().to_string());
+            }
+        }
+    });
+    rx
+}
+
+// 模拟的远程号码源：GET 任意路径返回当前设置的号码（每行一个），记录请求次数
+pub struct FakeSource {
+    url: String,
+    numbers: Arc<Mutex<Vec<String>>>,
+    requests: Arc<AtomicUsize>,
+}
+
+impl FakeSource {
+    pub async fn start() -> Result<FakeSource, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| e.to_string())?;
+        let url = format!("http://{}/numbers", listener.local_addr().map_err(|e| e.to_string())?);
+        let numbers = Arc::new(Mutex::new(Vec::new()));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (served, count) = (numbers.clone(), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                count.fetch_add(1, Ordering::Relaxed);
+                let body = served.lock().unwrap().join("\n");
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        Ok(FakeSource { url, numbers, requests })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // 之后的拉取返回这些号码
+    pub fn set_numbers<I, S>(&self, numbers: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        *self.numbers.lock().unwrap() = numbers.into_iter().map(Into::into).collect();
+    }
+
+    // 已收到的拉取请求数
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,85 @@
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+
+// 请求的追踪号：从 X-Trace-Id 或 W3C traceparent 头读取，没有时生成，
+// 附加在该请求的每条日志、批次和任务记录、Webhook 事件上，并在响应头中返回
+tokio::task_local! {
+    static TRACE_ID: String;
+}
+
+// 当前请求的追踪号
+pub fn current() -> Option<String> {
+    TRACE_ID.try_with(String::clone).ok()
+}
+
+// 在指定追踪号下运行，后台任务沿用发起请求的追踪号
+pub async fn scope<F: Future>(id: Option<String>, f: F) -> F::Output {
+    match id {
+        Some(id) => TRACE_ID.scope(id, f).await,
+        None => f.await,
+    }
+}
+
+// 为所有请求设置追踪号并回显
+pub async fn layer(request: Request, next: Next) -> Response {
+    let (id, traceparent) = from_headers(request.headers());
+    let mut response = TRACE_ID.scope(id.clone(), next.run(request)).await;
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        headers.insert("x-trace-id", value);
+    }
+    if let Some(value) = traceparent.and_then(|t| HeaderValue::from_str(&t).ok()) {
+        headers.insert("traceparent", value);
+    }
+    response
+}
+
+// 返回追踪号和响应中的 traceparent（追踪号为 32 位十六进制时才能组成 traceparent）
+fn from_headers(headers: &HeaderMap) -> (String, Option<String>) {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    let parent = header("traceparent").and_then(parse_traceparent);
+    let id = header("x-trace-id")
+        .filter(|id| !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic()))
+        .map(String::from)
+        .or_else(|| parent.as_ref().map(|(id, _)| id.clone()))
+        .unwrap_or_else(|| format!("{:016x}{:016x}", random(), random()));
+    let traceparent = is_trace_id(&id).then(|| {
+        let flags = parent.map_or_else(|| "01".to_string(), |(_, flags)| flags);
+        format!("00-{}-{:016x}-{}", id, random(), flags)
+    });
+    (id, traceparent)
+}
+
+// 解析 traceparent：00-<32 位 trace-id>-<16 位 parent-id>-<2 位 flags>
+fn parse_traceparent(value: &str) -> Option<(String, String)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let [version, trace_id, parent_id, flags] = parts[..] else { return None };
+    let hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit());
+    if !hex(version, 2) || !is_trace_id(trace_id) || !hex(parent_id, 16) || !hex(flags, 2) {
+        return None;
+    }
+    Some((trace_id.to_ascii_lowercase(), flags.to_string()))
+}
+
+fn is_trace_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit()) && id.bytes().any(|b| b != b'0')
+}
+
+fn random() -> u64 {
+    std::collections::hash_map::RandomState::new().build_hasher().finish()
+}
+
+// 日志格式，请求内的日志带上追踪号
+pub fn format(buf: &mut env_logger::fmt::Formatter, record: &log::Record) -> std::io::Result<()> {
+    let ts = buf.timestamp();
+    match current() {
+        Some(id) => writeln!(buf, "[{} {:<5} {} trace={}] {}", ts, record.level(), record.target(), id, record.args()),
+        None => writeln!(buf, "[{} {:<5} {}] {}", ts, record.level(), record.target(), record.args()),
+    }
+}
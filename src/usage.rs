@@ -0,0 +1,79 @@
+use jiff::{Timestamp, tz::TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// 多租户计费：请求头 X-Api-Key 对应的租户按 UTC 自然月累计用量，随进度保存，GET /usage 按月导出
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenantsConfig {
+    // API 密钥 => 租户名
+    pub keys: HashMap<String, String>,
+    // 可查看所有租户用量的管理密钥，不配置时 /usage 只能查看自己的用量
+    pub admin_key: Option<String>,
+}
+
+// 一个租户一个月的用量
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Counts {
+    // 下发的号码数（不含测试号）
+    pub issued: u64,
+    // 渲染的消息数（含测试号和超出条数预算被暂扣的号码）
+    pub rendered: u64,
+    // 生成的导出文件数（导出、归档和报告）
+    pub exports: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub tenant: String,
+    // 如 "2026-10"
+    pub month: String,
+    #[serde(flatten)]
+    pub counts: Counts,
+}
+
+#[derive(Debug, Default)]
+pub struct Usage {
+    // (月份, 租户) => 用量
+    counts: BTreeMap<(String, String), Counts>,
+}
+
+pub fn month(now: Timestamp) -> String {
+    now.to_zoned(TimeZone::UTC).strftime("%Y-%m").to_string()
+}
+
+impl TenantsConfig {
+    // 密钥对应的租户，没有 X-Api-Key 或密钥未配置时为 None
+    pub fn tenant(&self, key: Option<&str>) -> Option<&str> {
+        self.keys.get(key?).map(String::as_str)
+    }
+
+    pub fn is_admin(&self, key: Option<&str>) -> bool {
+        key.is_some() && key == self.admin_key.as_deref()
+    }
+}
+
+impl Usage {
+    pub fn record(&mut self, tenant: &str, now: Timestamp, update: impl FnOnce(&mut Counts)) {
+        update(self.counts.entry((month(now), tenant.to_string())).or_default());
+    }
+
+    // 指定月份各租户的用量，按租户名排序
+    pub fn month(&self, month: &str) -> Vec<Record> {
+        self.counts
+            .range((month.to_string(), String::new())..)
+            .take_while(|((m, _), _)| m == month)
+            .map(|((month, tenant), counts)| Record { tenant: tenant.clone(), month: month.clone(), counts: counts.clone() })
+            .collect()
+    }
+
+    pub fn records(&self) -> Vec<Record> {
+        self.counts
+            .iter()
+            .map(|((month, tenant), counts)| Record { tenant: tenant.clone(), month: month.clone(), counts: counts.clone() })
+            .collect()
+    }
+
+    pub fn restore(&mut self, records: Vec<Record>) {
+        self.counts = records.into_iter().map(|r| ((r.month, r.tenant), r.counts)).collect();
+    }
+}
@@ -0,0 +1,132 @@
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hasher};
+
+// 抽查：每个批次按比例抽取号码，设备需上传截图哈希或送达确认后才能取下一批
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifyConfig {
+    // 每批抽查的号码占比（百分比）
+    pub sample_percent: f64,
+}
+
+// 设备上传的抽查凭证
+#[derive(Debug, Clone, Deserialize)]
+pub struct Proof {
+    pub number: String,
+    pub screenshot_hash: Option<String>,
+    // 送达确认，false 表示其实未发出
+    pub delivered: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingCheck {
+    pub number: String,
+    pub batch_id: Option<String>,
+    pub since: Timestamp,
+}
+
+// 提交凭证的结果
+#[derive(Debug, Default, Serialize)]
+pub struct Outcome {
+    pub verified: usize,
+    // 设备确认未送达的号码，放回重发队列
+    pub undelivered: Vec<String>,
+    // 不在待抽查列表中或缺少凭证的号码
+    pub rejected: Vec<String>,
+}
+
+pub struct Verifier {
+    percent: f64,
+    // 设备 => 待上传凭证的号码
+    pending: HashMap<String, BTreeMap<String, PendingCheck>>,
+    pub verified: u64,
+    pub undelivered: u64,
+}
+
+impl Verifier {
+    pub fn new(config: &VerifyConfig) -> Verifier {
+        if !(0.0..=100.0).contains(&config.sample_percent) {
+            panic!("verification.sample_percent must be between 0 and 100");
+        }
+        Verifier { percent: config.sample_percent, pending: HashMap::new(), verified: 0, undelivered: 0 }
+    }
+
+    // 从批次中抽取号码并登记为该设备待抽查
+    pub fn sample(&mut self, device: &str, numbers: &[String], batch_id: Option<&str>, now: Timestamp) -> Vec<String> {
+        let state = std::collections::hash_map::RandomState::new();
+        let picked: Vec<String> = numbers
+            .iter()
+            .filter(|number| {
+                let mut hasher = state.build_hasher();
+                hasher.write(number.as_bytes());
+                (hasher.finish() % 10_000) as f64 / 100.0 < self.percent
+            })
+            .cloned()
+            .collect();
+        if !picked.is_empty() {
+            let pending = self.pending.entry(device.to_string()).or_default();
+            for number in &picked {
+                let check = PendingCheck { number: number.clone(), batch_id: batch_id.map(String::from), since: now };
+                pending.insert(number.clone(), check);
+            }
+        }
+        picked
+    }
+
+    // 设备尚未上传凭证的号码
+    pub fn pending(&self, device: &str) -> Vec<String> {
+        self.pending.get(device).map_or_else(Vec::new, |p| p.keys().cloned().collect())
+    }
+
+    // 所有设备的待抽查号码，按设备排序
+    pub fn list(&self) -> Vec<(&str, &PendingCheck)> {
+        let mut checks: Vec<(&str, &PendingCheck)> = self
+            .pending
+            .iter()
+            .flat_map(|(device, pending)| pending.values().map(move |check| (device.as_str(), check)))
+            .collect();
+        checks.sort_by_key(|(device, check)| (*device, check.since));
+        checks
+    }
+
+    pub fn pending_total(&self) -> usize {
+        self.pending.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn submit(&mut self, device: &str, proofs: Vec<Proof>) -> Outcome {
+        let mut outcome = Outcome::default();
+        let Some(pending) = self.pending.get_mut(device) else {
+            outcome.rejected = proofs.into_iter().map(|p| p.number).collect();
+            return outcome;
+        };
+        for proof in proofs {
+            let has_proof = proof.delivered.is_some() || proof.screenshot_hash.as_deref().is_some_and(|h| !h.is_empty());
+            if !has_proof || pending.remove(&proof.number).is_none() {
+                outcome.rejected.push(proof.number);
+            } else if proof.delivered == Some(false) {
+                self.undelivered += 1;
+                outcome.undelivered.push(proof.number);
+            } else {
+                self.verified += 1;
+                outcome.verified += 1;
+            }
+        }
+        if pending.is_empty() {
+            self.pending.remove(device);
+        }
+        outcome
+    }
+
+    // 号码已报告失败、退回或回收，不再需要凭证
+    pub fn cancel(&mut self, device: &str, numbers: &[String]) {
+        if let Some(pending) = self.pending.get_mut(device) {
+            for number in numbers {
+                pending.remove(number);
+            }
+            if pending.is_empty() {
+                self.pending.remove(device);
+            }
+        }
+    }
+}
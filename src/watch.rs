@@ -0,0 +1,25 @@
+use log::warn;
+use std::{path::Path, time::Duration, time::SystemTime};
+
+// 轮询文件修改时间，内容变化时回调新内容（无文件系统事件依赖）
+pub async fn watch_file(path: String, interval: Duration, mut on_change: impl FnMut(String)) {
+    let mut last_modified = modified(&path).await;
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let current = modified(&path).await;
+        if current.is_none() || current == last_modified {
+            continue;
+        }
+        last_modified = current;
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => on_change(content),
+            Err(e) => warn!("读取 {} 失败: {}", path, e),
+        }
+    }
+}
+
+async fn modified(path: impl AsRef<Path>) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.and_then(|m| m.modified()).ok()
+}
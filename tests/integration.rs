@@ -0,0 +1,143 @@
+// 通过 test_util 在测试进程中启动服务，按设备协议验证取号、确认、租约过期、号码源和进度计数；
+// 最后一个用例启动编译好的服务进程，确认两种方式行为一致
+use ios_sms_rpa::test_util::{Clock, FakeSource, TestServer};
+use std::{collections::HashSet, time::Duration};
+
+const NUMBERS: [&str; 5] = ["13800000001", "13800000002", "13800000003", "13800000004", "13800000005"];
+
+fn builder() -> ios_sms_rpa::test_util::Builder {
+    TestServer::builder()
+}
+
+// 不下发测试号，批次中只有号码池的号码
+fn without_test_number() -> ios_sms_rpa::test_util::Builder {
+    builder().config("[features]\ntest_number = false")
+}
+
+#[tokio::test]
+async fn fetch_prepends_test_number_and_ack_completes_batch() {
+    let server = builder()
+        .numbers(NUMBERS)
+        .default_fetch_count(2)
+        .message("hello")
+        .config("[leases]\nttl_secs = 60")
+        .start()
+        .await
+        .unwrap();
+    let client = server.client("dev-1").unwrap();
+
+    let batch = client.fetch(None).await.unwrap();
+    assert_eq!(batch.numbers(), ["13888888888", "13800000001", "13800000002"]);
+    assert_eq!(batch.message, "hello");
+    let batch_id = batch.batch_id.expect("leases are enabled");
+
+    let status = server.status().await.unwrap();
+    assert_eq!(status["outstanding"], 1);
+    let ack = client.ack(&batch_id).await.unwrap();
+    assert_eq!((ack.count, ack.failed), (2, 0));
+    let status = server.status().await.unwrap();
+    assert_eq!((status["served"].as_u64(), status["remaining"].as_u64()), (Some(2), Some(3)));
+    assert_eq!(status["outstanding"], 0);
+
+    // 已确认的批次不能再次确认
+    assert!(client.ack(&batch_id).await.is_err());
+}
+
+#[tokio::test]
+async fn expired_lease_returns_numbers_for_reissue() {
+    let clock = Clock::default();
+    let server = without_test_number()
+        .clock(clock.clone())
+        .numbers(NUMBERS)
+        .default_fetch_count(2)
+        .config("[leases]\nttl_secs = 60")
+        .start()
+        .await
+        .unwrap();
+    let first = server.client("dev-1").unwrap();
+    let second = server.client("dev-2").unwrap();
+
+    let batch = first.fetch(None).await.unwrap();
+    assert_eq!(batch.numbers(), ["13800000001", "13800000002"]);
+    clock.advance(120);
+
+    // 过期批次的号码回到重发队列，先于游标之后的号码下发
+    let reissued = second.fetch(None).await.unwrap();
+    assert_eq!(reissued.numbers(), ["13800000001", "13800000002"]);
+    assert!(first.ack(&batch.batch_id.unwrap()).await.is_err());
+    second.ack(&reissued.batch_id.unwrap()).await.unwrap();
+    let status = server.status().await.unwrap();
+    assert_eq!((status["served"].as_u64(), status["remaining"].as_u64()), (Some(2), Some(3)));
+}
+
+#[tokio::test]
+async fn cron_source_imports_new_numbers() {
+    let source = FakeSource::start().await.unwrap();
+    source.set_numbers(["13900000001", "13900000002", "13800000001"]);
+    let server = without_test_number().numbers(["13800000001"]).source("crm", &source).start().await.unwrap();
+    assert_eq!(server.status().await.unwrap()["total"], 1);
+
+    // 号码源每分钟拉取，时钟前进后触发，已有的号码去重
+    server.advance(60).await.unwrap();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    while server.status().await.unwrap()["total"] != 3 {
+        assert!(tokio::time::Instant::now() < deadline, "source was not imported:\n{}", server.log());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(source.requests() >= 1);
+
+    let batch = server.client("dev-1").unwrap().fetch(Some(10)).await.unwrap();
+    assert_eq!(batch.numbers(), ["13800000001", "13900000001", "13900000002"]);
+}
+
+#[tokio::test]
+async fn counters_stay_consistent_when_n_varies() {
+    let server = without_test_number().numbers(NUMBERS).start().await.unwrap();
+    let client = server.client("dev-1").unwrap();
+
+    // 每次请求不同的 n，号码既不重复也不遗漏
+    let mut issued = Vec::new();
+    for (n, served, remaining) in [(3, 3, 2), (1, 4, 1), (5, 5, 0)] {
+        let batch = client.fetch(Some(n)).await.unwrap();
+        issued.extend(batch.numbers().iter().map(|number| number.to_string()));
+        let status = server.status().await.unwrap();
+        assert_eq!((status["served"].as_u64(), status["remaining"].as_u64()), (Some(served), Some(remaining)));
+    }
+    assert_eq!(issued, NUMBERS);
+    assert_eq!(issued.iter().collect::<HashSet<_>>().len(), NUMBERS.len());
+
+    let empty = client.fetch(Some(5)).await.unwrap();
+    assert_eq!((empty.count, empty.code.as_deref()), (0, Some("no_more_numbers")));
+    let status = server.status().await.unwrap();
+    assert_eq!((status["served"].as_u64(), status["remaining"].as_u64()), (Some(5), Some(0)));
+
+    // 补充号码后计数随之增加，管理修改须带 If-Match
+    assert_eq!(server.post("/import", r#"["13800000006"]"#).await.unwrap().status, 428);
+    assert!(server.admin("POST", "/import", r#"["13800000006"]"#).await.unwrap().is_success());
+    let status = server.status().await.unwrap();
+    assert_eq!((status["served"].as_u64(), status["remaining"].as_u64()), (Some(5), Some(1)));
+    assert_eq!(client.fetch(Some(5)).await.unwrap().numbers(), ["13800000006"]);
+}
+
+#[tokio::test]
+async fn process_mode_serves_the_same_protocol() {
+    let server = builder()
+        .bin(env!("CARGO_BIN_EXE_ios_sms_rpa"))
+        .numbers(NUMBERS)
+        .default_fetch_count(2)
+        .message("hello")
+        .config("[leases]\nttl_secs = 60")
+        .start()
+        .await
+        .unwrap();
+    assert!(server.dir().is_some_and(|dir| dir.join("config.toml").exists()));
+    let client = server.client("dev-1").unwrap();
+
+    let batch = client.fetch(None).await.unwrap();
+    assert_eq!(batch.numbers(), ["13888888888", "13800000001", "13800000002"]);
+    assert_eq!(batch.message, "hello");
+    server.advance(120).await.unwrap();
+    assert!(client.ack(&batch.batch_id.unwrap()).await.is_err(), "{}", server.log());
+    let status = server.status().await.unwrap();
+    assert_eq!((status["served"].as_u64(), status["remaining"].as_u64()), (Some(0), Some(5)));
+}